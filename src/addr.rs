@@ -0,0 +1,149 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// Where a socket is bound.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BindAddr {
+    /// `0.0.0.0`, shown as `*` like lsof does.
+    #[default]
+    AnyV4,
+    /// `::`, which on most systems also accepts IPv4 connections.
+    AnyV6,
+    /// A concrete address. IPv4-mapped IPv6 addresses are stored as IPv4,
+    /// and `zone` is the scope of a link-local one (`fe80::1%en0`).
+    Ip { addr: IpAddr, zone: Option<String> },
+}
+
+impl BindAddr {
+    pub fn is_wildcard(&self) -> bool {
+        matches!(self, BindAddr::AnyV4 | BindAddr::AnyV6)
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        matches!(self, BindAddr::Ip { addr, .. } if addr.is_loopback())
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        match self {
+            BindAddr::AnyV4 => false,
+            BindAddr::AnyV6 => true,
+            BindAddr::Ip { addr, .. } => addr.is_ipv6(),
+        }
+    }
+
+    /// The bound IP, with wildcards as the unspecified address.
+    pub fn ip(&self) -> IpAddr {
+        match self {
+            BindAddr::AnyV4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            BindAddr::AnyV6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            BindAddr::Ip { addr, .. } => *addr,
+        }
+    }
+}
+
+impl From<IpAddr> for BindAddr {
+    fn from(addr: IpAddr) -> BindAddr {
+        match addr.to_canonical() {
+            IpAddr::V4(ip) if ip.is_unspecified() => BindAddr::AnyV4,
+            IpAddr::V6(ip) if ip.is_unspecified() => BindAddr::AnyV6,
+            addr => BindAddr::Ip { addr, zone: None },
+        }
+    }
+}
+
+impl FromStr for BindAddr {
+    type Err = String;
+
+    /// Accepts lsof's `*`, plain and bracketed IPs, and a `%zone` suffix.
+    fn from_str(s: &str) -> Result<BindAddr, String> {
+        if s == "*" {
+            return Ok(BindAddr::AnyV4);
+        }
+        let unbracketed = s.trim_start_matches('[').trim_end_matches(']');
+        let (ip, zone) = match unbracketed.split_once('%') {
+            Some((ip, zone)) => (ip, Some(zone.to_string())),
+            None => (unbracketed, None),
+        };
+        let addr: IpAddr = ip.parse().map_err(|_| format!("invalid address: {}", s))?;
+        Ok(match BindAddr::from(addr) {
+            BindAddr::Ip { addr, .. } => BindAddr::Ip { addr, zone },
+            wildcard => wildcard,
+        })
+    }
+}
+
+impl fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BindAddr::AnyV4 => f.write_str("*"),
+            BindAddr::AnyV6 => f.write_str("::"),
+            BindAddr::Ip { addr, zone: None } => write!(f, "{}", addr),
+            BindAddr::Ip {
+                addr,
+                zone: Some(zone),
+            } => write!(f, "{}%{}", addr, zone),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> BindAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for (input, shown) in [
+            ("*", "*"),
+            ("0.0.0.0", "*"),
+            ("::", "::"),
+            ("[::]", "::"),
+            ("127.0.0.1", "127.0.0.1"),
+            ("::1", "::1"),
+            ("::ffff:192.168.1.5", "192.168.1.5"),
+            ("::ffff:0.0.0.0", "*"),
+            ("fe80::1%en0", "fe80::1%en0"),
+            ("[fe80::1%lo0]", "fe80::1%lo0"),
+        ] {
+            assert_eq!(parse(input).to_string(), shown, "{}", input);
+        }
+        assert_eq!(
+            "localhost".parse::<BindAddr>(),
+            Err("invalid address: localhost".to_string())
+        );
+    }
+
+    #[test]
+    fn from_ip() {
+        assert_eq!(
+            BindAddr::from(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            BindAddr::AnyV4
+        );
+        assert_eq!(
+            BindAddr::from(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            BindAddr::AnyV6
+        );
+        assert_eq!(
+            BindAddr::from(IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped())),
+            parse("127.0.0.1")
+        );
+    }
+
+    #[test]
+    fn ordering() {
+        let mut addrs = [
+            parse("::1"),
+            parse("10.0.0.2"),
+            BindAddr::AnyV6,
+            parse("127.0.0.1"),
+            BindAddr::AnyV4,
+        ];
+        addrs.sort();
+        let shown: Vec<String> = addrs.iter().map(BindAddr::to_string).collect();
+        assert_eq!(shown, ["*", "::", "10.0.0.2", "127.0.0.1", "::1"]);
+    }
+}
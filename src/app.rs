@@ -1,20 +1,437 @@
 mod input;
+pub mod keymap;
+mod mouse;
 mod state;
 
+use std::collections::HashSet;
 use std::io::{self, Write};
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
 
-use crossterm::event::{self, Event, KeyEventKind};
+use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyEventKind};
+use crossterm::terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{cursor, execute, terminal};
 
-use crate::ports::{self, PortEntry};
-use crate::ui;
+use crate::container::{Container, ContainerAction};
+use crate::exposure::{self, Exposure, Interface};
+use crate::history::History;
+use crate::netns::NetnsScope;
+use crate::policy::Policy;
+use crate::ports::{self, PortEntry, ScanOptions, Traffic};
+use crate::probe::ProbeResult;
+use crate::procfs;
+use crate::snapshot::Snapshot;
+use crate::systemd::{Unit, UnitAction};
+use crate::{clipboard, service, ui, usage};
 
-pub const ACTIONS: [&str; 2] = ["Kill (SIGTERM)", "Force Kill (SIGKILL)"];
+// read by the panic hook, which can't reach the App
+static ALT_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// How often CPU and memory use is sampled while its columns are shown.
+const USAGE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often listeners are rescanned in the background.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Pid,
+    Process,
+    Proto,
+    Address,
+    Exposure,
+    Port,
+    Service,
+    Container,
+    Unit,
+    Netns,
+    Cpu,
+    Rss,
+    Command,
+}
+
+impl Column {
+    pub const DEFAULT: [Column; 8] = [
+        Column::Pid,
+        Column::Process,
+        Column::Proto,
+        Column::Address,
+        Column::Exposure,
+        Column::Port,
+        Column::Service,
+        Column::Command,
+    ];
+
+    pub fn sortable(self) -> bool {
+        self != Column::Proto
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sort {
+    pub column: Column,
+    pub descending: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuAction {
+    Kill,
+    ForceKill,
+    CopyPid,
+    CopyPort,
+    CopyAddress,
+    CopyUrl,
+    CopyCommand,
+    OpenBrowser,
+    ProbeTcp,
+    ProbeHttp,
+    StopContainer,
+    RestartContainer,
+    StopUnit,
+    RestartUnit,
+    UnitStatus,
+    Details,
+}
+
+impl MenuAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuAction::Kill => "Kill (SIGTERM)",
+            MenuAction::ForceKill => "Force Kill (SIGKILL)",
+            MenuAction::CopyPid => "Copy PID",
+            MenuAction::CopyPort => "Copy port",
+            MenuAction::CopyAddress => "Copy address:port",
+            MenuAction::CopyUrl => "Copy URL",
+            MenuAction::CopyCommand => "Copy command",
+            MenuAction::OpenBrowser => "Open in browser",
+            MenuAction::ProbeTcp => "Probe (TCP connect)",
+            MenuAction::ProbeHttp => "Probe (HTTP HEAD)",
+            MenuAction::StopContainer => "Stop container",
+            MenuAction::RestartContainer => "Restart container",
+            MenuAction::StopUnit => "Stop unit",
+            MenuAction::RestartUnit => "Restart unit",
+            MenuAction::UnitStatus => "Unit status",
+            MenuAction::Details => "Details",
+        }
+    }
+
+    /// Actions that act on or connect to the live listener, not possible
+    /// for a saved snapshot.
+    fn live_only(self) -> bool {
+        !matches!(
+            self,
+            MenuAction::Details
+                | MenuAction::CopyPid
+                | MenuAction::CopyPort
+                | MenuAction::CopyAddress
+                | MenuAction::CopyUrl
+                | MenuAction::CopyCommand
+        )
+    }
+
+    /// Actions that connect to the listener from portit's own namespace,
+    /// left out for listeners on a remote host or in another namespace.
+    fn local_only(self) -> bool {
+        matches!(
+            self,
+            MenuAction::OpenBrowser | MenuAction::ProbeTcp | MenuAction::ProbeHttp
+        )
+    }
+
+    /// Actions about one socket rather than the process, left out for a
+    /// process row in the grouped view.
+    fn per_listener(self) -> bool {
+        matches!(
+            self,
+            MenuAction::CopyPort
+                | MenuAction::CopyAddress
+                | MenuAction::CopyUrl
+                | MenuAction::OpenBrowser
+                | MenuAction::ProbeTcp
+                | MenuAction::ProbeHttp
+        )
+    }
+
+    /// Container and systemd listeners get container or unit actions in
+    /// place of killing the process: the process is usually just the
+    /// runtime's port proxy, or systemd would start it again.
+    fn for_entry(entry: &PortEntry) -> Vec<MenuAction> {
+        let mut items = if entry.container.is_some() {
+            vec![MenuAction::StopContainer, MenuAction::RestartContainer]
+        } else if entry.unit.is_some() {
+            vec![
+                MenuAction::StopUnit,
+                MenuAction::RestartUnit,
+                MenuAction::UnitStatus,
+            ]
+        } else {
+            vec![MenuAction::Kill, MenuAction::ForceKill]
+        };
+        items.extend([
+            MenuAction::Details,
+            MenuAction::CopyPid,
+            MenuAction::CopyPort,
+            MenuAction::CopyAddress,
+            MenuAction::CopyUrl,
+            MenuAction::CopyCommand,
+            MenuAction::OpenBrowser,
+            MenuAction::ProbeTcp,
+            MenuAction::ProbeHttp,
+        ]);
+        items
+    }
+}
+
+/// A line of the table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Row {
+    /// A listener, indexing `App::entries`.
+    Entry(usize),
+    /// A process in the grouped view, with its listeners, which follow as
+    /// `Entry` rows while it's expanded.
+    Process {
+        pid: u32,
+        entries: Vec<usize>,
+        expanded: bool,
+    },
+    /// One socket of an expanded listener that has several, indexing its
+    /// `sockets`.
+    Socket { entry: usize, socket: usize },
+}
+
+impl Row {
+    fn contains(&self, entry: usize) -> bool {
+        match self {
+            Row::Entry(i) | Row::Socket { entry: i, .. } => *i == entry,
+            Row::Process { entries, .. } => entries.contains(&entry),
+        }
+    }
+}
+
+/// Destructive action waiting for y/n.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Confirm {
+    Kill {
+        pid: u32,
+        name: String,
+        force: bool,
+    },
+    Container {
+        container: Container,
+        action: ContainerAction,
+    },
+    Unit {
+        unit: Unit,
+        action: UnitAction,
+    },
+}
+
+impl Confirm {
+    /// Confirmation for stopping what owns `entry`: its container or
+    /// systemd unit if it has one, the process otherwise.
+    fn for_entry(entry: &PortEntry, force: bool) -> Confirm {
+        if let Some(container) = &entry.container {
+            Confirm::Container {
+                container: container.clone(),
+                action: ContainerAction::Stop,
+            }
+        } else if let Some(unit) = &entry.unit {
+            Confirm::Unit {
+                unit: unit.clone(),
+                action: UnitAction::Stop,
+            }
+        } else {
+            Confirm::Kill {
+                pid: entry.pid,
+                name: entry.process_name.clone(),
+                force,
+            }
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        match self {
+            Confirm::Kill { pid, name, force } => {
+                let sig = ports::signal_name(*force);
+                format!("Kill {} (PID {}) with {}?", name, pid, sig)
+            }
+            Confirm::Container { container, action } => {
+                let verb = match action {
+                    ContainerAction::Stop => "Stop",
+                    ContainerAction::Restart => "Restart",
+                };
+                format!("{} container {}?", verb, container.label())
+            }
+            Confirm::Unit { unit, action } => {
+                format!("systemctl {} {}?", action.verb(), unit.label())
+            }
+        }
+    }
+}
+
+/// Read-only popup, e.g. probe results, closed by Esc, Enter, q or a click.
+pub struct InfoPopup {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+impl InfoPopup {
+    /// Everything known about a listener, including what the table may
+    /// have no column for.
+    /// `interfaces` are the host's own, empty when the listener lives
+    /// elsewhere.
+    fn details(entry: &PortEntry, interfaces: &[Interface]) -> InfoPopup {
+        let mut lines = vec![format!(
+            "Process:   {} (PID {})",
+            entry.process_name, entry.pid
+        )];
+        if entry.sockets.is_empty() {
+            lines.push(format!("Address:   {}", entry.socket_addr()));
+        }
+        for (i, socket) in entry.sockets.iter().enumerate() {
+            let fd = socket.fd.map_or("?".to_string(), |fd| fd.to_string());
+            let mut line = format!(
+                "{:<11}{} (fd {})",
+                if i == 0 { "Address:" } else { "" },
+                ports::socket_addr(&socket.address, entry.port),
+                fd
+            );
+            if let Some(stats) = &socket.stats {
+                line.push_str(&format!(", {}", stats.queue_label()));
+            }
+            lines.push(line);
+        }
+        if entry.backlog_full() {
+            lines.push("Queue:     full, new connections are being dropped".to_string());
+        }
+        lines.push(format!("Exposure:  {}", exposure_detail(entry, interfaces)));
+        if let Some(traffic) = traffic(entry) {
+            let count = |n: u64, what: &str| match n {
+                1 => format!("1 {}", what),
+                n => format!("{} {}s", n, what),
+            };
+            lines.push(format!(
+                "Traffic:   {}, {} sent, {} received, {}",
+                count(traffic.connections as u64, "connection"),
+                usage::format_bytes(traffic.bytes_sent),
+                usage::format_bytes(traffic.bytes_received),
+                count(traffic.retransmits, "retransmit")
+            ));
+        }
+        if let Some(service) = &entry.service {
+            lines.push(format!("Service:   {}", service));
+        }
+        lines.extend(owner_lines(entry));
+        for violation in &entry.violations {
+            lines.push(format!("Breaks:    {}", violation));
+        }
+        InfoPopup {
+            title: format!("Port {}", entry.port),
+            lines,
+        }
+    }
+
+    /// A process row of the grouped view, with all of its sockets.
+    fn process(entries: &[&PortEntry]) -> InfoPopup {
+        let entry = entries[0];
+        let sockets: Vec<String> = entries.iter().map(|e| e.socket_addr()).collect();
+        let mut lines = vec![
+            format!("Process:   {} (PID {})", entry.process_name, entry.pid),
+            format!("Ports:     {}", sockets.join(", ")),
+        ];
+        lines.extend(owner_lines(entry));
+        for e in entries {
+            for violation in &e.violations {
+                lines.push(format!("Breaks:    {}: {}", e.port, violation));
+            }
+        }
+        InfoPopup {
+            title: format!("PID {}", entry.pid),
+            lines,
+        }
+    }
+}
+
+/// Traffic summed over the listener's sockets, if sock_diag gave any.
+fn traffic(entry: &PortEntry) -> Option<Traffic> {
+    let mut sockets = entry
+        .sockets
+        .iter()
+        .filter_map(|s| s.stats?.traffic)
+        .peekable();
+    sockets.peek()?;
+    let mut total = Traffic::default();
+    for traffic in sockets {
+        total.add(&traffic);
+    }
+    Some(total)
+}
+
+/// What the process belongs to and how it was started, the same for all
+/// of its listeners.
+fn owner_lines(entry: &PortEntry) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(container) = &entry.container {
+        lines.push(format!(
+            "Container: {} [{} {}]",
+            container.label(),
+            container.runtime.cli(),
+            container.short_id()
+        ));
+    }
+    if let Some(unit) = &entry.unit {
+        lines.push(format!("Unit:      {}", unit.label()));
+    }
+    if let Some(uid) = entry.uid {
+        lines.push(match procfs::user_name(uid) {
+            Some(name) => format!("User:      {} ({})", name, uid),
+            None => format!("User:      {}", uid),
+        });
+    }
+    if let Some(usage) = &entry.usage {
+        let cpu = usage
+            .cpu
+            .map_or("-".to_string(), |cpu| format!("{:.1}%", cpu));
+        lines.push(format!(
+            "Usage:     {} CPU, {} RSS",
+            cpu,
+            usage::format_bytes(usage.rss)
+        ));
+    }
+    if !entry.command.is_empty() {
+        lines.push(format!("Command:   {}", entry.command));
+    }
+    lines
+}
+
+/// The exposure with the interfaces it reaches through, leaving out
+/// loopback for wildcard binds that reach further.
+fn exposure_detail(entry: &PortEntry, interfaces: &[Interface]) -> String {
+    let exposure = entry.exposure();
+    let beyond_loopback = entry.address.is_wildcard() && exposure != Exposure::Loopback;
+    let through: Vec<String> = exposure::reachable_through(&entry.address, interfaces)
+        .iter()
+        .filter(|i| !beyond_loopback || !i.addr.is_loopback())
+        .map(|i| format!("{} {}", i.name, i.addr))
+        .collect();
+    if through.is_empty() {
+        exposure.describe().to_string()
+    } else {
+        format!("{} via {}", exposure.describe(), through.join(", "))
+    }
+}
+
+/// Where the entries came from when browsing a saved snapshot.
+pub struct SnapshotInfo {
+    pub file: String,
+    pub host: String,
+    pub taken: String,
+}
 
 pub struct ActionMenu {
-    pub pid: u32,
-    pub name: String,
+    pub entry: PortEntry,
+    pub items: Vec<MenuAction>,
     pub selected: usize,
 }
 
@@ -25,20 +442,109 @@ pub struct App {
     pub filter: String,
     pub filter_mode: bool,
     pub filtered_entries: Vec<usize>,
+    /// Only listeners with this exposure are shown, on top of `filter`.
+    pub exposure_filter: Option<Exposure>,
+    /// What the table shows, built from `filtered_entries`; `selected` and
+    /// `scroll_offset` index into it.
+    pub rows: Vec<Row>,
+    /// Rows are grouped by process rather than one per listener.
+    pub grouped: bool,
     pub should_quit: bool,
-    pub confirm_kill: Option<(u32, String)>,
-    pub confirm_force: bool,
+    pub confirm: Option<Confirm>,
     pub action_menu: Option<ActionMenu>,
+    pub info: Option<InfoPopup>,
     pub status_msg: Option<String>,
     pub start_row: u16,
     pub height: usize,
     pub visible_rows: usize,
+    pub fullscreen: bool,
+    pub columns: Vec<Column>,
+    pub sort: Sort,
+    pub pending_count: Option<usize>,
+    pub port_prompt: Option<String>,
+    pub show_help: bool,
+    pub help_scroll: usize,
+    pub show_history: bool,
+    pub history_scroll: usize,
+    pub history: History,
+    pub snapshot: Option<SnapshotInfo>,
+    /// What the host scanned over ssh calls itself.
+    pub remote_host: Option<String>,
+    pub clipboard_fallback: bool,
+    scan: ScanOptions,
+    policy: Option<Policy>,
+    /// PIDs whose listeners are shown in the grouped view.
+    expanded: HashSet<u32>,
+    /// (PID, port) of listeners whose sockets are shown.
+    expanded_sockets: HashSet<(u32, u16)>,
+    usage: usage::Tracker,
+    /// When usage was last sampled, while the usage columns are shown.
+    usage_sampled: Option<Instant>,
+    /// While `-b` is given.
+    banners: Option<service::Banners>,
+    /// A rescan running in the background.
+    scanning: Option<Receiver<Vec<PortEntry>>>,
+    /// When the listeners were last scanned.
+    scanned: Instant,
+    /// Title for the result of the probe running in the background.
+    probing: Option<(String, Receiver<ProbeResult>)>,
+    pending_copy: Option<String>,
+    alt_screen: bool,
+    inline_area: Option<InlineArea>,
+    last_click: Option<(Instant, usize)>,
+}
+
+/// Rows reserved below the prompt, kept while the alternate screen is active.
+#[derive(Clone, Copy)]
+struct InlineArea {
+    start_row: u16,
+    height: usize,
+    visible_rows: usize,
 }
 
 impl App {
-    pub fn new() -> Self {
-        let entries = ports::list_listening_ports();
+    pub fn new(scan: ScanOptions) -> Self {
+        let mut app = Self::with_entries(ports::list_listening_ports(&scan));
+        app.scan = scan;
+        app.update_columns();
+        app
+    }
+
+    /// Browses a saved snapshot, read-only: no rescans, no signals.
+    pub fn from_snapshot(file: String, snapshot: Snapshot) -> Self {
+        let mut app = Self::with_entries(snapshot.entries);
+        app.snapshot = Some(SnapshotInfo {
+            file,
+            host: snapshot.host,
+            taken: snapshot.taken,
+        });
+        app.update_columns();
+        app.apply_sort();
+        app
+    }
+
+    /// Identifies listeners by what they speak, in the background, then
+    /// again for new ones after each refresh.
+    pub fn grab_banners(&mut self) {
+        let mut banners = service::Banners::new();
+        banners.request(&self.entries);
+        self.banners = Some(banners);
+    }
+
+    /// Flags the listeners breaking `policy`, now and after each refresh.
+    pub fn set_policy(&mut self, policy: Policy) {
+        policy.annotate(&mut self.entries);
+        self.policy = Some(policy);
+    }
+
+    /// Namespaces being scanned, when not just portit's own.
+    pub fn netns_scope(&self) -> Option<&NetnsScope> {
+        self.scan.netns.as_ref()
+    }
+
+    fn with_entries(entries: Vec<PortEntry>) -> Self {
         let filtered_entries: Vec<usize> = (0..entries.len()).collect();
+        let rows = filtered_entries.iter().map(|&i| Row::Entry(i)).collect();
         Self {
             entries,
             selected: 0,
@@ -46,26 +552,77 @@ impl App {
             filter: String::new(),
             filter_mode: false,
             filtered_entries,
+            exposure_filter: None,
+            rows,
+            grouped: false,
             should_quit: false,
-            confirm_kill: None,
-            confirm_force: false,
+            confirm: None,
             action_menu: None,
+            info: None,
             status_msg: None,
             start_row: 0,
             height: 0,
             visible_rows: 0,
+            fullscreen: false,
+            columns: Column::DEFAULT.to_vec(),
+            sort: Sort {
+                column: Column::Port,
+                descending: false,
+            },
+            alt_screen: false,
+            inline_area: None,
+            pending_count: None,
+            port_prompt: None,
+            show_help: false,
+            help_scroll: 0,
+            show_history: false,
+            history_scroll: 0,
+            history: History::default(),
+            snapshot: None,
+            remote_host: None,
+            clipboard_fallback: false,
+            scan: ScanOptions::default(),
+            policy: None,
+            expanded: HashSet::new(),
+            expanded_sockets: HashSet::new(),
+            usage: usage::Tracker::default(),
+            usage_sampled: None,
+            banners: None,
+            scanning: None,
+            scanned: Instant::now(),
+            probing: None,
+            pending_copy: None,
+            last_click: None,
         }
     }
 
     pub fn run(&mut self, w: &mut impl Write) -> io::Result<()> {
-        self.setup_display(w)?;
-
         while !self.should_quit {
+            if self.alt_screen != self.fullscreen || self.height == 0 {
+                self.sync_screen(w)?;
+                self.ensure_visible();
+            }
+            if let Some(text) = self.pending_copy.take() {
+                w.write_all(clipboard::osc52(&text).as_bytes())?;
+            }
+            if self
+                .usage_sampled
+                .is_some_and(|at| at.elapsed() >= USAGE_INTERVAL)
+            {
+                self.sample_usage();
+            }
+            self.auto_refresh();
+            self.receive_probe();
+            self.receive_banners();
             ui::render(w, self)?;
             if event::poll(Duration::from_millis(250))? {
                 match event::read()? {
                     Event::Key(key) if key.kind == KeyEventKind::Press => {
-                        self.handle_key(key.code);
+                        self.handle_key(key);
+                    }
+                    Event::Mouse(mouse) => {
+                        let (cols, _) = terminal::size()?;
+                        self.handle_mouse(mouse, cols as usize);
                     }
                     Event::Resize(_, h) => self.recalc_layout(h as usize),
                     _ => {}
@@ -75,9 +632,51 @@ impl App {
         Ok(())
     }
 
-    fn setup_display(&mut self, w: &mut impl Write) -> io::Result<()> {
+    /// Switches between the inline area and the alternate screen to match
+    /// `fullscreen`, reserving the inline rows the first time they're needed.
+    /// The mouse is only captured on the alternate screen, leaving the
+    /// terminal's own text selection to work inline.
+    fn sync_screen(&mut self, w: &mut impl Write) -> io::Result<()> {
         let (_, term_rows) = terminal::size()?;
-        self.recalc_layout(term_rows as usize);
+
+        if self.fullscreen {
+            if !self.alt_screen && self.height > 0 {
+                self.inline_area = Some(InlineArea {
+                    start_row: self.start_row,
+                    height: self.height,
+                    visible_rows: self.visible_rows,
+                });
+            }
+            execute!(
+                w,
+                EnterAlternateScreen,
+                EnableMouseCapture,
+                Clear(ClearType::All)
+            )?;
+            self.set_alt_screen(true);
+            self.start_row = 0;
+            self.recalc_layout(term_rows as usize);
+            return Ok(());
+        }
+
+        if self.alt_screen {
+            execute!(w, DisableMouseCapture, LeaveAlternateScreen)?;
+            self.set_alt_screen(false);
+        }
+
+        match self.inline_area {
+            Some(area) => {
+                self.start_row = area.start_row;
+                self.height = area.height;
+                self.visible_rows = area.visible_rows;
+            }
+            None => self.setup_inline(w, term_rows as usize)?,
+        }
+        Ok(())
+    }
+
+    fn setup_inline(&mut self, w: &mut impl Write, term_rows: usize) -> io::Result<()> {
+        self.recalc_layout(term_rows);
 
         for _ in 0..self.height {
             write!(w, "\r\n")?;
@@ -90,9 +689,39 @@ impl App {
         Ok(())
     }
 
+    fn set_alt_screen(&mut self, on: bool) {
+        self.alt_screen = on;
+        ALT_SCREEN.store(on, Ordering::Relaxed);
+    }
+
+    /// Leaves the alternate screen, or moves the cursor below the inline
+    /// area so the shell prompt is clean.
+    pub fn restore_screen(&mut self, w: &mut impl Write) {
+        if self.alt_screen {
+            let _ = execute!(w, DisableMouseCapture, LeaveAlternateScreen);
+            self.set_alt_screen(false);
+            let Some(area) = self.inline_area else {
+                return;
+            };
+            self.start_row = area.start_row;
+            self.height = area.height;
+        }
+        if self.height > 0 {
+            let _ = execute!(w, cursor::MoveTo(0, self.start_row + self.height as u16));
+            let _ = write!(w, "\r\n");
+            let _ = w.flush();
+        }
+    }
+
     fn recalc_layout(&mut self, term_rows: usize) {
+        if self.fullscreen {
+            self.height = term_rows;
+            self.visible_rows = self.height.saturating_sub(3);
+            return;
+        }
+
         let max_table = (term_rows / 2).clamp(3, 20);
-        let table_rows = self.filtered_entries.len().min(max_table).max(1);
+        let table_rows = self.rows.len().min(max_table).max(1);
         self.height = (table_rows + 3).min(term_rows);
         self.visible_rows = self.height.saturating_sub(3);
     }
@@ -100,6 +729,9 @@ impl App {
     pub fn install_panic_hook() {
         let default = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |info| {
+            if ALT_SCREEN.load(Ordering::Relaxed) {
+                let _ = execute!(io::stdout(), DisableMouseCapture, LeaveAlternateScreen);
+            }
             let _ = terminal::disable_raw_mode();
             let _ = execute!(io::stdout(), cursor::Show);
             default(info);
@@ -107,47 +739,48 @@ impl App {
     }
 }
 
-fn cycle_index(current: usize, len: usize, step: isize) -> usize {
-    if len == 0 {
-        return 0;
-    }
-    ((current as isize + step).rem_euclid(len as isize)) as usize
-}
-
 #[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ports::PortEntry;
-
-    fn test_app(n: usize) -> App {
+impl App {
+    pub(crate) fn test_app(n: usize) -> App {
         let entries: Vec<PortEntry> = (0..n)
             .map(|i| PortEntry {
                 pid: 1000 + i as u32,
                 process_name: format!("proc{}", i),
                 port: 3000 + i as u16,
-                address: "127.0.0.1".to_string(),
+                address: "127.0.0.1".parse().unwrap(),
                 command: format!("/usr/bin/proc{}", i),
+                ..Default::default()
             })
             .collect();
-        let filtered_entries: Vec<usize> = (0..n).collect();
-        App {
-            entries,
-            selected: 0,
-            scroll_offset: 0,
-            filter: String::new(),
-            filter_mode: false,
-            filtered_entries,
-            should_quit: false,
-            confirm_kill: None,
-            confirm_force: false,
-            action_menu: None,
-            status_msg: None,
-            start_row: 0,
-            height: 0,
-            visible_rows: 5,
-        }
+        let mut app = App::with_entries(entries);
+        app.visible_rows = 5;
+        app
     }
 
+    pub(crate) fn open_menu_for_test(&mut self) {
+        self.open_action_menu();
+    }
+}
+
+fn cycle_index(current: usize, len: usize, step: isize) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    ((current as isize + step).rem_euclid(len as isize)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addr::BindAddr;
+    use crate::ports::{Socket, SocketStats};
+    use crate::usage::Usage;
+    use crossterm::event::{
+        KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    };
+    use std::net::TcpListener;
+    use std::thread;
+
     // cycle_index tests
 
     #[test]
@@ -178,7 +811,7 @@ mod tests {
 
     #[test]
     fn layout_small_terminal() {
-        let mut app = test_app(10);
+        let mut app = App::test_app(10);
         app.recalc_layout(10);
         // max_table = (10/2).clamp(3,20) = 5
         // table_rows = min(10, 5).max(1) = 5
@@ -190,7 +823,7 @@ mod tests {
 
     #[test]
     fn layout_large_terminal() {
-        let mut app = test_app(10);
+        let mut app = App::test_app(10);
         app.recalc_layout(50);
         // max_table = (50/2).clamp(3,20) = 20
         // table_rows = min(10, 20).max(1) = 10
@@ -202,7 +835,7 @@ mod tests {
 
     #[test]
     fn layout_tiny_terminal() {
-        let mut app = test_app(10);
+        let mut app = App::test_app(10);
         app.recalc_layout(4);
         // max_table = (4/2).clamp(3,20) = 3
         // table_rows = min(10, 3).max(1) = 3
@@ -214,7 +847,7 @@ mod tests {
 
     #[test]
     fn layout_no_entries() {
-        let mut app = test_app(0);
+        let mut app = App::test_app(0);
         app.recalc_layout(20);
         // max_table = (20/2).clamp(3,20) = 10
         // table_rows = min(0, 10).max(1) = 1
@@ -224,18 +857,37 @@ mod tests {
         assert_eq!(app.visible_rows, 1);
     }
 
+    #[test]
+    fn layout_fullscreen_uses_all_rows() {
+        let mut app = App::test_app(100);
+        app.fullscreen = true;
+        app.recalc_layout(50);
+        assert_eq!(app.height, 50);
+        assert_eq!(app.visible_rows, 47);
+    }
+
+    #[test]
+    fn layout_fullscreen_few_entries() {
+        let mut app = App::test_app(2);
+        app.fullscreen = true;
+        app.recalc_layout(30);
+        // full screen isn't shrunk to fit the entries
+        assert_eq!(app.height, 30);
+        assert_eq!(app.visible_rows, 27);
+    }
+
     // selection & scrolling tests
 
     #[test]
     fn next_row_moves_forward() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.next_row();
         assert_eq!(app.selected, 1);
     }
 
     #[test]
     fn next_row_wraps() {
-        let mut app = test_app(3);
+        let mut app = App::test_app(3);
         app.selected = 2;
         app.next_row();
         assert_eq!(app.selected, 0);
@@ -243,14 +895,14 @@ mod tests {
 
     #[test]
     fn prev_row_wraps() {
-        let mut app = test_app(3);
+        let mut app = App::test_app(3);
         app.prev_row();
         assert_eq!(app.selected, 2);
     }
 
     #[test]
     fn scroll_follows_selection_down() {
-        let mut app = test_app(10);
+        let mut app = App::test_app(10);
         app.visible_rows = 3;
         for _ in 0..5 {
             app.next_row();
@@ -261,7 +913,7 @@ mod tests {
 
     #[test]
     fn scroll_follows_selection_up() {
-        let mut app = test_app(10);
+        let mut app = App::test_app(10);
         app.visible_rows = 3;
         app.selected = 5;
         app.scroll_offset = 5;
@@ -272,7 +924,7 @@ mod tests {
 
     #[test]
     fn empty_list_selection() {
-        let mut app = test_app(0);
+        let mut app = App::test_app(0);
         app.next_row(); // should not panic
         app.prev_row(); // should not panic
         assert_eq!(app.selected, 0);
@@ -282,7 +934,7 @@ mod tests {
 
     #[test]
     fn filter_by_name() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.filter = "proc2".to_string();
         app.apply_filter();
         assert_eq!(app.filtered_entries, vec![2]);
@@ -290,7 +942,7 @@ mod tests {
 
     #[test]
     fn filter_case_insensitive() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.filter = "PROC1".to_string();
         app.apply_filter();
         assert_eq!(app.filtered_entries, vec![1]);
@@ -298,7 +950,7 @@ mod tests {
 
     #[test]
     fn filter_by_port() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.filter = "3002".to_string();
         app.apply_filter();
         assert_eq!(app.filtered_entries, vec![2]);
@@ -306,7 +958,7 @@ mod tests {
 
     #[test]
     fn filter_empty_shows_all() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.filter = String::new();
         app.apply_filter();
         assert_eq!(app.filtered_entries, vec![0, 1, 2, 3, 4]);
@@ -314,7 +966,7 @@ mod tests {
 
     #[test]
     fn filter_no_match() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.filter = "zzzzz".to_string();
         app.apply_filter();
         assert!(app.filtered_entries.is_empty());
@@ -323,7 +975,7 @@ mod tests {
 
     #[test]
     fn filter_clamps_selection() {
-        let mut app = test_app(5);
+        let mut app = App::test_app(5);
         app.selected = 4;
         app.filter = "proc0".to_string();
         app.apply_filter();
@@ -334,15 +986,853 @@ mod tests {
 
     #[test]
     fn selected_entry_valid() {
-        let app = test_app(3);
+        let app = App::test_app(3);
         let entry = app.selected_entry().unwrap();
         assert_eq!(entry.pid, 1000);
     }
 
     #[test]
     fn selected_entry_empty() {
-        let mut app = test_app(0);
+        let mut app = App::test_app(0);
         app.filtered_entries.clear();
         assert!(app.selected_entry().is_none());
     }
+
+    // sorting tests
+
+    #[test]
+    fn sort_by_pid_descending() {
+        let mut app = App::test_app(3);
+        app.sort_by(Column::Pid);
+        assert!(!app.sort.descending);
+        app.sort_by(Column::Pid);
+        assert!(app.sort.descending);
+        let pids: Vec<u32> = app.entries.iter().map(|e| e.pid).collect();
+        assert_eq!(pids, vec![1002, 1001, 1000]);
+    }
+
+    #[test]
+    fn sort_new_column_starts_ascending() {
+        let mut app = App::test_app(3);
+        app.sort.descending = true;
+        app.sort_by(Column::Process);
+        assert_eq!(app.sort.column, Column::Process);
+        assert!(!app.sort.descending);
+    }
+
+    #[test]
+    fn cycle_sort_wraps() {
+        let mut app = App::test_app(1);
+        let sortable = app.columns.iter().filter(|c| c.sortable()).count();
+        for _ in 0..sortable {
+            app.cycle_sort_column();
+            assert_ne!(app.sort.column, Column::Proto);
+        }
+        assert_eq!(app.sort.column, Column::Port);
+    }
+
+    #[test]
+    fn sort_by_service_puts_unknown_last() {
+        let mut app = App::test_app(3);
+        app.entries[0].service = Some("ssh".to_string());
+        app.entries[2].service = Some("http".to_string());
+        app.sort_by(Column::Service);
+        let ports: Vec<u16> = app.entries.iter().map(|e| e.port).collect();
+        assert_eq!(ports, vec![3002, 3000, 3001]);
+    }
+
+    #[test]
+    fn filter_by_service() {
+        let mut app = App::test_app(3);
+        app.entries[1].service = Some("postgres".to_string());
+        app.filter = "postgres".to_string();
+        app.apply_filter();
+        assert_eq!(app.filtered_entries, vec![1]);
+    }
+
+    // mouse tests
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    fn click(app: &mut App, column: u16, row: u16) {
+        app.handle_mouse(
+            mouse(MouseEventKind::Down(MouseButton::Left), column, row),
+            80,
+        );
+    }
+
+    #[test]
+    fn click_selects_row() {
+        let mut app = App::test_app(5);
+        click(&mut app, 5, 4); // third table row
+        assert_eq!(app.selected, 2);
+        assert!(app.action_menu.is_none());
+    }
+
+    #[test]
+    fn double_click_opens_menu() {
+        let mut app = App::test_app(5);
+        click(&mut app, 5, 3);
+        click(&mut app, 5, 3);
+        assert_eq!(app.action_menu.as_ref().map(|m| m.entry.pid), Some(1001));
+    }
+
+    #[test]
+    fn click_header_sorts() {
+        let mut app = App::test_app(3);
+        click(&mut app, 2, 1); // PID header
+        assert_eq!(app.sort.column, Column::Pid);
+        click(&mut app, 2, 1);
+        assert!(app.sort.descending);
+    }
+
+    #[test]
+    fn click_menu_item_confirms() {
+        let mut app = App::test_app(3);
+        app.height = 20;
+        app.open_action_menu();
+        let rect = crate::ui::action_popup_rect(&app, 80);
+        click(&mut app, rect.x + 2, rect.y + 2); // second item
+        assert!(app.action_menu.is_none());
+        assert_eq!(
+            app.confirm,
+            Some(Confirm::Kill {
+                pid: 1000,
+                name: "proc0".to_string(),
+                force: true,
+            })
+        );
+    }
+
+    #[test]
+    fn click_outside_menu_closes_it() {
+        let mut app = App::test_app(3);
+        app.open_action_menu();
+        click(&mut app, 0, 0);
+        assert!(app.action_menu.is_none());
+        assert!(app.confirm.is_none());
+    }
+
+    #[test]
+    fn wheel_scrolls_and_drags_selection() {
+        let mut app = App::test_app(20);
+        app.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 5), 80);
+        assert_eq!(app.scroll_offset, 3);
+        assert_eq!(app.selected, 3);
+        app.handle_mouse(mouse(MouseEventKind::ScrollUp, 0, 5), 80);
+        assert_eq!(app.scroll_offset, 0);
+        assert_eq!(app.selected, 3);
+    }
+
+    #[test]
+    fn overlays_keep_the_mouse_off_the_table() {
+        let mut app = App::test_app(20);
+        app.show_help = true;
+        click(&mut app, 5, 4);
+        app.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 5), 80);
+        assert_eq!((app.selected, app.scroll_offset), (0, 0));
+        assert_eq!(app.help_scroll, 3);
+        assert!(app.action_menu.is_none());
+
+        app.show_help = false;
+        app.filter_mode = true;
+        click(&mut app, 5, 4);
+        click(&mut app, 5, 4);
+        assert_eq!(app.selected, 0);
+        assert!(app.action_menu.is_none());
+    }
+
+    #[test]
+    fn wheel_stops_at_end() {
+        let mut app = App::test_app(7);
+        for _ in 0..5 {
+            app.handle_mouse(mouse(MouseEventKind::ScrollDown, 0, 5), 80);
+        }
+        assert_eq!(app.scroll_offset, 2);
+    }
+
+    // page navigation tests
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn press_ctrl(app: &mut App, c: char) {
+        app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL));
+    }
+
+    #[test]
+    fn page_down_and_up_clamp() {
+        let mut app = App::test_app(12);
+        press(&mut app, KeyCode::PageDown);
+        assert_eq!(app.selected, 5);
+        press(&mut app, KeyCode::PageDown);
+        press(&mut app, KeyCode::PageDown);
+        assert_eq!(app.selected, 11); // no wrap
+        assert_eq!(app.scroll_offset, 7);
+        press(&mut app, KeyCode::PageUp);
+        assert_eq!(app.selected, 6);
+        assert!(app.scroll_offset <= app.selected);
+    }
+
+    #[test]
+    fn ctrl_d_and_ctrl_u_move_half_page() {
+        let mut app = App::test_app(12);
+        press_ctrl(&mut app, 'd');
+        assert_eq!(app.selected, 2);
+        press_ctrl(&mut app, 'd');
+        press_ctrl(&mut app, 'u');
+        assert_eq!(app.selected, 2);
+    }
+
+    #[test]
+    fn g_and_shift_g_jump_to_ends() {
+        let mut app = App::test_app(12);
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.selected, 11);
+        assert_eq!(app.scroll_offset, 7);
+        press(&mut app, KeyCode::Char('g'));
+        assert_eq!(app.selected, 0);
+        assert_eq!(app.scroll_offset, 0);
+    }
+
+    #[test]
+    fn count_prefix_moves_without_wrapping() {
+        let mut app = App::test_app(12);
+        press(&mut app, KeyCode::Char('5'));
+        assert_eq!(app.pending_count, Some(5));
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected, 5);
+        assert_eq!(app.pending_count, None);
+        press(&mut app, KeyCode::Char('1'));
+        press(&mut app, KeyCode::Char('0'));
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected, 11);
+        press(&mut app, KeyCode::Char('3'));
+        press(&mut app, KeyCode::Char('k'));
+        assert_eq!(app.selected, 8);
+    }
+
+    #[test]
+    fn count_prefix_with_shift_g() {
+        let mut app = App::test_app(12);
+        press(&mut app, KeyCode::Char('4'));
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.selected, 3);
+    }
+
+    #[test]
+    fn leading_zero_is_not_a_count() {
+        let mut app = App::test_app(3);
+        press(&mut app, KeyCode::Char('0'));
+        assert_eq!(app.pending_count, None);
+    }
+
+    #[test]
+    fn goto_port_selects_row() {
+        let mut app = App::test_app(12);
+        press(&mut app, KeyCode::Char(':'));
+        for c in "3009".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+        assert!(app.port_prompt.is_none());
+        assert_eq!(app.selected, 9);
+        assert!(app.scroll_offset + app.visible_rows > 9);
+    }
+
+    #[test]
+    fn goto_port_clears_hiding_filter() {
+        let mut app = App::test_app(5);
+        app.filter = "proc1".to_string();
+        app.apply_filter();
+        app.goto_port(3003);
+        assert!(app.filter.is_empty());
+        assert_eq!(app.selected, 3);
+    }
+
+    #[test]
+    fn goto_port_missing() {
+        let mut app = App::test_app(5);
+        app.selected = 2;
+        app.goto_port(9999);
+        assert_eq!(app.selected, 2);
+        assert!(app.status_msg.is_some());
+    }
+
+    #[test]
+    fn port_prompt_ignores_non_digits() {
+        let mut app = App::test_app(5);
+        press(&mut app, KeyCode::Char(':'));
+        press(&mut app, KeyCode::Char('x'));
+        press(&mut app, KeyCode::Char('8'));
+        assert_eq!(app.port_prompt.as_deref(), Some("8"));
+        press(&mut app, KeyCode::Esc);
+        assert!(app.port_prompt.is_none());
+        assert!(!app.should_quit);
+    }
+
+    // action menu tests
+
+    fn run_menu_action(app: &mut App, action: MenuAction) {
+        app.open_action_menu();
+        let menu = app.action_menu.as_mut().unwrap();
+        menu.selected = menu.items.iter().position(|a| *a == action).unwrap();
+        app.run_selected_action();
+    }
+
+    #[test]
+    fn menu_kill_asks_for_confirmation() {
+        let mut app = App::test_app(3);
+        run_menu_action(&mut app, MenuAction::Kill);
+        assert_eq!(
+            app.confirm,
+            Some(Confirm::Kill {
+                pid: 1000,
+                name: "proc0".to_string(),
+                force: false,
+            })
+        );
+    }
+
+    fn test_container() -> Container {
+        Container {
+            runtime: crate::container::Runtime::Docker,
+            id: "3f4b2c1d9e8a".repeat(5),
+            name: Some("web".to_string()),
+            image: Some("nginx".to_string()),
+        }
+    }
+
+    #[test]
+    fn container_entries_offer_container_actions() {
+        let mut app = App::test_app(2);
+        app.entries[0].container = Some(test_container());
+        let items = MenuAction::for_entry(&app.entries[0]);
+        assert_eq!(
+            items[..2],
+            [MenuAction::StopContainer, MenuAction::RestartContainer]
+        );
+        assert!(!items.contains(&MenuAction::Kill));
+        assert_eq!(MenuAction::for_entry(&app.entries[1])[0], MenuAction::Kill);
+
+        run_menu_action(&mut app, MenuAction::RestartContainer);
+        assert_eq!(
+            app.confirm,
+            Some(Confirm::Container {
+                container: test_container(),
+                action: ContainerAction::Restart,
+            })
+        );
+        assert_eq!(
+            app.confirm.unwrap().prompt(),
+            "Restart container web (nginx)?"
+        );
+    }
+
+    #[test]
+    fn kill_key_stops_container() {
+        let mut app = App::test_app(2);
+        app.entries[0].container = Some(test_container());
+        press(&mut app, KeyCode::Char('K'));
+        assert!(matches!(
+            app.confirm,
+            Some(Confirm::Container {
+                action: ContainerAction::Stop,
+                ..
+            })
+        ));
+        press(&mut app, KeyCode::Char('n'));
+        assert!(app.confirm.is_none());
+    }
+
+    #[test]
+    fn container_column_follows_entries() {
+        let mut app = App::test_app(2);
+        app.update_columns();
+        assert!(!app.columns.contains(&Column::Container));
+
+        app.entries[1].container = Some(test_container());
+        app.update_columns();
+        let at = app.columns.iter().position(|c| *c == Column::Container);
+        assert_eq!(at, Some(app.columns.len() - 2)); // just before Command
+
+        app.sort_by(Column::Container);
+        assert_eq!(app.entries[0].pid, 1001);
+        app.filter = "nginx".to_string();
+        app.apply_filter();
+        assert_eq!(app.filtered_entries.len(), 1);
+
+        app.entries[0].container = None;
+        app.update_columns();
+        assert!(!app.columns.contains(&Column::Container));
+        assert_eq!(app.sort.column, Column::Port);
+    }
+
+    #[test]
+    fn menu_copy_queues_osc52() {
+        let mut app = App::test_app(3);
+        app.selected = 1;
+        run_menu_action(&mut app, MenuAction::CopyPid);
+        assert_eq!(app.pending_copy.as_deref(), Some("1001"));
+        assert!(app.confirm.is_none());
+        assert!(app.status_msg.is_some());
+    }
+
+    #[test]
+    fn menu_copy_values() {
+        let cases = [
+            (MenuAction::CopyPort, "3000"),
+            (MenuAction::CopyAddress, "127.0.0.1:3000"),
+            (MenuAction::CopyUrl, "http://127.0.0.1:3000"),
+            (MenuAction::CopyCommand, "/usr/bin/proc0"),
+        ];
+        for (action, expected) in cases {
+            let mut app = App::test_app(1);
+            run_menu_action(&mut app, action);
+            assert_eq!(app.pending_copy.as_deref(), Some(expected), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn probe_runs_in_the_background() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut app = App::test_app(1);
+        app.entries[0].port = listener.local_addr().unwrap().port();
+        run_menu_action(&mut app, MenuAction::ProbeTcp);
+        assert!(
+            app.status_msg
+                .as_ref()
+                .unwrap()
+                .starts_with("Probing 127.0.0.1:")
+        );
+        while app.info.is_none() {
+            thread::sleep(Duration::from_millis(10));
+            app.receive_probe();
+        }
+        assert!(app.status_msg.is_none());
+        let info = app.info.as_ref().unwrap();
+        assert!(info.lines[0].starts_with("TCP connect to 127.0.0.1:"));
+    }
+
+    #[test]
+    fn info_popup_closes_on_esc() {
+        let mut app = App::test_app(3);
+        app.info = Some(InfoPopup {
+            title: "Probe".to_string(),
+            lines: vec!["TCP connect failed".to_string()],
+        });
+        press(&mut app, KeyCode::Char('j'));
+        assert!(app.info.is_some());
+        assert_eq!(app.selected, 0);
+        press(&mut app, KeyCode::Esc);
+        assert!(app.info.is_none());
+        assert!(!app.should_quit);
+    }
+
+    fn test_unit() -> Unit {
+        Unit {
+            name: "nginx.service".to_string(),
+            user: None,
+        }
+    }
+
+    #[test]
+    fn unit_entries_offer_systemctl_actions() {
+        let mut app = App::test_app(2);
+        app.entries[0].unit = Some(test_unit());
+        let items = MenuAction::for_entry(&app.entries[0]);
+        assert_eq!(
+            items[..3],
+            [
+                MenuAction::StopUnit,
+                MenuAction::RestartUnit,
+                MenuAction::UnitStatus
+            ]
+        );
+        assert!(!items.contains(&MenuAction::Kill));
+
+        press(&mut app, KeyCode::Char('K'));
+        assert_eq!(
+            app.confirm,
+            Some(Confirm::Unit {
+                unit: test_unit(),
+                action: UnitAction::Stop,
+            })
+        );
+        assert_eq!(
+            app.confirm.unwrap().prompt(),
+            "systemctl stop nginx.service?"
+        );
+    }
+
+    #[test]
+    fn details_show_unit() {
+        let mut app = App::test_app(2);
+        app.entries[0].unit = Some(test_unit());
+        press(&mut app, KeyCode::Char('i'));
+        let info = app.info.as_ref().unwrap();
+        assert_eq!(info.title, "Port 3000");
+        assert!(info.lines.contains(&"Unit:      nginx.service".to_string()));
+        press(&mut app, KeyCode::Esc);
+        assert!(app.info.is_none());
+    }
+
+    #[test]
+    fn toggle_unit_column() {
+        let mut app = App::test_app(2);
+        press(&mut app, KeyCode::Char('u'));
+        assert_eq!(app.columns[app.columns.len() - 2], Column::Unit);
+        app.sort_by(Column::Unit);
+        press(&mut app, KeyCode::Char('u'));
+        assert!(!app.columns.contains(&Column::Unit));
+        assert_eq!(app.sort.column, Column::Port);
+    }
+
+    #[test]
+    fn usage_columns_toggle_and_sort() {
+        let mut app = App::test_app(3);
+        press(&mut app, KeyCode::Char('m'));
+        let n = app.columns.len();
+        assert_eq!(
+            app.columns[n - 3..],
+            [Column::Cpu, Column::Rss, Column::Command]
+        );
+        assert!(app.usage_sampled.is_some());
+        for (e, cpu) in app.entries.iter_mut().zip([Some(3.0), None, Some(80.5)]) {
+            e.usage = Some(Usage {
+                cpu,
+                rss: 1 << 20,
+                ..Default::default()
+            });
+        }
+        app.sort_by(Column::Cpu);
+        app.sort_by(Column::Cpu);
+        let ports: Vec<u16> = app.entries.iter().map(|e| e.port).collect();
+        assert_eq!(ports, [3001, 3002, 3000]);
+        press(&mut app, KeyCode::Char('i'));
+        let info = app.info.as_ref().unwrap();
+        assert!(
+            info.lines
+                .contains(&"Usage:     - CPU, 1.0M RSS".to_string())
+        );
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('m'));
+        assert!(!app.columns.contains(&Column::Rss));
+        assert_eq!(app.sort.column, Column::Port);
+        assert!(app.usage_sampled.is_none());
+    }
+
+    #[test]
+    fn usage_resort_keeps_the_view() {
+        let mut app = App::test_app(20);
+        app.sort_by(Column::Cpu);
+        app.select_index(12);
+        app.scroll_by(3);
+        let (offset, height) = (app.scroll_offset, app.selected - app.scroll_offset);
+        for e in &mut app.entries {
+            e.usage = Some(Usage {
+                cpu: Some(f64::from(3019 - e.port)),
+                ..Default::default()
+            });
+        }
+        app.resort_in_place();
+        assert_eq!(app.selected_entry().unwrap().port, 3012);
+        assert_eq!(app.selected, 7);
+        assert_eq!(app.selected - app.scroll_offset, height);
+        assert_ne!(app.scroll_offset, offset);
+    }
+
+    #[test]
+    fn rescans_in_the_background() {
+        let mut app = App::test_app(3);
+        app.scanned = Instant::now() - REFRESH_INTERVAL;
+        app.auto_refresh();
+        assert!(app.scanning.is_some());
+        while app.scanning.is_some() {
+            thread::sleep(Duration::from_millis(10));
+            app.auto_refresh();
+        }
+        assert!(
+            app.history
+                .newest_first()
+                .any(|r| matches!(&r.event, crate::history::Event::Closed(e) if e.port == 3000))
+        );
+        assert!(!app.entries.iter().any(|e| e.pid == 1000));
+        // not again until the next interval
+        app.auto_refresh();
+        assert!(app.scanning.is_none());
+    }
+
+    #[test]
+    fn history_panel_scrolls_and_closes() {
+        let mut app = App::test_app(3);
+        app.height = 4;
+        let old = app.entries.clone();
+        app.history.observe(&old, &[]);
+        press(&mut app, KeyCode::Char('H'));
+        assert!(app.show_history);
+        // 3 events, 2 rows of body
+        for _ in 0..5 {
+            press(&mut app, KeyCode::Char('j'));
+        }
+        assert_eq!(app.history_scroll, 1);
+        press(&mut app, KeyCode::Char('q'));
+        assert!(!app.show_history);
+        assert!(!app.should_quit);
+    }
+
+    #[test]
+    fn snapshot_is_read_only() {
+        let snapshot = Snapshot {
+            host: "web-1".to_string(),
+            taken: "2026-03-02T09:00:00Z".to_string(),
+            entries: App::test_app(3).entries.into_iter().rev().collect(),
+        };
+        let mut app = App::from_snapshot("before.json".to_string(), snapshot);
+        assert_eq!(app.entries[0].port, 3000);
+        press(&mut app, KeyCode::Char('K'));
+        assert!(app.confirm.is_none());
+        assert_eq!(app.status_msg.as_deref(), Some("Snapshot is read-only"));
+        press(&mut app, KeyCode::Enter);
+        let items = &app.action_menu.as_ref().unwrap().items;
+        assert_eq!(items[0], MenuAction::Details);
+        assert!(!items.contains(&MenuAction::Kill));
+        assert!(!items.contains(&MenuAction::ProbeTcp));
+    }
+
+    #[test]
+    fn remote_host_leaves_out_local_actions() {
+        let mut app = App::test_app(2);
+        app.scan.host = Some("devbox".to_string());
+        press(&mut app, KeyCode::Char('m'));
+        assert!(!app.columns.contains(&Column::Cpu));
+        assert_eq!(
+            app.status_msg.as_deref(),
+            Some("Not available for a remote host")
+        );
+        press(&mut app, KeyCode::Enter);
+        let items = &app.action_menu.as_ref().unwrap().items;
+        assert!(items.contains(&MenuAction::Kill));
+        assert!(!items.contains(&MenuAction::OpenBrowser));
+        assert!(!items.contains(&MenuAction::ProbeHttp));
+    }
+
+    #[test]
+    fn netns_rows_leave_out_probes() {
+        let mut app = App::test_app(1);
+        app.entries[0].netns = Some("blue".to_string());
+        press(&mut app, KeyCode::Enter);
+        let items = &app.action_menu.as_ref().unwrap().items;
+        assert!(items.contains(&MenuAction::Kill));
+        assert!(!items.contains(&MenuAction::OpenBrowser));
+        assert!(!items.contains(&MenuAction::ProbeTcp));
+    }
+
+    #[test]
+    fn policy_flags_entries() {
+        let mut app = App::test_app(3);
+        app.set_policy(Policy::parse("nothing on 3001").unwrap());
+        assert!(app.entries[0].violations.is_empty());
+        assert_eq!(app.entries[1].violations, ["nothing on 3001 (line 1)"]);
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('i'));
+        let info = app.info.as_ref().unwrap();
+        assert!(
+            info.lines
+                .contains(&"Breaks:    nothing on 3001 (line 1)".to_string())
+        );
+    }
+
+    #[test]
+    fn details_show_queue_and_traffic() {
+        let mut app = App::test_app(1);
+        app.entries[0].sockets = vec![Socket {
+            address: app.entries[0].address.clone(),
+            fd: Some(5),
+            stats: Some(SocketStats {
+                queued: 129,
+                backlog: Some(128),
+                traffic: Some(Traffic {
+                    connections: 4,
+                    bytes_sent: 3 << 20,
+                    bytes_received: 512 << 10,
+                    retransmits: 2,
+                }),
+            }),
+        }];
+        assert!(app.entries[0].backlog_full());
+        press(&mut app, KeyCode::Char('i'));
+        let info = app.info.as_ref().unwrap();
+        assert_eq!(
+            info.lines[1..3],
+            [
+                "Address:   127.0.0.1:3000 (fd 5), 129/128 queued",
+                "Queue:     full, new connections are being dropped"
+            ]
+        );
+        assert!(info.lines.contains(
+            &"Traffic:   4 connections, 3.0M sent, 512K received, 2 retransmits".to_string()
+        ));
+    }
+
+    #[test]
+    fn exposure_filter_cycles() {
+        let mut app = App::test_app(3);
+        app.entries[1].address = "*".parse().unwrap();
+        app.entries[2].address = "192.168.1.5".parse().unwrap();
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.exposure_filter, Some(Exposure::Loopback));
+        assert_eq!(app.filtered_entries, [0]);
+        press(&mut app, KeyCode::Char('x'));
+        press(&mut app, KeyCode::Char('x'));
+        assert_eq!(app.filtered_entries, [2]);
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Char('x'));
+        }
+        assert_eq!(app.exposure_filter, None);
+        assert_eq!(app.filtered_entries.len(), 3);
+        // the badge text works in the plain filter too
+        app.filter = "all".to_string();
+        app.apply_filter();
+        assert_eq!(app.filtered_entries, [1]);
+    }
+
+    /// Five listeners: PID 1000 on 3000, 3002 and 3004, PID 1001 on 3001
+    /// and 3003.
+    fn grouped_app() -> App {
+        let mut app = App::test_app(5);
+        for (i, e) in app.entries.iter_mut().enumerate() {
+            e.pid = 1000 + (i as u32 % 2);
+        }
+        app.visible_rows = 10;
+        press(&mut app, KeyCode::Char('p'));
+        app
+    }
+
+    #[test]
+    fn grouped_rows_expand_and_collapse() {
+        let mut app = grouped_app();
+        assert!(app.grouped);
+        assert_eq!(
+            app.rows,
+            [
+                Row::Process {
+                    pid: 1000,
+                    entries: vec![0, 2, 4],
+                    expanded: false
+                },
+                Row::Process {
+                    pid: 1001,
+                    entries: vec![1, 3],
+                    expanded: false
+                },
+            ]
+        );
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(app.rows.len(), 4);
+        assert_eq!(app.rows[2..], [Row::Entry(1), Row::Entry(3)]);
+        assert_eq!(app.selected, 1);
+        // collapsing from a listener selects its process
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('h'));
+        assert_eq!(app.rows.len(), 2);
+        assert_eq!(app.selected, 1);
+        press(&mut app, KeyCode::Char('p'));
+        assert_eq!(app.rows.len(), 5);
+        assert_eq!(app.selected_entry().unwrap().port, 3001);
+    }
+
+    #[test]
+    fn grouped_kill_targets_the_process() {
+        let mut app = grouped_app();
+        press(&mut app, KeyCode::Char('K'));
+        assert_eq!(
+            app.confirm,
+            Some(Confirm::Kill {
+                pid: 1000,
+                name: "proc0".to_string(),
+                force: false
+            })
+        );
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Enter);
+        let items = &app.action_menu.as_ref().unwrap().items;
+        assert!(items.contains(&MenuAction::Kill));
+        assert!(!items.contains(&MenuAction::CopyPort));
+        press(&mut app, KeyCode::Esc);
+        press(&mut app, KeyCode::Char('i'));
+        let info = app.info.as_ref().unwrap();
+        assert_eq!(info.title, "PID 1000");
+        assert_eq!(
+            info.lines[1],
+            "Ports:     127.0.0.1:3000, 127.0.0.1:3002, 127.0.0.1:3004"
+        );
+    }
+
+    #[test]
+    fn grouped_goto_port_expands() {
+        let mut app = grouped_app();
+        app.goto_port(3003);
+        assert_eq!(app.rows.len(), 4);
+        assert_eq!(app.selected_entry().unwrap().port, 3003);
+        assert!(!app.process_selected());
+    }
+
+    #[test]
+    fn socket_rows_expand_and_show_fds() {
+        let mut app = App::test_app(3);
+        app.entries[1].address = BindAddr::AnyV4;
+        app.entries[1].sockets = vec![
+            Socket {
+                address: BindAddr::AnyV4,
+                fd: Some(3),
+                stats: None,
+            },
+            Socket {
+                address: BindAddr::AnyV6,
+                fd: None,
+                stats: None,
+            },
+        ];
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(app.rows.len(), 3); // a single socket has nothing to expand
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Right);
+        assert_eq!(
+            app.rows[2..4],
+            [
+                Row::Socket {
+                    entry: 1,
+                    socket: 0
+                },
+                Row::Socket {
+                    entry: 1,
+                    socket: 1
+                }
+            ]
+        );
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('i'));
+        let info = app.info.as_ref().unwrap();
+        assert_eq!(info.title, "Port 3001");
+        assert_eq!(
+            info.lines[1..3],
+            ["Address:   *:3001 (fd 3)", "           [::]:3001 (fd ?)"]
+        );
+        press(&mut app, KeyCode::Esc);
+        // collapsing from a socket selects its listener
+        press(&mut app, KeyCode::Left);
+        assert_eq!(app.rows.len(), 3);
+        assert_eq!(app.selected, 1);
+    }
 }
@@ -1,110 +1,423 @@
-use crossterm::event::KeyCode;
+use crossterm::event::{KeyCode, KeyEvent};
 
-use crate::ports;
+use crate::container::{self, ContainerAction};
+use crate::exposure::{self, Interface};
+use crate::history::Event;
+use crate::ports::PortEntry;
+use crate::snapshot::Snapshot;
+use crate::systemd::{self, UnitAction};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
 
-use super::{ACTIONS, ActionMenu, App, cycle_index};
+use crate::{clipboard, ports, probe, ui};
+
+use super::keymap::{self, Action, Context};
+use super::{ActionMenu, App, Confirm, InfoPopup, MenuAction, Row, cycle_index};
 
 impl App {
-    pub(super) fn handle_key(&mut self, code: KeyCode) {
+    pub(super) fn context(&self) -> Context {
+        if self.confirm.is_some() {
+            Context::Confirm
+        } else if self.action_menu.is_some() {
+            Context::ActionMenu
+        } else if self.info.is_some() {
+            Context::Info
+        } else if self.show_help {
+            Context::Help
+        } else if self.show_history {
+            Context::History
+        } else if self.filter_mode {
+            Context::Filter
+        } else if self.port_prompt.is_some() {
+            Context::PortPrompt
+        } else {
+            Context::Table
+        }
+    }
+
+    pub(super) fn handle_key(&mut self, key: KeyEvent) {
         self.status_msg = None;
 
-        if self.confirm_kill.is_some() {
-            self.handle_confirm(code);
-            return;
+        let context = self.context();
+        let action = keymap::lookup(context, &key);
+        match context {
+            Context::Table => self.handle_table(action, key.code),
+            Context::Filter => self.handle_filter_input(action, key.code),
+            Context::PortPrompt => self.handle_port_prompt(action, key.code),
+            Context::ActionMenu => self.handle_action_menu(action),
+            Context::Confirm => self.handle_confirm(action),
+            Context::Help => self.handle_help(action),
+            Context::History => self.handle_history(action),
+            Context::Info => {
+                if action == Some(Action::Back) {
+                    self.info = None;
+                }
+            }
         }
+    }
 
-        if self.action_menu.is_some() {
-            self.handle_action_menu(code);
+    fn handle_table(&mut self, action: Option<Action>, code: KeyCode) {
+        if let KeyCode::Char(c @ '0'..='9') = code
+            && (action == Some(Action::Count) || self.pending_count.is_some())
+        {
+            let digit = c.to_digit(10).unwrap_or(0) as usize;
+            let count = self.pending_count.unwrap_or(0);
+            self.pending_count = Some(count.saturating_mul(10).saturating_add(digit));
             return;
         }
+        let count = self.pending_count.take();
 
-        if self.filter_mode {
-            self.handle_filter_input(code);
+        let Some(action) = action else {
             return;
+        };
+        match action {
+            Action::Kill
+            | Action::ForceKill
+            | Action::Refresh
+            | Action::CycleNetns
+            | Action::SaveSnapshot
+            | Action::ToggleUsage
+                if self.snapshot.is_some() =>
+            {
+                self.status_msg = Some("Snapshot is read-only".to_string());
+            }
+            Action::CycleNetns | Action::ToggleUsage if self.scan.host.is_some() => {
+                self.status_msg = Some("Not available for a remote host".to_string());
+            }
+            Action::Quit => self.should_quit = true,
+            Action::Down => match count {
+                Some(n) => self.move_clamped(n as isize),
+                None => self.next_row(),
+            },
+            Action::Up => match count {
+                Some(n) => self.move_clamped(-(n as isize)),
+                None => self.prev_row(),
+            },
+            Action::PageDown => self.move_clamped(self.page() as isize),
+            Action::PageUp => self.move_clamped(-(self.page() as isize)),
+            Action::HalfPageDown => self.move_clamped(self.half_page() as isize),
+            Action::HalfPageUp => self.move_clamped(-(self.half_page() as isize)),
+            Action::Top => self.select_index(0),
+            Action::Bottom => match count {
+                // 1-based like vim's `5G`
+                Some(n) => self.select_index(n.saturating_sub(1)),
+                None => self.select_index(usize::MAX),
+            },
+            Action::GotoPort => self.port_prompt = Some(String::new()),
+            Action::Select => self.open_action_menu(),
+            Action::Details => self.show_details(),
+            Action::ToggleUnitColumn => self.toggle_unit_column(),
+            Action::ToggleUsage => self.toggle_usage_columns(),
+            Action::CycleNetns => self.cycle_netns(),
+            Action::CycleExposure => self.cycle_exposure_filter(),
+            Action::ToggleGroups => self.toggle_grouped(),
+            Action::Expand => self.expand_selected(Some(true)),
+            Action::Collapse => self.expand_selected(Some(false)),
+            Action::ToggleExpand => self.expand_selected(None),
+            Action::Filter => self.filter_mode = true,
+            Action::Kill => self.request_kill(false),
+            Action::ForceKill => self.request_kill(true),
+            Action::Refresh => self.refresh(),
+            Action::SaveSnapshot => self.save_snapshot(),
+            Action::Fullscreen => self.fullscreen = !self.fullscreen,
+            Action::CycleSort => self.cycle_sort_column(),
+            Action::ReverseSort => self.sort_by(self.sort.column),
+            Action::Help => {
+                self.show_help = true;
+                self.help_scroll = 0;
+            }
+            Action::History => {
+                self.show_history = true;
+                self.history_scroll = 0;
+            }
+            _ => {}
         }
+    }
 
-        match code {
-            KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
-            KeyCode::Char('j') | KeyCode::Down => self.next_row(),
-            KeyCode::Char('k') | KeyCode::Up => self.prev_row(),
-            KeyCode::Enter => self.open_action_menu(),
-            KeyCode::Char('/') => self.filter_mode = true,
-            KeyCode::Char('K') => self.request_kill(false),
-            KeyCode::Char('F') => self.request_kill(true),
-            KeyCode::Char('r') => self.refresh(),
+    pub(super) fn handle_history(&mut self, action: Option<Action>) {
+        match action {
+            Some(Action::Down) => {
+                let max = self
+                    .history
+                    .len()
+                    .saturating_sub(self.height.saturating_sub(2));
+                self.history_scroll = (self.history_scroll + 1).min(max);
+            }
+            Some(Action::Up) => self.history_scroll = self.history_scroll.saturating_sub(1),
+            Some(Action::Back) => self.show_history = false,
             _ => {}
         }
     }
 
-    fn handle_action_menu(&mut self, code: KeyCode) {
-        match code {
-            KeyCode::Char('j') | KeyCode::Down => self.move_action_selection(1),
-            KeyCode::Char('k') | KeyCode::Up => self.move_action_selection(-1),
-            KeyCode::Enter => {
-                if let Some(menu) = self.action_menu.take() {
-                    self.confirm_kill = Some((menu.pid, menu.name));
-                    self.confirm_force = menu.selected == 1;
+    pub(super) fn handle_help(&mut self, action: Option<Action>) {
+        match action {
+            Some(Action::Down) => {
+                let max = ui::help_lines()
+                    .len()
+                    .saturating_sub(self.height.saturating_sub(2));
+                self.help_scroll = (self.help_scroll + 1).min(max);
+            }
+            Some(Action::Up) => self.help_scroll = self.help_scroll.saturating_sub(1),
+            Some(Action::Back) => self.show_help = false,
+            _ => {}
+        }
+    }
+
+    fn handle_action_menu(&mut self, action: Option<Action>) {
+        match action {
+            Some(Action::Down) => self.move_action_selection(1),
+            Some(Action::Up) => self.move_action_selection(-1),
+            Some(Action::Select) => self.run_selected_action(),
+            Some(Action::Back) => self.action_menu = None,
+            _ => {}
+        }
+    }
+
+    pub(super) fn run_selected_action(&mut self) {
+        let Some(menu) = self.action_menu.take() else {
+            return;
+        };
+        let Some(&action) = menu.items.get(menu.selected) else {
+            return;
+        };
+        let entry = menu.entry;
+
+        match action {
+            MenuAction::Kill | MenuAction::ForceKill => {
+                self.confirm = Some(Confirm::Kill {
+                    pid: entry.pid,
+                    name: entry.process_name,
+                    force: action == MenuAction::ForceKill,
+                });
+            }
+            MenuAction::StopContainer | MenuAction::RestartContainer => {
+                if let Some(container) = entry.container {
+                    self.confirm = Some(Confirm::Container {
+                        container,
+                        action: if action == MenuAction::StopContainer {
+                            ContainerAction::Stop
+                        } else {
+                            ContainerAction::Restart
+                        },
+                    });
                 }
             }
-            KeyCode::Esc | KeyCode::Char('q') => {
-                self.action_menu = None;
+            MenuAction::StopUnit | MenuAction::RestartUnit => {
+                if let Some(unit) = entry.unit {
+                    self.confirm = Some(Confirm::Unit {
+                        unit,
+                        action: if action == MenuAction::StopUnit {
+                            UnitAction::Stop
+                        } else {
+                            UnitAction::Restart
+                        },
+                    });
+                }
             }
-            _ => {}
+            MenuAction::UnitStatus => {
+                if let Some(unit) = entry.unit {
+                    self.info = Some(InfoPopup {
+                        title: format!("systemctl status {}", unit.name),
+                        lines: systemd::status(&unit),
+                    });
+                }
+            }
+            MenuAction::Details => {
+                self.info = Some(InfoPopup::details(&entry, &self.interfaces(&entry)))
+            }
+            MenuAction::CopyPid => self.copy("PID", entry.pid.to_string()),
+            MenuAction::CopyPort => self.copy("port", entry.port.to_string()),
+            MenuAction::CopyAddress => self.copy("address", entry.socket_addr()),
+            MenuAction::CopyUrl => self.copy("URL", entry.url()),
+            MenuAction::CopyCommand => self.copy("command", entry.command),
+            MenuAction::OpenBrowser => {
+                let url = entry.url();
+                self.status_msg = Some(if probe::open_url(&url) {
+                    format!("Opened {}", url)
+                } else {
+                    format!("Failed to open {}", url)
+                });
+            }
+            MenuAction::ProbeTcp | MenuAction::ProbeHttp => {
+                let http = action == MenuAction::ProbeHttp;
+                let (host, port) = (entry.connect_host(), entry.port);
+                let (tx, rx) = mpsc::channel();
+                thread::spawn(move || {
+                    let _ = tx.send(probe::probe(&host, port, http));
+                });
+                self.status_msg = Some(format!("Probing {}\u{2026}", entry.socket_addr()));
+                self.probing = Some((format!("Probe {}", entry.socket_addr()), rx));
+            }
+        }
+    }
+
+    /// Queues `text` for the terminal clipboard, written by `run` via OSC 52.
+    fn copy(&mut self, what: &str, text: String) {
+        if self.clipboard_fallback && !clipboard::copy_with_tool(&text) {
+            self.status_msg = Some("No clipboard tool found, sent OSC 52 only".to_string());
+        } else {
+            self.status_msg = Some(format!("Copied {}: {}", what, text));
+        }
+        self.pending_copy = Some(text);
+    }
+
+    /// Writes all listeners, ignoring the filter, to a timestamped file in
+    /// the working directory.
+    fn save_snapshot(&mut self) {
+        let mut snapshot = Snapshot::capture(self.entries.clone());
+        if let Some(host) = &self.remote_host {
+            snapshot.host = host.clone();
         }
+        let file = format!("portit-{}.json", snapshot.taken.replace([':', '-'], ""));
+        self.status_msg = Some(match snapshot.save(Path::new(&file)) {
+            Ok(()) => format!("Saved snapshot to {}", file),
+            Err(e) => format!("Failed to save snapshot: {}", e),
+        });
     }
 
     fn move_action_selection(&mut self, step: isize) {
         if let Some(menu) = &mut self.action_menu {
-            menu.selected = cycle_index(menu.selected, ACTIONS.len(), step);
+            menu.selected = cycle_index(menu.selected, menu.items.len(), step);
+        }
+    }
+
+    fn show_details(&mut self) {
+        if let Some(Row::Process { entries, .. }) = self.rows.get(self.selected) {
+            let entries: Vec<&PortEntry> = entries.iter().map(|&i| &self.entries[i]).collect();
+            self.info = Some(InfoPopup::process(&entries));
+        } else if let Some(entry) = self.selected_entry() {
+            self.info = Some(InfoPopup::details(entry, &self.interfaces(entry)));
+        }
+    }
+
+    /// The host's interfaces, unless `entry` is from a snapshot, another
+    /// namespace or another host.
+    fn interfaces(&self, entry: &PortEntry) -> Vec<Interface> {
+        if self.snapshot.is_some() || !entry.in_own_netns() || self.scan.host.is_some() {
+            return Vec::new();
         }
+        exposure::interfaces()
     }
 
-    fn open_action_menu(&mut self) {
+    pub(super) fn open_action_menu(&mut self) {
         if let Some(entry) = self.selected_entry() {
+            let mut items = MenuAction::for_entry(entry);
+            if self.snapshot.is_some() {
+                items.retain(|action| !action.live_only());
+            }
+            if self.scan.host.is_some() || !entry.in_own_netns() {
+                items.retain(|action| !action.local_only());
+            }
+            if self.process_selected() {
+                items.retain(|action| !action.per_listener());
+            }
             self.action_menu = Some(ActionMenu {
-                pid: entry.pid,
-                name: entry.process_name.clone(),
+                items,
+                entry: entry.clone(),
                 selected: 0,
             });
         }
     }
 
-    fn handle_confirm(&mut self, code: KeyCode) {
-        match code {
-            KeyCode::Char('y') | KeyCode::Char('Y') => {
-                if let Some((pid, name)) = self.confirm_kill.take() {
-                    if ports::kill_process(pid, self.confirm_force) {
-                        self.status_msg = Some(format!("Killed {} (PID {})", name, pid));
-                    } else {
-                        self.status_msg =
-                            Some(format!("Failed to kill {} (PID {})", name, pid));
-                    }
-                    self.confirm_force = false;
-                    self.refresh();
+    fn handle_confirm(&mut self, action: Option<Action>) {
+        let Some(confirm) = self.confirm.take() else {
+            return;
+        };
+        if action != Some(Action::Confirm) {
+            return;
+        }
+        let (msg, event) = match confirm {
+            Confirm::Kill { pid, name, force } => {
+                let result = ports::kill_process(pid, force, self.scan.host.as_deref());
+                let msg = match &result {
+                    Ok(()) => format!("Killed {} (PID {})", name, pid),
+                    Err(e) => format!("Failed to kill {} (PID {}): {}", name, pid, e),
+                };
+                let signal = ports::signal_name(force);
+                (
+                    msg,
+                    Event::Signal {
+                        pid,
+                        name,
+                        signal,
+                        result,
+                    },
+                )
+            }
+            Confirm::Unit { unit, action } => {
+                let result = systemd::run_action(&unit, action);
+                let msg = match &result {
+                    Ok(()) => format!("systemctl {} {}", action.verb(), unit.name),
+                    Err(e) => format!("Failed to {} {}: {}", action.verb(), unit.name, e),
+                };
+                let command = systemd::command_line(&unit, action);
+                (msg, Event::Command { command, result })
+            }
+            Confirm::Container { container, action } => {
+                let label = container.label();
+                let ok = container::run_action(&container, action);
+                let msg = if ok {
+                    format!("{} container {}", action.past(), label)
+                } else {
+                    format!("Failed to {} container {}", action.verb(), label)
+                };
+                let command = format!("{} {} {}", container.runtime.cli(), action.verb(), label);
+                let result = if ok {
+                    Ok(())
+                } else {
+                    Err("exited with an error".to_string())
+                };
+                (msg, Event::Command { command, result })
+            }
+        };
+        self.status_msg = Some(msg);
+        self.history.record(event);
+        self.refresh();
+    }
+
+    fn handle_port_prompt(&mut self, action: Option<Action>, code: KeyCode) {
+        match (action, code) {
+            (Some(Action::Select), _) => {
+                let input = self.port_prompt.take().unwrap_or_default();
+                match input.parse::<u16>() {
+                    Ok(port) => self.goto_port(port),
+                    Err(_) if input.is_empty() => {}
+                    Err(_) => self.status_msg = Some(format!("Invalid port: {}", input)),
                 }
             }
-            _ => {
-                self.confirm_kill = None;
-                self.confirm_force = false;
+            (Some(Action::Back), _) => self.port_prompt = None,
+            (Some(Action::Backspace), _) => {
+                if let Some(input) = &mut self.port_prompt {
+                    input.pop();
+                }
             }
+            (None, KeyCode::Char(c)) if c.is_ascii_digit() => {
+                if let Some(input) = &mut self.port_prompt
+                    && input.len() < 5
+                {
+                    input.push(c);
+                }
+            }
+            _ => {}
         }
     }
 
-    fn handle_filter_input(&mut self, code: KeyCode) {
-        match code {
-            KeyCode::Enter => {
+    fn handle_filter_input(&mut self, action: Option<Action>, code: KeyCode) {
+        match (action, code) {
+            (Some(Action::Select), _) => {
                 self.filter_mode = false;
                 self.apply_filter();
             }
-            KeyCode::Esc => {
+            (Some(Action::Back), _) => {
                 self.filter_mode = false;
                 self.filter.clear();
                 self.apply_filter();
             }
-            KeyCode::Backspace => {
+            (Some(Action::Backspace), _) => {
                 self.filter.pop();
             }
-            KeyCode::Char(c) => {
+            (None, KeyCode::Char(c)) => {
                 self.filter.push(c);
             }
             _ => {}
@@ -0,0 +1,410 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Which part of the UI currently receives key presses.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Context {
+    Table,
+    Filter,
+    PortPrompt,
+    ActionMenu,
+    Confirm,
+    Info,
+    Help,
+    History,
+}
+
+impl Context {
+    pub const ALL: [Context; 8] = [
+        Context::Table,
+        Context::Filter,
+        Context::PortPrompt,
+        Context::ActionMenu,
+        Context::Confirm,
+        Context::Info,
+        Context::Help,
+        Context::History,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Context::Table => "Table",
+            Context::Filter => "Filter",
+            Context::PortPrompt => "Go to port",
+            Context::ActionMenu => "Action menu",
+            Context::Confirm => "Confirm",
+            Context::Info => "Info popup",
+            Context::Help => "Help",
+            Context::History => "History",
+        }
+    }
+
+    /// What happens to keys that have no binding in this context.
+    pub fn fallback(self) -> Option<&'static str> {
+        match self {
+            Context::Filter => Some("Other keys type into the filter"),
+            Context::PortPrompt => Some("Digits type the port number"),
+            Context::Confirm => Some("Any other key cancels"),
+            _ => None,
+        }
+    }
+
+    /// What the mouse does here. It's only captured in fullscreen, so
+    /// inline the terminal keeps selecting text.
+    pub fn mouse(self) -> Option<&'static str> {
+        match self {
+            Context::Table => Some(
+                "Mouse, fullscreen only: click selects or sorts, double-click opens actions, wheel scrolls",
+            ),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    Top,
+    Bottom,
+    Count,
+    GotoPort,
+    Filter,
+    Select,
+    Kill,
+    ForceKill,
+    Refresh,
+    CycleSort,
+    ReverseSort,
+    Details,
+    ToggleUnitColumn,
+    ToggleUsage,
+    CycleNetns,
+    CycleExposure,
+    ToggleGroups,
+    Expand,
+    Collapse,
+    ToggleExpand,
+    History,
+    SaveSnapshot,
+    Fullscreen,
+    Help,
+    Backspace,
+    Confirm,
+    Back,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Key {
+    Code(KeyCode),
+    Ctrl(char),
+}
+
+impl Key {
+    fn matches(self, key: &KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match self {
+            Key::Code(code) => !ctrl && key.code == code,
+            Key::Ctrl(c) => ctrl && key.code == KeyCode::Char(c),
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            Key::Ctrl(c) => format!("Ctrl-{}", c),
+            Key::Code(KeyCode::Char(' ')) => "Space".to_string(),
+            Key::Code(KeyCode::Char(c)) => c.to_string(),
+            Key::Code(KeyCode::Up) => "\u{2191}".to_string(),
+            Key::Code(KeyCode::Down) => "\u{2193}".to_string(),
+            Key::Code(KeyCode::PageUp) => "PgUp".to_string(),
+            Key::Code(KeyCode::PageDown) => "PgDn".to_string(),
+            Key::Code(KeyCode::Backspace) => "Bksp".to_string(),
+            Key::Code(code) => format!("{:?}", code),
+        }
+    }
+}
+
+pub struct Binding {
+    pub context: Context,
+    pub keys: &'static [Key],
+    pub action: Action,
+    pub desc: &'static str,
+}
+
+impl Binding {
+    /// Keys joined with " / ", collapsing runs of consecutive characters
+    /// such as the count digits into "1-9".
+    pub fn keys_label(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        let mut i = 0;
+        while i < self.keys.len() {
+            let mut j = i;
+            while let (Key::Code(KeyCode::Char(a)), Some(Key::Code(KeyCode::Char(b)))) =
+                (self.keys[j], self.keys.get(j + 1))
+                && *b as u32 == a as u32 + 1
+            {
+                j += 1;
+            }
+            if j - i >= 2 {
+                parts.push(format!("{}-{}", self.keys[i].label(), self.keys[j].label()));
+            } else {
+                parts.extend(self.keys[i..=j].iter().map(|k| k.label()));
+            }
+            i = j + 1;
+        }
+        parts.join(" / ")
+    }
+}
+
+const fn ch(c: char) -> Key {
+    Key::Code(KeyCode::Char(c))
+}
+
+const fn code(c: KeyCode) -> Key {
+    Key::Code(c)
+}
+
+macro_rules! bind {
+    ($context:ident, [$($key:expr),+ $(,)?], $action:ident, $desc:expr) => {
+        Binding {
+            context: Context::$context,
+            keys: &[$($key),+],
+            action: Action::$action,
+            desc: $desc,
+        }
+    };
+}
+
+pub const BINDINGS: &[Binding] = &[
+    bind!(Table, [ch('j'), code(KeyCode::Down)], Down, "Move down"),
+    bind!(Table, [ch('k'), code(KeyCode::Up)], Up, "Move up"),
+    bind!(Table, [code(KeyCode::PageDown)], PageDown, "Page down"),
+    bind!(Table, [code(KeyCode::PageUp)], PageUp, "Page up"),
+    bind!(Table, [Key::Ctrl('d')], HalfPageDown, "Half page down"),
+    bind!(Table, [Key::Ctrl('u')], HalfPageUp, "Half page up"),
+    bind!(Table, [ch('g'), code(KeyCode::Home)], Top, "Jump to top"),
+    bind!(
+        Table,
+        [ch('G'), code(KeyCode::End)],
+        Bottom,
+        "Jump to bottom (5G: row 5)"
+    ),
+    bind!(
+        Table,
+        [
+            ch('1'),
+            ch('2'),
+            ch('3'),
+            ch('4'),
+            ch('5'),
+            ch('6'),
+            ch('7'),
+            ch('8'),
+            ch('9'),
+        ],
+        Count,
+        "Count prefix for j / k / G"
+    ),
+    bind!(Table, [ch(':')], GotoPort, "Go to port"),
+    bind!(Table, [ch('/')], Filter, "Filter by name, port or command"),
+    bind!(Table, [code(KeyCode::Enter)], Select, "Open action menu"),
+    bind!(Table, [ch('i')], Details, "Show listener details"),
+    bind!(Table, [ch('K')], Kill, "Kill selected process (SIGTERM)"),
+    bind!(
+        Table,
+        [ch('F')],
+        ForceKill,
+        "Force kill selected process (SIGKILL)"
+    ),
+    bind!(Table, [ch('r')], Refresh, "Refresh port list"),
+    bind!(Table, [ch('s')], CycleSort, "Cycle sort column"),
+    bind!(Table, [ch('S')], ReverseSort, "Reverse sort order"),
+    bind!(
+        Table,
+        [ch('u')],
+        ToggleUnitColumn,
+        "Toggle systemd unit column"
+    ),
+    bind!(
+        Table,
+        [ch('m')],
+        ToggleUsage,
+        "Toggle CPU and memory columns"
+    ),
+    bind!(Table, [ch('n')], CycleNetns, "Cycle network namespace"),
+    bind!(
+        Table,
+        [ch('x')],
+        CycleExposure,
+        "Cycle exposure filter (local, lan, all, ...)"
+    ),
+    bind!(Table, [ch('p')], ToggleGroups, "Group rows by process"),
+    bind!(
+        Table,
+        [ch('l'), code(KeyCode::Right)],
+        Expand,
+        "Expand process or sockets"
+    ),
+    bind!(
+        Table,
+        [ch('h'), code(KeyCode::Left)],
+        Collapse,
+        "Collapse process or sockets"
+    ),
+    bind!(Table, [ch(' ')], ToggleExpand, "Expand or collapse"),
+    bind!(Table, [ch('H')], History, "Show event history"),
+    bind!(Table, [ch('w')], SaveSnapshot, "Save a snapshot as JSON"),
+    bind!(Table, [ch('z')], Fullscreen, "Toggle full-screen mode"),
+    bind!(Table, [ch('?')], Help, "Show this help"),
+    bind!(Table, [ch('q'), code(KeyCode::Esc)], Quit, "Quit"),
+    bind!(Filter, [code(KeyCode::Enter)], Select, "Apply filter"),
+    bind!(Filter, [code(KeyCode::Esc)], Back, "Clear filter and close"),
+    bind!(
+        Filter,
+        [code(KeyCode::Backspace)],
+        Backspace,
+        "Delete last character"
+    ),
+    bind!(PortPrompt, [code(KeyCode::Enter)], Select, "Jump to port"),
+    bind!(PortPrompt, [code(KeyCode::Esc)], Back, "Cancel"),
+    bind!(
+        PortPrompt,
+        [code(KeyCode::Backspace)],
+        Backspace,
+        "Delete last digit"
+    ),
+    bind!(
+        ActionMenu,
+        [ch('j'), code(KeyCode::Down)],
+        Down,
+        "Next action"
+    ),
+    bind!(
+        ActionMenu,
+        [ch('k'), code(KeyCode::Up)],
+        Up,
+        "Previous action"
+    ),
+    bind!(ActionMenu, [code(KeyCode::Enter)], Select, "Run action"),
+    bind!(
+        ActionMenu,
+        [code(KeyCode::Esc), ch('q')],
+        Back,
+        "Close menu"
+    ),
+    bind!(Confirm, [ch('y'), ch('Y')], Confirm, "Confirm the action"),
+    bind!(Confirm, [ch('n'), code(KeyCode::Esc)], Back, "Cancel"),
+    bind!(
+        Info,
+        [code(KeyCode::Esc), code(KeyCode::Enter), ch('q')],
+        Back,
+        "Close"
+    ),
+    bind!(Help, [ch('j'), code(KeyCode::Down)], Down, "Scroll down"),
+    bind!(Help, [ch('k'), code(KeyCode::Up)], Up, "Scroll up"),
+    bind!(
+        Help,
+        [ch('?'), ch('q'), code(KeyCode::Esc)],
+        Back,
+        "Close help"
+    ),
+    bind!(History, [ch('j'), code(KeyCode::Down)], Down, "Scroll down"),
+    bind!(History, [ch('k'), code(KeyCode::Up)], Up, "Scroll up"),
+    bind!(
+        History,
+        [ch('H'), ch('q'), code(KeyCode::Esc)],
+        Back,
+        "Close history"
+    ),
+];
+
+pub fn lookup(context: Context, key: &KeyEvent) -> Option<Action> {
+    BINDINGS
+        .iter()
+        .find(|b| b.context == context && b.keys.iter().any(|k| k.matches(key)))
+        .map(|b| b.action)
+}
+
+pub fn bindings(context: Context) -> impl Iterator<Item = &'static Binding> {
+    BINDINGS.iter().filter(move |b| b.context == context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn lookup_by_context() {
+        let enter = key(KeyCode::Enter);
+        assert_eq!(lookup(Context::Table, &enter), Some(Action::Select));
+        assert_eq!(lookup(Context::Filter, &enter), Some(Action::Select));
+        let q = key(KeyCode::Char('q'));
+        assert_eq!(lookup(Context::Table, &q), Some(Action::Quit));
+        assert_eq!(lookup(Context::ActionMenu, &q), Some(Action::Back));
+        assert_eq!(lookup(Context::Filter, &q), None);
+    }
+
+    #[test]
+    fn lookup_ctrl_keys() {
+        let ctrl_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert_eq!(lookup(Context::Table, &ctrl_d), Some(Action::HalfPageDown));
+        // plain `d` isn't bound, and Ctrl doesn't leak into plain bindings
+        assert_eq!(lookup(Context::Table, &key(KeyCode::Char('d'))), None);
+        let ctrl_j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::CONTROL);
+        assert_eq!(lookup(Context::Table, &ctrl_j), None);
+    }
+
+    #[test]
+    fn lookup_ignores_shift() {
+        let shift_k = KeyEvent::new(KeyCode::Char('K'), KeyModifiers::SHIFT);
+        assert_eq!(lookup(Context::Table, &shift_k), Some(Action::Kill));
+    }
+
+    #[test]
+    fn keys_label_collapses_ranges() {
+        let count = bindings(Context::Table)
+            .find(|b| b.action == Action::Count)
+            .unwrap();
+        assert_eq!(count.keys_label(), "1-9");
+        let down = bindings(Context::Table)
+            .find(|b| b.action == Action::Down)
+            .unwrap();
+        assert_eq!(down.keys_label(), "j / \u{2193}");
+        let confirm = bindings(Context::Confirm)
+            .find(|b| b.action == Action::Confirm)
+            .unwrap();
+        assert_eq!(confirm.keys_label(), "y / Y");
+    }
+
+    #[test]
+    fn no_duplicate_keys_per_context() {
+        for context in Context::ALL {
+            let keys: Vec<Key> = bindings(context)
+                .flat_map(|b| b.keys.iter().copied())
+                .collect();
+            for (i, k) in keys.iter().enumerate() {
+                assert!(
+                    !keys[i + 1..].contains(k),
+                    "{:?} bound twice in {:?}",
+                    k,
+                    context
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_context_has_bindings() {
+        for context in Context::ALL {
+            assert!(bindings(context).next().is_some(), "{:?}", context);
+        }
+    }
+}
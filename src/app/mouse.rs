@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+
+use crate::ui;
+
+use super::App;
+use super::keymap::{Action, Context};
+
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+const WHEEL_STEP: isize = 3;
+
+impl App {
+    /// Mouse events go where keys would, so nothing reaches the table
+    /// while something is drawn over it: the help and history overlays
+    /// scroll with the wheel, and a click only acts on an open popup.
+    pub(super) fn handle_mouse(&mut self, mouse: MouseEvent, cols: usize) {
+        let wheel = match mouse.kind {
+            MouseEventKind::ScrollDown => Some(Action::Down),
+            MouseEventKind::ScrollUp => Some(Action::Up),
+            _ => None,
+        };
+        match (self.context(), mouse.kind) {
+            (Context::Table, MouseEventKind::Down(MouseButton::Left)) => {
+                self.status_msg = None;
+                self.click_table(mouse.column, mouse.row, cols);
+            }
+            (Context::Table, MouseEventKind::ScrollDown) => self.scroll_by(WHEEL_STEP),
+            (Context::Table, MouseEventKind::ScrollUp) => self.scroll_by(-WHEEL_STEP),
+            (Context::ActionMenu, MouseEventKind::Down(MouseButton::Left)) => {
+                self.status_msg = None;
+                self.click_action_menu(mouse.column, mouse.row, cols);
+            }
+            (Context::Info, MouseEventKind::Down(_)) => self.info = None,
+            (Context::Help, _) if wheel.is_some() => {
+                for _ in 0..WHEEL_STEP {
+                    self.handle_help(wheel);
+                }
+            }
+            (Context::History, _) if wheel.is_some() => {
+                for _ in 0..WHEEL_STEP {
+                    self.handle_history(wheel);
+                }
+            }
+            // the confirm dialog and the prompts wait for the keyboard
+            _ => {}
+        }
+    }
+
+    fn click_action_menu(&mut self, x: u16, y: u16, cols: usize) {
+        if let Some(item) = ui::action_item_at(self, cols, x, y) {
+            if let Some(menu) = &mut self.action_menu {
+                menu.selected = item;
+            }
+            self.run_selected_action();
+        } else if !ui::action_popup_rect(self, cols).contains(x, y) {
+            self.action_menu = None;
+        }
+    }
+
+    fn click_table(&mut self, x: u16, y: u16, cols: usize) {
+        if let Some(column) = ui::column_at(self, cols, x, y) {
+            self.sort_by(column);
+            return;
+        }
+
+        let Some(row) = ui::row_at(self, y) else {
+            return;
+        };
+        self.select_index(row);
+
+        let now = Instant::now();
+        let double = self
+            .last_click
+            .is_some_and(|(at, last)| last == row && now.duration_since(at) <= DOUBLE_CLICK);
+        if double {
+            self.last_click = None;
+            self.open_action_menu();
+        } else {
+            self.last_click = Some((now, row));
+        }
+    }
+}
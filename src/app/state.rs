@@ -1,6 +1,15 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::Instant;
+
+use crate::exposure::Exposure;
+use crate::netns::{self, NetnsScope};
 use crate::ports::{self, PortEntry};
 
-use super::{App, cycle_index};
+use super::{App, Column, Confirm, InfoPopup, REFRESH_INTERVAL, Row, cycle_index};
 
 impl App {
     pub(super) fn next_row(&mut self) {
@@ -12,14 +21,71 @@ impl App {
     }
 
     fn move_selection(&mut self, step: isize) {
-        if self.filtered_entries.is_empty() {
+        if self.rows.is_empty() {
             return;
         }
-        self.selected = cycle_index(self.selected, self.filtered_entries.len(), step);
+        self.selected = cycle_index(self.selected, self.rows.len(), step);
         self.ensure_visible();
     }
 
-    fn ensure_visible(&mut self) {
+    /// Moves the selection by `step` rows, stopping at either end.
+    pub(super) fn move_clamped(&mut self, step: isize) {
+        self.select_index(self.selected.saturating_add_signed(step));
+    }
+
+    pub(super) fn page(&self) -> usize {
+        self.visible_rows.max(1)
+    }
+
+    pub(super) fn half_page(&self) -> usize {
+        (self.visible_rows / 2).max(1)
+    }
+
+    /// Selects the row listening on `port`, clearing the filter if it hides
+    /// it and expanding its process in the grouped view.
+    pub(super) fn goto_port(&mut self, port: u16) {
+        let Some(target) = self.entries.iter().position(|e| e.port == port) else {
+            self.status_msg = Some(format!("Nothing listening on port {}", port));
+            return;
+        };
+        if !self.filtered_entries.contains(&target) {
+            self.filter.clear();
+            self.exposure_filter = None;
+            self.apply_filter();
+        }
+        if self.grouped {
+            self.expanded.insert(self.entries[target].pid);
+            self.build_rows();
+        }
+        if let Some(row) = self.rows.iter().position(|r| *r == Row::Entry(target)) {
+            self.select_index(row);
+        }
+    }
+
+    pub(super) fn select_index(&mut self, index: usize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        self.selected = index.min(self.rows.len() - 1);
+        self.ensure_visible();
+    }
+
+    /// Scrolls the viewport without wrapping, dragging the selection along
+    /// when it would otherwise fall off screen.
+    pub(super) fn scroll_by(&mut self, step: isize) {
+        let max_offset = self.rows.len().saturating_sub(self.visible_rows);
+        self.scroll_offset = self
+            .scroll_offset
+            .saturating_add_signed(step)
+            .min(max_offset);
+        if self.visible_rows == 0 || self.rows.is_empty() {
+            return;
+        }
+        let last_visible = self.scroll_offset + self.visible_rows - 1;
+        self.selected = self.selected.clamp(self.scroll_offset, last_visible);
+    }
+
+    pub(super) fn ensure_visible(&mut self) {
         if self.visible_rows == 0 {
             return;
         }
@@ -32,43 +98,529 @@ impl App {
 
     pub(super) fn request_kill(&mut self, force: bool) {
         if let Some(entry) = self.selected_entry() {
-            self.confirm_kill = Some((entry.pid, entry.process_name.clone()));
-            self.confirm_force = force;
+            self.confirm = Some(Confirm::for_entry(entry, force));
         }
     }
 
+    /// Rescans, recording listeners that came or went since the last scan.
     pub(super) fn refresh(&mut self) {
-        self.entries = ports::list_listening_ports();
+        let entries = ports::list_listening_ports(&self.scan);
+        self.history.observe(&self.entries, &entries);
+        self.set_entries(entries);
+    }
+
+    /// Rescans in the background every `REFRESH_INTERVAL`, so the history
+    /// also catches listeners that come and go between manual refreshes.
+    pub(super) fn auto_refresh(&mut self) {
+        if self.snapshot.is_some() {
+            return;
+        }
+        match self.scanning.take() {
+            Some(rx) => match rx.try_recv() {
+                Ok(entries) => {
+                    self.history.observe(&self.entries, &entries);
+                    self.set_entries(entries);
+                }
+                Err(TryRecvError::Empty) => self.scanning = Some(rx),
+                Err(TryRecvError::Disconnected) => self.scanned = Instant::now(),
+            },
+            None if self.scanned.elapsed() >= REFRESH_INTERVAL => {
+                let scan = self.scan.clone();
+                let (tx, rx) = mpsc::channel();
+                thread::spawn(move || {
+                    let _ = tx.send(ports::list_listening_ports(&scan));
+                });
+                self.scanning = Some(rx);
+            }
+            None => {}
+        }
+    }
+
+    /// Shows a new scan, keeping the selection on its listener. A scan
+    /// still running in the background would be older, so it's dropped.
+    fn set_entries(&mut self, entries: Vec<PortEntry>) {
+        self.scanning = None;
+        self.scanned = Instant::now();
+        let anchor = self.view_anchor();
+        self.entries = entries;
+        if let Some(policy) = &self.policy {
+            policy.annotate(&mut self.entries);
+        }
+        if self.usage_sampled.is_some() {
+            self.annotate_usage();
+        }
+        if let Some(banners) = &mut self.banners {
+            banners.request(&self.entries);
+            banners.annotate(&mut self.entries);
+        }
+        self.update_columns();
+        self.apply_sort();
+        self.restore_view(anchor);
+    }
+
+    /// Shows the probe's result once it's done.
+    pub(super) fn receive_probe(&mut self) {
+        let Some((title, rx)) = self.probing.take() else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok(result) => {
+                self.status_msg = None;
+                self.info = Some(InfoPopup {
+                    title,
+                    lines: result.lines(),
+                });
+            }
+            Err(TryRecvError::Empty) => self.probing = Some((title, rx)),
+            Err(TryRecvError::Disconnected) => {}
+        }
+    }
+
+    /// Fills in the banners grabbed since the last tick.
+    pub(super) fn receive_banners(&mut self) {
+        let Some(banners) = &mut self.banners else {
+            return;
+        };
+        if banners.receive() {
+            banners.annotate(&mut self.entries);
+            self.resort_in_place();
+        }
+    }
+
+    /// Shows the container and namespace columns only while some listener
+    /// has one.
+    pub(super) fn update_columns(&mut self) {
+        let containers = self.entries.iter().any(|e| e.container.is_some());
+        self.show_column(Column::Container, containers);
+        let namespaces = self.entries.iter().any(|e| e.netns.is_some());
+        self.show_column(Column::Netns, namespaces);
+    }
+
+    /// Steps through portit's own namespace, all of them, then each one.
+    pub(super) fn cycle_netns(&mut self) {
+        let mut scopes = vec![None, Some(NetnsScope::All)];
+        scopes.extend(
+            netns::list()
+                .into_iter()
+                .map(|ns| Some(NetnsScope::Named(ns.name))),
+        );
+        let current = scopes
+            .iter()
+            .position(|s| *s == self.scan.netns)
+            .unwrap_or(0);
+        self.scan.netns = scopes[cycle_index(current, scopes.len(), 1)].clone();
+        // a different scope isn't listeners opening or closing
+        self.set_entries(ports::list_listening_ports(&self.scan));
+        self.status_msg = Some(format!(
+            "Network namespace: {}",
+            self.scan.netns.as_ref().map_or(netns::HOST, |s| s.label())
+        ));
+    }
+
+    /// Steps the exposure filter through each exposure, then back to none.
+    pub(super) fn cycle_exposure_filter(&mut self) {
+        self.exposure_filter = match self.exposure_filter {
+            None => Some(Exposure::ALL[0]),
+            Some(current) => Exposure::ALL
+                .iter()
+                .position(|e| *e == current)
+                .and_then(|i| Exposure::ALL.get(i + 1))
+                .copied(),
+        };
+        self.apply_filter();
+        self.status_msg = Some(match self.exposure_filter {
+            Some(exposure) => format!("Exposure: {}", exposure.describe()),
+            None => "Exposure: any".to_string(),
+        });
+    }
+
+    pub(super) fn toggle_unit_column(&mut self) {
+        let shown = self.columns.contains(&Column::Unit);
+        self.show_column(Column::Unit, !shown);
+    }
+
+    /// Shows the CPU and RSS columns and starts sampling, or hides them.
+    pub(super) fn toggle_usage_columns(&mut self) {
+        let show = !self.columns.contains(&Column::Cpu);
+        self.show_column(Column::Cpu, show);
+        self.show_column(Column::Rss, show);
+        if show {
+            self.sample_usage();
+        } else {
+            self.usage_sampled = None;
+        }
+    }
+
+    /// Reads the CPU and memory use of every listening process, re-sorting
+    /// in place when sorted by either.
+    pub(super) fn sample_usage(&mut self) {
+        let mut pids: Vec<u32> = self.entries.iter().map(|e| e.pid).collect();
+        pids.sort_unstable();
+        pids.dedup();
+        self.usage.sample(&pids);
+        self.usage_sampled = Some(Instant::now());
+        self.annotate_usage();
+        if matches!(self.sort.column, Column::Cpu | Column::Rss) {
+            self.resort_in_place();
+        }
+    }
+
+    /// Re-sorts after values changed without the user doing anything.
+    pub(super) fn resort_in_place(&mut self) {
+        let anchor = self.view_anchor();
+        self.apply_sort();
+        self.restore_view(anchor);
+    }
+
+    fn view_anchor(&self) -> ViewAnchor {
+        ViewAnchor {
+            listener: self.selected_entry().map(|e| (e.pid, e.port)),
+            on_process: self.process_selected(),
+            height: self.selected.saturating_sub(self.scroll_offset),
+            offset: self.scroll_offset,
+        }
+    }
+
+    /// Once the rows are rebuilt, puts the selection back on its listener
+    /// at the same height on screen, or keeps the view put if the
+    /// listener is gone.
+    fn restore_view(&mut self, anchor: ViewAnchor) {
+        let row = anchor
+            .listener
+            .and_then(|(pid, port)| {
+                self.entries
+                    .iter()
+                    .position(|e| e.pid == pid && e.port == port)
+            })
+            .and_then(|entry| {
+                self.rows.iter().position(|r| match r {
+                    Row::Process { .. } => anchor.on_process && r.contains(entry),
+                    _ => *r == Row::Entry(entry),
+                })
+            });
+        self.scroll_offset = match row {
+            Some(row) => {
+                self.selected = row;
+                row.saturating_sub(anchor.height)
+            }
+            None => anchor.offset,
+        };
+        let max_offset = self.rows.len().saturating_sub(self.visible_rows);
+        self.scroll_offset = self.scroll_offset.min(max_offset);
+        self.ensure_visible();
+    }
+
+    fn annotate_usage(&mut self) {
+        for e in &mut self.entries {
+            e.usage = self.usage.get(e.pid).cloned();
+        }
+    }
+
+    /// Adds an optional column just before `Command`, or removes it.
+    fn show_column(&mut self, column: Column, show: bool) {
+        let shown = self.columns.contains(&column);
+        if show && !shown {
+            let at = self
+                .columns
+                .iter()
+                .position(|c| *c == Column::Command)
+                .unwrap_or(self.columns.len());
+            self.columns.insert(at, column);
+        } else if !show && shown {
+            self.columns.retain(|c| *c != column);
+            if self.sort.column == column {
+                self.sort.column = Column::Port;
+                self.apply_sort();
+            }
+        }
+    }
+
+    /// Sorts by `column`, flipping the direction if it's already the sort column.
+    pub(super) fn sort_by(&mut self, column: Column) {
+        if self.sort.column == column {
+            self.sort.descending = !self.sort.descending;
+        } else {
+            self.sort.column = column;
+            self.sort.descending = false;
+        }
+        self.apply_sort();
+    }
+
+    pub(super) fn cycle_sort_column(&mut self) {
+        let sortable: Vec<Column> = self
+            .columns
+            .iter()
+            .copied()
+            .filter(|c| c.sortable())
+            .collect();
+        let Some(current) = sortable.iter().position(|c| *c == self.sort.column) else {
+            self.sort.column = sortable.first().copied().unwrap_or(Column::Port);
+            self.apply_sort();
+            return;
+        };
+        self.sort.column = sortable[cycle_index(current, sortable.len(), 1)];
+        self.apply_sort();
+    }
+
+    pub(super) fn apply_sort(&mut self) {
+        let sort = self.sort;
+        self.entries.sort_by(|a, b| {
+            let ord = compare_by(sort.column, a, b).then_with(|| a.port.cmp(&b.port));
+            if sort.descending { ord.reverse() } else { ord }
+        });
         self.apply_filter();
+        self.ensure_visible();
     }
 
     pub(super) fn apply_filter(&mut self) {
         let query = self.filter.to_ascii_lowercase();
-        self.filtered_entries = if query.is_empty() {
+        let exposure = self.exposure_filter;
+        self.filtered_entries = if query.is_empty() && exposure.is_none() {
             (0..self.entries.len()).collect()
         } else {
             self.entries
                 .iter()
                 .enumerate()
+                .filter(|(_, e)| exposure.is_none_or(|x| e.exposure() == x))
                 .filter(|(_, e)| {
-                    e.process_name.to_ascii_lowercase().contains(&query)
+                    query.is_empty()
+                        || e.process_name.to_ascii_lowercase().contains(&query)
                         || e.port.to_string().contains(&query)
                         || e.command.to_ascii_lowercase().contains(&query)
+                        || e.service
+                            .as_ref()
+                            .is_some_and(|s| s.to_ascii_lowercase().contains(&query))
+                        || e.container
+                            .as_ref()
+                            .is_some_and(|c| c.label().to_ascii_lowercase().contains(&query))
+                        || e.unit
+                            .as_ref()
+                            .is_some_and(|u| u.name.to_ascii_lowercase().contains(&query))
+                        || e.netns
+                            .as_ref()
+                            .is_some_and(|n| n.to_ascii_lowercase().contains(&query))
+                        || e.exposure().badge() == query
                 })
                 .map(|(i, _)| i)
                 .collect()
         };
 
-        if self.filtered_entries.is_empty() {
+        self.build_rows();
+        self.scroll_offset = 0;
+    }
+
+    /// Rebuilds `rows` from `filtered_entries`: a row per listener, or in
+    /// the grouped view a row per process in order of its first listener,
+    /// followed by its listeners while expanded. A listener with its
+    /// sockets expanded is followed by a row per socket.
+    fn build_rows(&mut self) {
+        let mut rows = Vec::new();
+        if self.grouped {
+            let mut groups: Vec<(u32, Vec<usize>)> = Vec::new();
+            for &i in &self.filtered_entries {
+                let pid = self.entries[i].pid;
+                match groups.iter_mut().find(|(p, _)| *p == pid) {
+                    Some((_, members)) => members.push(i),
+                    None => groups.push((pid, vec![i])),
+                }
+            }
+            for (pid, entries) in groups {
+                let expanded = self.expanded.contains(&pid);
+                let children = entries.clone();
+                rows.push(Row::Process {
+                    pid,
+                    entries,
+                    expanded,
+                });
+                if expanded {
+                    for i in children {
+                        self.push_entry_rows(&mut rows, i);
+                    }
+                }
+            }
+        } else {
+            for &i in &self.filtered_entries {
+                self.push_entry_rows(&mut rows, i);
+            }
+        }
+        self.rows = rows;
+
+        if self.rows.is_empty() {
             self.selected = 0;
         } else {
-            self.selected = self.selected.min(self.filtered_entries.len() - 1);
+            self.selected = self.selected.min(self.rows.len() - 1);
         }
-        self.scroll_offset = 0;
     }
 
-    pub(super) fn selected_entry(&self) -> Option<&PortEntry> {
-        let idx = self.filtered_entries.get(self.selected)?;
-        self.entries.get(*idx)
+    fn push_entry_rows(&self, rows: &mut Vec<Row>, entry: usize) {
+        rows.push(Row::Entry(entry));
+        if self.sockets_expanded(&self.entries[entry]) {
+            rows.extend(
+                (0..self.entries[entry].sockets.len()).map(|socket| Row::Socket { entry, socket }),
+            );
+        }
     }
+
+    /// Whether the listener has several sockets and they're shown.
+    pub(crate) fn sockets_expanded(&self, entry: &PortEntry) -> bool {
+        entry.sockets.len() > 1 && self.expanded_sockets.contains(&(entry.pid, entry.port))
+    }
+
+    /// Switches between the flat and grouped views, keeping the selected
+    /// listener, or its process, selected.
+    pub(super) fn toggle_grouped(&mut self) {
+        let selected = self.selected_entry_index();
+        self.grouped = !self.grouped;
+        self.build_rows();
+        if let Some(entry) = selected
+            && let Some(row) = self.rows.iter().position(|r| r.contains(entry))
+        {
+            self.select_index(row);
+        }
+        self.status_msg = Some(
+            if self.grouped {
+                "Grouped by process"
+            } else {
+                "One row per listener"
+            }
+            .to_string(),
+        );
+    }
+
+    /// Shows or hides the listeners of the selected process, or the
+    /// sockets of a listener with several. `None` toggles. Collapsing a
+    /// child row collapses its parent and selects it.
+    pub(super) fn expand_selected(&mut self, expand: Option<bool>) {
+        let Some(row) = self.rows.get(self.selected).cloned() else {
+            return;
+        };
+        let target = match row {
+            Row::Process { pid, .. } => {
+                set_expanded(&mut self.expanded, pid, expand);
+                Target::Process(pid)
+            }
+            // collapsing a listener whose sockets are hidden falls through
+            // to its process
+            Row::Entry(i)
+                if self.entries[i].sockets.len() > 1
+                    && (expand != Some(false) || self.sockets_expanded(&self.entries[i])) =>
+            {
+                let e = &self.entries[i];
+                set_expanded(&mut self.expanded_sockets, (e.pid, e.port), expand);
+                Target::Row(row)
+            }
+            Row::Socket { entry, .. } if expand != Some(true) => {
+                let e = &self.entries[entry];
+                self.expanded_sockets.remove(&(e.pid, e.port));
+                Target::Row(Row::Entry(entry))
+            }
+            Row::Entry(i) if self.grouped && expand != Some(true) => {
+                let pid = self.entries[i].pid;
+                self.expanded.remove(&pid);
+                Target::Process(pid)
+            }
+            _ => return,
+        };
+        self.build_rows();
+        let found = self.rows.iter().position(|r| match (&target, r) {
+            (Target::Process(pid), Row::Process { pid: p, .. }) => p == pid,
+            (Target::Row(row), r) => r == row,
+            _ => false,
+        });
+        if let Some(row) = found {
+            self.select_index(row);
+        }
+    }
+
+    /// The listener on the selected row, or a process row's first one.
+    fn selected_entry_index(&self) -> Option<usize> {
+        match self.rows.get(self.selected)? {
+            Row::Entry(i) | Row::Socket { entry: i, .. } => Some(*i),
+            Row::Process { entries, .. } => entries.first().copied(),
+        }
+    }
+
+    /// For a process row, its first listener: process-level actions like
+    /// killing work the same on any of them.
+    pub(crate) fn selected_entry(&self) -> Option<&PortEntry> {
+        self.entries.get(self.selected_entry_index()?)
+    }
+
+    /// Whether the selected row is a whole process in the grouped view.
+    pub(super) fn process_selected(&self) -> bool {
+        matches!(self.rows.get(self.selected), Some(Row::Process { .. }))
+    }
+}
+
+fn compare_by(column: Column, a: &PortEntry, b: &PortEntry) -> Ordering {
+    match column {
+        Column::Pid => a.pid.cmp(&b.pid),
+        Column::Process => a
+            .process_name
+            .to_ascii_lowercase()
+            .cmp(&b.process_name.to_ascii_lowercase()),
+        Column::Proto => Ordering::Equal,
+        Column::Address => a.address.cmp(&b.address),
+        Column::Exposure => a.exposure().cmp(&b.exposure()),
+        Column::Port => a.port.cmp(&b.port),
+        Column::Service => none_last(a.service.as_ref(), b.service.as_ref()),
+        Column::Container => none_last(
+            a.container.as_ref().map(|c| c.label()),
+            b.container.as_ref().map(|c| c.label()),
+        ),
+        Column::Unit => none_last(
+            a.unit.as_ref().map(|u| &u.name),
+            b.unit.as_ref().map(|u| &u.name),
+        ),
+        Column::Netns => none_last(a.netns.as_ref(), b.netns.as_ref()),
+        Column::Cpu => none_last_by(
+            a.usage.as_ref().and_then(|u| u.cpu),
+            b.usage.as_ref().and_then(|u| u.cpu),
+            f64::total_cmp,
+        ),
+        Column::Rss => none_last(
+            a.usage.as_ref().map(|u| u.rss),
+            b.usage.as_ref().map(|u| u.rss),
+        ),
+        Column::Command => a.command.cmp(&b.command),
+    }
+}
+
+/// Orders present values before missing ones, e.g. identified services
+/// before unknown ones.
+fn none_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    none_last_by(a, b, |x, y| x.cmp(y))
+}
+
+fn none_last_by<T>(a: Option<T>, b: Option<T>, cmp: impl Fn(&T, &T) -> Ordering) -> Ordering {
+    match (a, b) {
+        (Some(x), Some(y)) => cmp(&x, &y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// The row to select after expanding or collapsing.
+enum Target {
+    Process(u32),
+    Row(Row),
+}
+
+fn set_expanded<T: Eq + Hash>(set: &mut HashSet<T>, key: T, expand: Option<bool>) {
+    if expand.unwrap_or(!set.contains(&key)) {
+        set.insert(key);
+    } else {
+        set.remove(&key);
+    }
+}
+
+/// Where the selection was before the rows were rebuilt.
+struct ViewAnchor {
+    /// PID and port of the selected listener.
+    listener: Option<(u32, u16)>,
+    on_process: bool,
+    /// Rows between the top of the table and the selection.
+    height: usize,
+    offset: usize,
 }
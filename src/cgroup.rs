@@ -0,0 +1,43 @@
+use std::fs;
+
+/// cgroup paths of `pid`, one per hierarchy. Empty off Linux or when the
+/// process is gone.
+pub fn paths(pid: u32) -> Vec<String> {
+    fs::read_to_string(format!("/proc/{}/cgroup", pid))
+        .map(|content| parse(&content))
+        .unwrap_or_default()
+}
+
+/// Parses `/proc/<pid>/cgroup` lines of the form `id:controllers:path`.
+pub fn parse(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_v1_hierarchies() {
+        let paths = parse(include_str!("../fixtures/cgroup/docker-v1.txt"));
+        assert_eq!(paths.len(), 5);
+        assert!(paths[0].starts_with("/docker/3f4b"));
+        assert_eq!(paths[4], "/");
+    }
+
+    #[test]
+    fn parse_v2_unified() {
+        let paths = parse(include_str!("../fixtures/cgroup/host-process.txt"));
+        assert_eq!(paths, vec!["/user.slice/user-1000.slice/session-3.scope"]);
+    }
+
+    #[test]
+    fn parse_skips_malformed_lines() {
+        assert!(parse("garbage\n\n").is_empty());
+    }
+}
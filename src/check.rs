@@ -0,0 +1,355 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use crate::cli::{self, CheckArgs};
+use crate::container;
+use crate::ports::{self, PortEntry, ScanOptions};
+use crate::systemd::{self, UnitAction};
+
+/// Exit codes: 0 when every port is free, 1 when some are taken, 2 when a
+/// port file can't be read.
+pub fn run(args: &CheckArgs) -> ExitCode {
+    let mut wanted = args.ports.clone();
+    for path in &args.files {
+        match ports_from_file(path) {
+            Ok(ports) => wanted.extend(ports),
+            Err(e) => {
+                eprintln!("portit: {}: {}", path.display(), e);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    dedup_in_order(&mut wanted);
+
+    let scan = ScanOptions {
+        netns: args.netns.clone(),
+        ..Default::default()
+    };
+    let mut entries = ports::list_listening_ports(&scan);
+    let mut taken = occupied(&entries, &wanted);
+
+    if !args.quiet {
+        print!("{}", report(&wanted, &taken));
+    }
+
+    if args.kill && !taken.is_empty() {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+        let mut stopped = false;
+        for entry in &taken {
+            if confirm(&mut input, &prompt(entry)) {
+                println!("{}", stop(entry));
+                stopped = true;
+            }
+        }
+        if stopped {
+            // give SIGTERM handlers a moment before looking again
+            thread::sleep(Duration::from_millis(300));
+        }
+        entries = ports::list_listening_ports(&scan);
+        taken = occupied(&entries, &wanted);
+        if !args.quiet && !taken.is_empty() {
+            let ports: Vec<String> = taken.iter().map(|e| e.port.to_string()).collect();
+            println!("still in use: {}", ports.join(", "));
+        }
+    }
+
+    if taken.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
+}
+
+/// Listeners on any of `wanted`, in the order the ports were given.
+fn occupied(entries: &[PortEntry], wanted: &[u16]) -> Vec<PortEntry> {
+    wanted
+        .iter()
+        .flat_map(|&port| entries.iter().filter(move |e| e.port == port))
+        .cloned()
+        .collect()
+}
+
+fn report(wanted: &[u16], taken: &[PortEntry]) -> String {
+    let mut out = format!(
+        "{:<7}{:<8}{:<8}{:<16}{}\n",
+        "PORT", "STATUS", "PID", "PROCESS", "COMMAND"
+    );
+    for &port in wanted {
+        let owners: Vec<&PortEntry> = taken.iter().filter(|e| e.port == port).collect();
+        if owners.is_empty() {
+            out.push_str(&format!("{:<7}free\n", port));
+        }
+        for e in owners {
+            let command = match (&e.container, &e.unit) {
+                (Some(c), _) => format!("container {}", c.label()),
+                (None, Some(u)) => format!("unit {}", u.label()),
+                (None, None) => e.command.clone(),
+            };
+            out.push_str(&format!(
+                "{:<7}{:<8}{:<8}{:<16}{}\n",
+                port, "in use", e.pid, e.process_name, command
+            ));
+        }
+    }
+    let in_use = wanted
+        .iter()
+        .filter(|p| taken.iter().any(|e| e.port == **p))
+        .count();
+    out.push_str(&format!("{} of {} ports in use\n", in_use, wanted.len()));
+    out
+}
+
+fn prompt(entry: &PortEntry) -> String {
+    match (&entry.container, &entry.unit) {
+        (Some(c), _) => format!("Stop container {} on port {}?", c.label(), entry.port),
+        (None, Some(u)) => format!("Stop unit {} on port {}?", u.label(), entry.port),
+        (None, None) => format!(
+            "Kill {} (PID {}) on port {}?",
+            entry.process_name, entry.pid, entry.port
+        ),
+    }
+}
+
+fn confirm(input: &mut impl BufRead, prompt: &str) -> bool {
+    print!("{} [y/N] ", prompt);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if input.read_line(&mut answer).unwrap_or(0) == 0 {
+        println!();
+        return false;
+    }
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+/// Frees the port the way the TUI would: through the container runtime or
+/// systemd when they own the listener, otherwise with SIGTERM.
+fn stop(entry: &PortEntry) -> String {
+    if let Some(c) = &entry.container {
+        return if container::run_action(c, container::ContainerAction::Stop) {
+            format!("stopped container {}", c.label())
+        } else {
+            format!("failed to stop container {}", c.label())
+        };
+    }
+    if let Some(unit) = &entry.unit {
+        return match systemd::run_action(unit, UnitAction::Stop) {
+            Ok(()) => format!("stopped {}", unit.name),
+            Err(e) => format!("failed to stop {}: {}", unit.name, e),
+        };
+    }
+    match ports::kill_process(entry.pid, false, None) {
+        Ok(()) => format!("killed {} (PID {})", entry.process_name, entry.pid),
+        Err(e) => format!(
+            "failed to kill {} (PID {}): {}",
+            entry.process_name, entry.pid, e
+        ),
+    }
+}
+
+fn dedup_in_order(ports: &mut Vec<u16>) {
+    let mut seen = std::collections::HashSet::new();
+    ports.retain(|p| seen.insert(*p));
+}
+
+/// Ports from a compose file or a `.env` file, told apart by name.
+fn ports_from_file(path: &Path) -> Result<Vec<u16>, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if name.ends_with(".yml") || name.ends_with(".yaml") {
+        Ok(compose_ports(&content))
+    } else if name == ".env" || name.starts_with(".env.") || name.ends_with(".env") {
+        Ok(env_ports(&content))
+    } else {
+        Err("expected a compose .yml/.yaml or a .env file".to_string())
+    }
+}
+
+/// Host ports published by `ports:` sections of a compose file, in short
+/// (`"8080:80"`, `"127.0.0.1:8000-8002:8000-8002"`) or long
+/// (`published: 8080`) syntax. Container-only ports and UDP are skipped.
+pub fn compose_ports(content: &str) -> Vec<u16> {
+    let mut ports = Vec::new();
+    let mut section: Option<usize> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+
+        if let Some(section_indent) = section {
+            // list items may sit at the same indent as `ports:`
+            if indent > section_indent || (indent == section_indent && trimmed.starts_with('-')) {
+                let item = trimmed.split(" #").next().unwrap_or(trimmed);
+                let item = item.trim_start_matches('-').trim();
+                if let Some(published) = item.strip_prefix("published:") {
+                    ports.extend(parse_host_ports(unquote(published.trim())));
+                } else if trimmed.starts_with('-') && !item.contains(": ") {
+                    ports.extend(short_syntax_ports(unquote(item)));
+                }
+                continue;
+            }
+            section = None;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("ports:") {
+            let rest = rest.trim();
+            if let Some(flow) = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+                for item in flow.split(',') {
+                    ports.extend(short_syntax_ports(unquote(item.trim())));
+                }
+            } else {
+                section = Some(indent);
+            }
+        }
+    }
+    ports
+}
+
+/// `[ip:]host:container[/proto]` -> host ports.
+fn short_syntax_ports(item: &str) -> Vec<u16> {
+    let item = resolve_defaults(item);
+    let (mapping, proto) = item.split_once('/').unwrap_or((&item, "tcp"));
+    if proto != "tcp" {
+        return Vec::new();
+    }
+    // IPv6 host IPs come in brackets: [::1]:8080:80
+    let mapping = mapping.rsplit_once(']').map_or(mapping, |(_, rest)| rest);
+    let parts: Vec<&str> = mapping.trim_start_matches(':').split(':').collect();
+    match parts.len() {
+        // only a container port: the host side is ephemeral
+        0 | 1 => Vec::new(),
+        n => parse_host_ports(parts[n - 2]),
+    }
+}
+
+fn parse_host_ports(spec: &str) -> Vec<u16> {
+    cli::parse_port_spec(&resolve_defaults(spec)).unwrap_or_default()
+}
+
+/// Replaces `${VAR:-default}` and `${VAR-default}` with the default; other
+/// variables are left as is and then fail to parse.
+fn resolve_defaults(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let inner = &rest[start + 2..start + end];
+        match inner.split_once(":-").or_else(|| inner.split_once('-')) {
+            Some((_, default)) => out.push_str(default),
+            None => out.push_str(&rest[start..=start + end]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Values of `*PORT*` variables, e.g. `DB_PORT=5432` or `export PORT="3000"`.
+pub fn env_ports(content: &str) -> Vec<u16> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.strip_prefix("export ").unwrap_or(l).split_once('='))
+        .filter(|(key, _)| key.trim().to_ascii_uppercase().contains("PORT"))
+        .filter_map(|(_, value)| {
+            let value = value.split(" #").next().unwrap_or(value);
+            unquote(value.trim()).parse::<u16>().ok()
+        })
+        .filter(|&port| port != 0)
+        .collect()
+}
+
+fn unquote(s: &str) -> &str {
+    s.trim_matches(|c| c == '"' || c == '\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose_short_and_long_syntax() {
+        let ports = compose_ports(include_str!("../fixtures/check/docker-compose.yml"));
+        assert_eq!(
+            ports,
+            [5432, 6379, 8080, 8443, 9000, 9001, 9002, 3000, 4000, 5000]
+        );
+    }
+
+    #[test]
+    fn compose_skips_container_only_and_udp() {
+        assert!(short_syntax_ports("3000").is_empty());
+        assert!(short_syntax_ports("5353:53/udp").is_empty());
+        assert_eq!(short_syntax_ports("[::1]:8080:80"), [8080]);
+        assert_eq!(short_syntax_ports("127.0.0.1:8080:80/tcp"), [8080]);
+    }
+
+    #[test]
+    fn compose_variable_defaults() {
+        assert_eq!(resolve_defaults("${WEB_PORT:-8080}:80"), "8080:80");
+        assert_eq!(resolve_defaults("${WEB_PORT-81}:80"), "81:80");
+        assert_eq!(resolve_defaults("${WEB_PORT}:80"), "${WEB_PORT}:80");
+        assert!(short_syntax_ports("${WEB_PORT}:80").is_empty());
+    }
+
+    #[test]
+    fn env_file_ports() {
+        let ports = env_ports(include_str!("../fixtures/check/local.env"));
+        assert_eq!(ports, [5432, 3000, 6379, 8025]);
+    }
+
+    #[test]
+    fn report_lists_free_and_taken_ports() {
+        let taken = vec![PortEntry {
+            pid: 812,
+            process_name: "postgres".to_string(),
+            port: 5432,
+            command: "postgres -D /var/lib/postgresql".to_string(),
+            ..Default::default()
+        }];
+        let out = report(&[5432, 6379], &taken);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("PORT"));
+        assert_eq!(
+            lines[1],
+            "5432   in use  812     postgres        postgres -D /var/lib/postgresql"
+        );
+        assert_eq!(lines[2], "6379   free");
+        assert_eq!(lines[3], "1 of 2 ports in use");
+    }
+
+    #[test]
+    fn occupied_keeps_requested_order() {
+        let entry = |port| PortEntry {
+            port,
+            ..Default::default()
+        };
+        let entries = vec![entry(80), entry(443), entry(8080)];
+        let taken: Vec<u16> = occupied(&entries, &[8080, 22, 80])
+            .iter()
+            .map(|e| e.port)
+            .collect();
+        assert_eq!(taken, [8080, 80]);
+    }
+
+    #[test]
+    fn confirm_answers() {
+        assert!(confirm(&mut "y\n".as_bytes(), "Kill?"));
+        assert!(!confirm(&mut "\n".as_bytes(), "Kill?"));
+        assert!(!confirm(&mut "".as_bytes(), "Kill?"));
+    }
+}
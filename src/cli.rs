@@ -0,0 +1,836 @@
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::monitor::Webhook;
+use crate::netns::NetnsScope;
+use crate::ports::Backend;
+use crate::wait::Until;
+
+pub const USAGE: &str = "\
+Usage: portit [options]
+       portit <command> [args]
+
+Options:
+  -f, --fullscreen   Use the alternate screen and the full terminal height
+  --clipboard-fallback
+                     Also copy with wl-copy, xclip, xsel or pbcopy, not only OSC 52
+  -b, --banners      Identify services by connecting to each listener
+  --netns NAME|all   List listeners in another network namespace, or in all
+  --backend NAME     How to find listeners: auto (default), lsof, or netlink
+                     for the kernel's sock_diag on Linux
+  --host HOST        List and kill listeners on HOST through ssh
+  --log FILE         Append observed listeners and every kill to FILE
+  --load FILE        Browse a saved snapshot instead of live listeners
+  --policy FILE      Flag listeners breaking a policy (default
+                     ~/.config/portit/policy, if it exists)
+  -h, --help         Print this help
+  -V, --version      Print version
+
+Commands:
+  wait PORT...       Wait for ports to open or become free
+  check PORT...      Report which ports are already taken, and by what
+  free               Print unused ports for ad-hoc servers
+  monitor            Report listeners as they appear or go away
+  snapshot [FILE]    Save the current listeners as JSON
+  diff BEFORE AFTER  Compare two snapshots
+  audit              Report listeners breaking a policy";
+
+pub const CHECK_USAGE: &str = "\
+Usage: portit check [options] [PORT|FROM-TO...]
+
+Reports which of the given ports are already listening, with the PID,
+process and command holding each one.
+
+Options:
+  -f, --file FILE    Also check the host ports published in a compose file
+                     (.yml/.yaml) or the *PORT* variables of a .env file
+  -k, --kill         Offer to kill each offender, or stop its container or
+                     systemd unit
+  -q, --quiet        Print nothing, only set the exit status
+  --netns NAME|all   Look in another network namespace
+  -h, --help         Print this help
+
+Exit status: 0 when every port is free, 1 when some are in use, 2 on usage
+errors or unreadable files.";
+
+pub const MONITOR_USAGE: &str = "\
+Usage: portit monitor [options]
+
+Watches for listeners that appear or go away and prints each change as a
+JSON line on stdout, until killed.
+
+Options:
+  -i, --interval DUR How often to rescan (default 2s)
+  -a, --allow FILE   Ignore listeners matching FILE, one rule per line:
+                     PROCESS, PORT, FROM-TO or PROCESS:PORTS (* = any)
+  --notify           Also send a desktop notification with notify-send
+  --webhook URL      Also POST each event to an http:// URL
+  -q, --quiet        Don't print events on stdout
+  --netns NAME|all   Watch another network namespace
+  -h, --help         Print this help";
+
+pub const SNAPSHOT_USAGE: &str = "\
+Usage: portit snapshot [options] [FILE]
+
+Saves the current listeners as JSON to FILE, or prints them. Open a
+snapshot with `portit --load FILE`, or compare two with `portit diff`.
+
+Options:
+  --netns NAME|all   Capture another network namespace
+  -h, --help         Print this help";
+
+pub const DIFF_USAGE: &str = "\
+Usage: portit diff BEFORE AFTER
+
+Shows listeners added, removed, or owned by a different process between two
+snapshots.
+
+Exit status: 0 when they match, 1 when they differ, 2 on errors.";
+
+pub const AUDIT_USAGE: &str = "\
+Usage: portit audit [options]
+
+Checks the listeners against a policy file and prints those breaking it.
+Rules, one per line, # for comments:
+
+  nothing on PORTS                   e.g. nothing on 23, 6000-6063
+  only NAMES may listen on ADDRESS   e.g. only sshd may listen on public
+  ports PORTS must bind ADDRESSES    e.g. ports 8000-8999 must bind localhost
+
+ADDRESS is an IP, `*` for the wildcard, `localhost` for any loopback
+address, or `public` for anything but loopback.
+
+Options:
+  -p, --policy FILE  Policy to check (default ~/.config/portit/policy)
+  --load FILE        Check a saved snapshot instead of live listeners
+  -q, --quiet        Print nothing, only set the exit status
+  --netns NAME|all   Check another network namespace
+  -h, --help         Print this help
+
+Exit status: 0 when nothing breaks the policy, 1 on violations, 2 on
+errors.";
+
+pub const FREE_USAGE: &str = "\
+Usage: portit free [options]
+
+Prints free ports, one per line. A port is free when nothing listens on it
+and binding it actually succeeds.
+
+Options:
+  -n, --count N      How many ports to find (default 1)
+  -u, --udp          Find UDP ports instead of TCP
+  -r, --range FROM-TO
+                     Only look in this range (default 1024-65535)
+  --no-well-known    Skip ports below 1024 and well-known service ports
+  --no-ephemeral     Skip the OS's ephemeral range for outgoing connections
+  -h, --help         Print this help
+
+Exit status: 0 when enough ports were found, 1 when the range ran out, 2 on
+usage errors.";
+
+pub const WAIT_USAGE: &str = "\
+Usage: portit wait [options] PORT...
+
+Waits until every PORT is listening, or with --free until none is, and
+prints the owning process as each one opens.
+
+Options:
+  --free             Wait for the ports to be released instead
+  -t, --timeout DUR  Give up after DUR, e.g. 30s, 500ms, 2m; 0 waits forever
+                     (default 30s)
+  -i, --interval DUR Time between checks (default 250ms)
+  -q, --quiet        Print nothing, only set the exit status
+  --netns NAME|all   Look in another network namespace
+  -h, --help         Print this help
+
+Exit status: 0 once reached, 1 on timeout, 2 on usage errors.";
+
+#[derive(Default)]
+pub struct Args {
+    pub fullscreen: bool,
+    pub clipboard_fallback: bool,
+    pub grab_banners: bool,
+    pub netns: Option<NetnsScope>,
+    pub backend: Backend,
+    pub host: Option<String>,
+    pub log: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub policy: Option<PathBuf>,
+}
+
+pub struct WaitArgs {
+    pub ports: Vec<u16>,
+    pub until: Until,
+    /// `None` waits forever.
+    pub timeout: Option<Duration>,
+    pub interval: Duration,
+    pub quiet: bool,
+    pub netns: Option<NetnsScope>,
+}
+
+pub struct CheckArgs {
+    pub ports: Vec<u16>,
+    pub files: Vec<PathBuf>,
+    pub kill: bool,
+    pub quiet: bool,
+    pub netns: Option<NetnsScope>,
+}
+
+pub struct FreeArgs {
+    pub count: usize,
+    pub udp: bool,
+    pub range: RangeInclusive<u16>,
+    pub avoid_well_known: bool,
+    pub avoid_ephemeral: bool,
+}
+
+pub struct MonitorArgs {
+    pub interval: Duration,
+    pub allowlist: Option<PathBuf>,
+    pub notify: bool,
+    pub webhook: Option<Webhook>,
+    pub quiet: bool,
+    pub netns: Option<NetnsScope>,
+}
+
+pub struct SnapshotArgs {
+    pub output: Option<PathBuf>,
+    pub netns: Option<NetnsScope>,
+}
+
+pub struct AuditArgs {
+    pub policy: Option<PathBuf>,
+    pub load: Option<PathBuf>,
+    pub quiet: bool,
+    pub netns: Option<NetnsScope>,
+}
+
+pub struct DiffArgs {
+    pub before: PathBuf,
+    pub after: PathBuf,
+}
+
+pub enum Cli {
+    Tui(Args),
+    Wait(WaitArgs),
+    Check(CheckArgs),
+    Free(FreeArgs),
+    Monitor(MonitorArgs),
+    Snapshot(SnapshotArgs),
+    Diff(DiffArgs),
+    Audit(AuditArgs),
+    /// Usage text of the command help was asked for.
+    Help(&'static str),
+    Version,
+}
+
+impl Cli {
+    /// The namespace a scanning command was pointed at.
+    pub fn netns(&self) -> Option<&NetnsScope> {
+        match self {
+            Cli::Tui(args) => args.netns.as_ref(),
+            Cli::Wait(args) => args.netns.as_ref(),
+            Cli::Check(args) => args.netns.as_ref(),
+            Cli::Monitor(args) => args.netns.as_ref(),
+            Cli::Snapshot(args) => args.netns.as_ref(),
+            Cli::Audit(args) => args.netns.as_ref(),
+            Cli::Free(_) | Cli::Diff(_) | Cli::Help(_) | Cli::Version => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UsageError {
+    pub message: String,
+    /// Usage of the command being parsed, printed after the message.
+    pub usage: &'static str,
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Cli, UsageError> {
+    let mut args = args.into_iter().peekable();
+    let (usage, result) = match args.peek().map(String::as_str) {
+        Some("wait") => {
+            args.next();
+            (WAIT_USAGE, parse_wait(args))
+        }
+        Some("check") => {
+            args.next();
+            (CHECK_USAGE, parse_check(args))
+        }
+        Some("free") => {
+            args.next();
+            (FREE_USAGE, parse_free(args))
+        }
+        Some("monitor") => {
+            args.next();
+            (MONITOR_USAGE, parse_monitor(args))
+        }
+        Some("snapshot") => {
+            args.next();
+            (SNAPSHOT_USAGE, parse_snapshot(args))
+        }
+        Some("diff") => {
+            args.next();
+            (DIFF_USAGE, parse_diff(args))
+        }
+        Some("audit") => {
+            args.next();
+            (AUDIT_USAGE, parse_audit(args))
+        }
+        _ => (USAGE, parse_tui(args)),
+    };
+    result.map_err(|message| UsageError { message, usage })
+}
+
+fn parse_tui(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = Args::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--fullscreen" => parsed.fullscreen = true,
+            "--clipboard-fallback" => parsed.clipboard_fallback = true,
+            "-b" | "--banners" => parsed.grab_banners = true,
+            "--netns" => parsed.netns = Some(parse_netns(&mut args)?),
+            "--backend" => {
+                let name = value(&mut args, &arg)?;
+                parsed.backend = Backend::parse(&name)
+                    .ok_or_else(|| format!("unknown backend: {} (auto, lsof or netlink)", name))?;
+            }
+            "--host" => parsed.host = Some(value(&mut args, &arg)?),
+            "--log" => parsed.log = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--load" => parsed.load = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--policy" => parsed.policy = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "-h" | "--help" => return Ok(Cli::Help(USAGE)),
+            "-V" | "--version" => return Ok(Cli::Version),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if parsed.host.is_some()
+        && (parsed.netns.is_some() || parsed.grab_banners || parsed.load.is_some())
+    {
+        return Err("--host can't be combined with --netns, --banners or --load".to_string());
+    }
+    Ok(Cli::Tui(parsed))
+}
+
+fn parse_wait(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = WaitArgs {
+        ports: Vec::new(),
+        until: Until::Open,
+        timeout: Some(Duration::from_secs(30)),
+        interval: Duration::from_millis(250),
+        quiet: false,
+        netns: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--free" => parsed.until = Until::Free,
+            "-t" | "--timeout" => {
+                let timeout = parse_duration(&value(&mut args, &arg)?)?;
+                parsed.timeout = (!timeout.is_zero()).then_some(timeout);
+            }
+            "-i" | "--interval" => parsed.interval = parse_duration(&value(&mut args, &arg)?)?,
+            "-q" | "--quiet" => parsed.quiet = true,
+            "--netns" => parsed.netns = Some(parse_netns(&mut args)?),
+            "-h" | "--help" => return Ok(Cli::Help(WAIT_USAGE)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => parsed.ports.push(parse_port(&arg)?),
+        }
+    }
+
+    if parsed.ports.is_empty() {
+        return Err("no port given".to_string());
+    }
+    Ok(Cli::Wait(parsed))
+}
+
+fn parse_check(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = CheckArgs {
+        ports: Vec::new(),
+        files: Vec::new(),
+        kill: false,
+        quiet: false,
+        netns: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--file" => parsed.files.push(value(&mut args, &arg)?.into()),
+            "-k" | "--kill" => parsed.kill = true,
+            "-q" | "--quiet" => parsed.quiet = true,
+            "--netns" => parsed.netns = Some(parse_netns(&mut args)?),
+            "-h" | "--help" => return Ok(Cli::Help(CHECK_USAGE)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => parsed.ports.extend(parse_port_spec(&arg)?),
+        }
+    }
+
+    if parsed.ports.is_empty() && parsed.files.is_empty() {
+        return Err("no port or file given".to_string());
+    }
+    Ok(Cli::Check(parsed))
+}
+
+fn parse_free(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = FreeArgs {
+        count: 1,
+        udp: false,
+        range: 1024..=65535,
+        avoid_well_known: false,
+        avoid_ephemeral: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--count" => {
+                let n = value(&mut args, &arg)?;
+                parsed.count = match n.parse() {
+                    Ok(0) | Err(_) => return Err(format!("invalid count: {}", n)),
+                    Ok(count) => count,
+                };
+            }
+            "-u" | "--udp" => parsed.udp = true,
+            "-r" | "--range" => {
+                let spec = value(&mut args, &arg)?;
+                let ports = parse_port_spec(&spec)?;
+                parsed.range = ports[0]..=ports[ports.len() - 1];
+            }
+            "--no-well-known" => parsed.avoid_well_known = true,
+            "--no-ephemeral" => parsed.avoid_ephemeral = true,
+            "-h" | "--help" => return Ok(Cli::Help(FREE_USAGE)),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    Ok(Cli::Free(parsed))
+}
+
+fn parse_monitor(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = MonitorArgs {
+        interval: Duration::from_secs(2),
+        allowlist: None,
+        notify: false,
+        webhook: None,
+        quiet: false,
+        netns: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-i" | "--interval" => {
+                parsed.interval = parse_duration(&value(&mut args, &arg)?)?;
+                if parsed.interval.is_zero() {
+                    return Err("--interval must be more than 0".to_string());
+                }
+            }
+            "-a" | "--allow" => parsed.allowlist = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--notify" => parsed.notify = true,
+            "--webhook" => parsed.webhook = Some(Webhook::parse(&value(&mut args, &arg)?)?),
+            "-q" | "--quiet" => parsed.quiet = true,
+            "--netns" => parsed.netns = Some(parse_netns(&mut args)?),
+            "-h" | "--help" => return Ok(Cli::Help(MONITOR_USAGE)),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    Ok(Cli::Monitor(parsed))
+}
+
+fn parse_snapshot(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = SnapshotArgs {
+        output: None,
+        netns: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--netns" => parsed.netns = Some(parse_netns(&mut args)?),
+            "-h" | "--help" => return Ok(Cli::Help(SNAPSHOT_USAGE)),
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown argument: {}", arg));
+            }
+            _ if parsed.output.is_some() => return Err(format!("unexpected argument: {}", arg)),
+            // `-` is stdout, same as no file
+            _ => parsed.output = (arg != "-").then(|| PathBuf::from(arg)),
+        }
+    }
+
+    Ok(Cli::Snapshot(parsed))
+}
+
+fn parse_audit(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut parsed = AuditArgs {
+        policy: None,
+        load: None,
+        quiet: false,
+        netns: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" | "--policy" => parsed.policy = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--load" => parsed.load = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "-q" | "--quiet" => parsed.quiet = true,
+            "--netns" => parsed.netns = Some(parse_netns(&mut args)?),
+            "-h" | "--help" => return Ok(Cli::Help(AUDIT_USAGE)),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    Ok(Cli::Audit(parsed))
+}
+
+fn parse_diff(args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Cli::Help(DIFF_USAGE)),
+            _ if arg.starts_with('-') => return Err(format!("unknown argument: {}", arg)),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    let [before, after] =
+        <[PathBuf; 2]>::try_from(files).map_err(|_| "diff needs two snapshot files".to_string())?;
+    Ok(Cli::Diff(DiffArgs { before, after }))
+}
+
+/// The argument following `flag`.
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn parse_netns(args: &mut impl Iterator<Item = String>) -> Result<NetnsScope, String> {
+    let name = args
+        .next()
+        .ok_or("--netns needs a namespace name or `all`")?;
+    Ok(NetnsScope::parse(&name))
+}
+
+fn parse_port(s: &str) -> Result<u16, String> {
+    match s.parse() {
+        Ok(0) | Err(_) => Err(format!("invalid port: {}", s)),
+        Ok(port) => Ok(port),
+    }
+}
+
+/// A port or an inclusive range like `8000-8010`.
+pub fn parse_port_spec(s: &str) -> Result<Vec<u16>, String> {
+    match s.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (parse_port(from)?, parse_port(to)?);
+            if from > to {
+                return Err(format!("invalid port range: {}", s));
+            }
+            Ok((from..=to).collect())
+        }
+        None => Ok(vec![parse_port(s)?]),
+    }
+}
+
+/// `500ms`, `30s`, `2m`, or a bare number of seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration: {}", s);
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = number.parse().map_err(|_| invalid())?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(n)),
+        "s" => Ok(Duration::from_secs(n)),
+        "m" => n
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_strs(args: &[&str]) -> Result<Cli, UsageError> {
+        parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let Ok(Cli::Tui(args)) = parse_strs(&[]) else {
+            panic!("expected tui");
+        };
+        assert!(!args.fullscreen);
+        assert!(!args.clipboard_fallback);
+    }
+
+    #[test]
+    fn parse_fullscreen() {
+        for flag in ["-f", "--fullscreen"] {
+            let Ok(Cli::Tui(args)) = parse_strs(&[flag]) else {
+                panic!("expected tui");
+            };
+            assert!(args.fullscreen);
+        }
+    }
+
+    #[test]
+    fn parse_help_and_version() {
+        assert!(matches!(parse_strs(&["--help"]), Ok(Cli::Help(USAGE))));
+        assert!(matches!(parse_strs(&["-V"]), Ok(Cli::Version)));
+    }
+
+    #[test]
+    fn parse_netns() {
+        let Ok(Cli::Tui(args)) = parse_strs(&["--netns", "all"]) else {
+            panic!("expected tui");
+        };
+        assert_eq!(args.netns, Some(NetnsScope::All));
+        let Ok(Cli::Tui(args)) = parse_strs(&["--netns", "sandbox", "-f"]) else {
+            panic!("expected tui");
+        };
+        assert_eq!(args.netns, Some(NetnsScope::Named("sandbox".to_string())));
+        assert!(args.fullscreen);
+        assert!(parse_strs(&["--netns"]).is_err());
+    }
+
+    #[test]
+    fn parse_backend() {
+        let Ok(Cli::Tui(args)) = parse_strs(&["--backend", "lsof"]) else {
+            panic!("expected tui");
+        };
+        assert_eq!(args.backend, Backend::Lsof);
+        let Ok(Cli::Tui(args)) = parse_strs(&[]) else {
+            panic!("expected tui");
+        };
+        assert_eq!(args.backend, Backend::Auto);
+        assert!(parse_strs(&["--backend", "ss"]).is_err());
+    }
+
+    #[test]
+    fn parse_host() {
+        let Ok(Cli::Tui(args)) = parse_strs(&["--host", "devbox"]) else {
+            panic!("expected tui");
+        };
+        assert_eq!(args.host.as_deref(), Some("devbox"));
+        assert!(parse_strs(&["--host"]).is_err());
+        assert!(parse_strs(&["--host", "devbox", "--netns", "all"]).is_err());
+    }
+
+    #[test]
+    fn parse_unknown() {
+        assert!(parse_strs(&["--nope"]).is_err());
+    }
+
+    fn parse_wait_strs(args: &[&str]) -> WaitArgs {
+        match parse_strs(args) {
+            Ok(Cli::Wait(args)) => args,
+            _ => panic!("expected wait"),
+        }
+    }
+
+    #[test]
+    fn parse_wait_defaults() {
+        let args = parse_wait_strs(&["wait", "5432", "6379"]);
+        assert_eq!(args.ports, [5432, 6379]);
+        assert_eq!(args.until, Until::Open);
+        assert_eq!(args.timeout, Some(Duration::from_secs(30)));
+        assert!(!args.quiet);
+    }
+
+    #[test]
+    fn parse_wait_options() {
+        let args = parse_wait_strs(&["wait", "--free", "-t", "2m", "-i", "100ms", "-q", "3000"]);
+        assert_eq!(args.until, Until::Free);
+        assert_eq!(args.timeout, Some(Duration::from_secs(120)));
+        assert_eq!(args.interval, Duration::from_millis(100));
+        assert!(args.quiet);
+        // 0 waits forever
+        assert_eq!(parse_wait_strs(&["wait", "-t", "0", "1"]).timeout, None);
+    }
+
+    #[test]
+    fn parse_wait_errors_use_wait_usage() {
+        for args in [
+            &["wait"][..],
+            &["wait", "http"],
+            &["wait", "0"],
+            &["wait", "-t", "soon", "80"],
+            &["wait", "80", "--timeout"],
+        ] {
+            let Err(e) = parse_strs(args) else {
+                panic!("expected error for {:?}", args);
+            };
+            assert_eq!(e.usage, WAIT_USAGE);
+        }
+        assert!(matches!(
+            parse_strs(&["wait", "--help"]),
+            Ok(Cli::Help(WAIT_USAGE))
+        ));
+        let Err(e) = parse_strs(&["wait", "--timout", "5", "80"]) else {
+            panic!("expected error");
+        };
+        assert_eq!(e.message, "unknown option: --timout");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("15"), Ok(Duration::from_secs(15)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse_duration("1h").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration(&format!("{}m", u64::MAX / 60 + 1)).is_err());
+    }
+
+    #[test]
+    fn parse_check_ports_and_files() {
+        let Ok(Cli::Check(args)) = parse_strs(&[
+            "check",
+            "5432",
+            "8000-8002",
+            "-f",
+            "docker-compose.yml",
+            "--kill",
+        ]) else {
+            panic!("expected check");
+        };
+        assert_eq!(args.ports, [5432, 8000, 8001, 8002]);
+        assert_eq!(args.files, [PathBuf::from("docker-compose.yml")]);
+        assert!(args.kill);
+
+        assert!(matches!(
+            parse_strs(&["check", "--file", ".env"]),
+            Ok(Cli::Check(_))
+        ));
+        for args in [&["check"][..], &["check", "90-80"], &["check", "-f"]] {
+            let Err(e) = parse_strs(args) else {
+                panic!("expected error for {:?}", args);
+            };
+            assert_eq!(e.usage, CHECK_USAGE);
+        }
+        let Err(e) = parse_strs(&["check", "-x", "80"]) else {
+            panic!("expected error");
+        };
+        assert_eq!(e.message, "unknown option: -x");
+        assert_eq!(e.usage, CHECK_USAGE);
+    }
+
+    #[test]
+    fn port_specs() {
+        assert_eq!(parse_port_spec("80"), Ok(vec![80]));
+        assert_eq!(parse_port_spec("80-82"), Ok(vec![80, 81, 82]));
+        assert!(parse_port_spec("0").is_err());
+        assert!(parse_port_spec("80-").is_err());
+        assert!(parse_port_spec("70000").is_err());
+    }
+
+    #[test]
+    fn parse_free_options() {
+        let Ok(Cli::Free(args)) = parse_strs(&["free"]) else {
+            panic!("expected free");
+        };
+        assert_eq!((args.count, args.range), (1, 1024..=65535));
+
+        let Ok(Cli::Free(args)) = parse_strs(&[
+            "free",
+            "-n",
+            "3",
+            "--udp",
+            "--range",
+            "8000-8100",
+            "--no-well-known",
+            "--no-ephemeral",
+        ]) else {
+            panic!("expected free");
+        };
+        assert_eq!(args.count, 3);
+        assert!(args.udp && args.avoid_well_known && args.avoid_ephemeral);
+        assert_eq!(args.range, 8000..=8100);
+
+        for args in [&["free", "-n", "0"][..], &["free", "3000"], &["free", "-r"]] {
+            let Err(e) = parse_strs(args) else {
+                panic!("expected error for {:?}", args);
+            };
+            assert_eq!(e.usage, FREE_USAGE);
+        }
+    }
+
+    #[test]
+    fn parse_monitor_options() {
+        let Ok(Cli::Monitor(args)) = parse_strs(&[
+            "monitor",
+            "-i",
+            "500ms",
+            "--allow",
+            "allow.txt",
+            "--notify",
+            "--webhook",
+            "http://127.0.0.1:9000/hook",
+        ]) else {
+            panic!("expected monitor");
+        };
+        assert_eq!(args.interval, Duration::from_millis(500));
+        assert_eq!(args.allowlist, Some(PathBuf::from("allow.txt")));
+        assert!(args.notify && !args.quiet);
+        assert!(args.webhook.is_some());
+
+        for args in [
+            &["monitor", "--webhook", "https://example.com"][..],
+            &["monitor", "-i", "0"],
+            &["monitor", "3000"],
+        ] {
+            let Err(e) = parse_strs(args) else {
+                panic!("expected error for {:?}", args);
+            };
+            assert_eq!(e.usage, MONITOR_USAGE);
+        }
+    }
+
+    #[test]
+    fn parse_snapshot_and_diff() {
+        let Ok(Cli::Snapshot(args)) = parse_strs(&["snapshot", "before.json"]) else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(args.output, Some(PathBuf::from("before.json")));
+        let Ok(Cli::Snapshot(args)) = parse_strs(&["snapshot"]) else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(args.output, None);
+        assert!(parse_strs(&["snapshot", "a.json", "b.json"]).is_err());
+
+        let Ok(Cli::Diff(args)) = parse_strs(&["diff", "a.json", "b.json"]) else {
+            panic!("expected diff");
+        };
+        assert_eq!(
+            (args.before, args.after),
+            (PathBuf::from("a.json"), PathBuf::from("b.json"))
+        );
+        let Err(e) = parse_strs(&["diff", "a.json"]) else {
+            panic!("expected error");
+        };
+        assert_eq!(e.usage, DIFF_USAGE);
+
+        let Ok(Cli::Tui(args)) = parse_strs(&["--load", "a.json"]) else {
+            panic!("expected tui");
+        };
+        assert_eq!(args.load, Some(PathBuf::from("a.json")));
+    }
+
+    #[test]
+    fn parse_audit_options() {
+        let Ok(Cli::Audit(args)) =
+            parse_strs(&["audit", "-p", "policy.txt", "--load", "a.json", "-q"])
+        else {
+            panic!("expected audit");
+        };
+        assert_eq!(args.policy, Some(PathBuf::from("policy.txt")));
+        assert_eq!(args.load, Some(PathBuf::from("a.json")));
+        assert!(args.quiet);
+        let Err(e) = parse_strs(&["audit", "22"]) else {
+            panic!("expected error");
+        };
+        assert_eq!(e.usage, AUDIT_USAGE);
+    }
+}
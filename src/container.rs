@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+use crate::cgroup;
+use crate::ports::PortEntry;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Runtime {
+    Docker,
+    Podman,
+    /// containerd's CRI plugin, running Kubernetes pods.
+    Containerd,
+}
+
+impl Runtime {
+    /// CLI used to list and control containers of this runtime.
+    pub fn cli(self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+            Runtime::Containerd => "nerdctl",
+        }
+    }
+
+    /// The CLI, set up for this runtime's containers: CRI keeps them in
+    /// containerd's `k8s.io` namespace, not nerdctl's `default` one.
+    fn command(self) -> Command {
+        let mut cmd = Command::new(self.cli());
+        if self == Runtime::Containerd {
+            cmd.args(["--namespace", "k8s.io"]);
+        }
+        cmd.stdin(Stdio::null());
+        cmd
+    }
+
+    pub fn from_cli(cli: &str) -> Option<Runtime> {
+        [Runtime::Docker, Runtime::Podman, Runtime::Containerd]
+            .into_iter()
+            .find(|r| r.cli() == cli)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Container {
+    pub runtime: Runtime,
+    pub id: String,
+    pub name: Option<String>,
+    pub image: Option<String>,
+}
+
+impl Container {
+    pub fn short_id(&self) -> &str {
+        &self.id[..self.id.len().min(12)]
+    }
+
+    /// Name and image for the table, falling back to the short ID.
+    pub fn label(&self) -> String {
+        let name = self.name.as_deref().unwrap_or(self.short_id());
+        match &self.image {
+            Some(image) => format!("{} ({})", name, image),
+            None => name.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContainerAction {
+    Stop,
+    Restart,
+}
+
+impl ContainerAction {
+    pub fn verb(self) -> &'static str {
+        match self {
+            ContainerAction::Stop => "stop",
+            ContainerAction::Restart => "restart",
+        }
+    }
+
+    pub fn past(self) -> &'static str {
+        match self {
+            ContainerAction::Stop => "Stopped",
+            ContainerAction::Restart => "Restarted",
+        }
+    }
+}
+
+pub fn run_action(container: &Container, action: ContainerAction) -> bool {
+    container
+        .runtime
+        .command()
+        .args([action.verb(), &container.id])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// systemd scope names the runtimes create per container.
+const SCOPE_PREFIXES: [(&str, Runtime); 3] = [
+    ("docker-", Runtime::Docker),
+    ("libpod-", Runtime::Podman),
+    ("cri-containerd-", Runtime::Containerd),
+];
+
+/// Container ID from a single cgroup path, for the layouts used by Docker
+/// (cgroupfs and systemd drivers), Podman and containerd/CRI.
+pub fn id_from_cgroup_path(path: &str) -> Option<(Runtime, String)> {
+    for segment in path.split('/').rev() {
+        let scope = segment.strip_suffix(".scope").unwrap_or(segment);
+        for (prefix, runtime) in SCOPE_PREFIXES {
+            if let Some(id) = scope.strip_prefix(prefix)
+                && is_container_id(id)
+            {
+                return Some((runtime, id.to_string()));
+            }
+        }
+    }
+
+    // cgroupfs driver: /docker/<id>, /kubepods/.../pod<uid>/<id>
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    let first = segments.next()?;
+    let last = path.rsplit('/').next()?;
+    if !is_container_id(last) {
+        return None;
+    }
+    match first {
+        "docker" => Some((Runtime::Docker, last.to_string())),
+        "kubepods" | "kubepods.slice" => Some((Runtime::Containerd, last.to_string())),
+        _ => None,
+    }
+}
+
+fn is_container_id(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Runtime whose userland proxy forwards a published port, judged from the
+/// listener's command line.
+pub fn proxy_runtime(command: &str) -> Option<Runtime> {
+    let program = command.split_whitespace().next()?;
+    match program.rsplit('/').next()? {
+        "docker-proxy" => Some(Runtime::Docker),
+        "rootlessport" | "rootlessport-child" => Some(Runtime::Podman),
+        _ => None,
+    }
+}
+
+/// Host port a docker-proxy forwards, from its `-host-port` argument.
+fn proxy_host_port(command: &str) -> Option<u16> {
+    let mut args = command.split_whitespace();
+    while let Some(arg) = args.next() {
+        if arg == "-host-port" {
+            return args.next()?.parse().ok();
+        }
+    }
+    None
+}
+
+/// A row of `<runtime> ps --format '{{.ID}}\t{{.Names}}\t{{.Image}}\t{{.Ports}}'`.
+#[derive(Debug, PartialEq, Eq)]
+struct PsRow {
+    id: String,
+    name: String,
+    image: String,
+    ports: String,
+}
+
+fn parse_ps_line(line: &str) -> Option<PsRow> {
+    let mut fields = line.split('\t');
+    let id = fields.next()?.trim();
+    if id.is_empty() {
+        return None;
+    }
+    Some(PsRow {
+        id: id.to_string(),
+        name: fields.next()?.trim().to_string(),
+        image: fields.next()?.trim().to_string(),
+        ports: fields.next().unwrap_or("").trim().to_string(),
+    })
+}
+
+/// Whether a `Ports` column like `0.0.0.0:8080->80/tcp, :::8080->80/tcp`
+/// publishes `port` on the host over TCP.
+fn publishes(ports: &str, port: u16) -> bool {
+    ports.split(',').any(|mapping| {
+        let Some((host, target)) = mapping.trim().split_once("->") else {
+            return false;
+        };
+        if target.ends_with("/udp") {
+            return false;
+        }
+        let host_ports = host.rsplit(':').next().unwrap_or(host);
+        match host_ports.split_once('-') {
+            Some((lo, hi)) => match (lo.parse::<u16>(), hi.parse::<u16>()) {
+                (Ok(lo), Ok(hi)) => (lo..=hi).contains(&port),
+                _ => false,
+            },
+            None => host_ports.parse() == Ok(port),
+        }
+    })
+}
+
+fn list_containers(runtime: Runtime) -> Vec<PsRow> {
+    let Ok(output) = runtime
+        .command()
+        .args([
+            "ps",
+            "--no-trunc",
+            "--format",
+            "{{.ID}}\t{{.Names}}\t{{.Image}}\t{{.Ports}}",
+        ])
+        .stderr(Stdio::null())
+        .output()
+    else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_ps_line)
+        .collect()
+}
+
+/// Sets `container` on entries owned by a container process or by a
+/// runtime's port-forwarding proxy.
+pub fn annotate(entries: &mut [PortEntry]) {
+    let mut ps_cache: HashMap<Runtime, Vec<PsRow>> = HashMap::new();
+
+    for entry in entries.iter_mut() {
+        let from_cgroup = cgroup::paths(entry.pid)
+            .iter()
+            .find_map(|p| id_from_cgroup_path(p));
+
+        entry.container = if let Some((runtime, id)) = from_cgroup {
+            let rows = ps_cache
+                .entry(runtime)
+                .or_insert_with(|| list_containers(runtime));
+            let row = rows.iter().find(|r| r.id == id);
+            Some(Container {
+                runtime,
+                name: row.map(|r| r.name.clone()),
+                image: row.map(|r| r.image.clone()),
+                id,
+            })
+        } else if let Some(runtime) = proxy_runtime(&entry.command) {
+            let port = proxy_host_port(&entry.command).unwrap_or(entry.port);
+            let rows = ps_cache
+                .entry(runtime)
+                .or_insert_with(|| list_containers(runtime));
+            rows.iter()
+                .find(|r| publishes(&r.ports, port))
+                .map(|r| Container {
+                    runtime,
+                    id: r.id.clone(),
+                    name: Some(r.name.clone()),
+                    image: Some(r.image.clone()),
+                })
+        } else {
+            None
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id_from_fixture(content: &str) -> Option<(Runtime, String)> {
+        cgroup::parse(content)
+            .iter()
+            .find_map(|p| id_from_cgroup_path(p))
+    }
+
+    #[test]
+    fn docker_cgroupfs_driver() {
+        let (runtime, id) =
+            id_from_fixture(include_str!("../fixtures/cgroup/docker-v1.txt")).unwrap();
+        assert_eq!(runtime, Runtime::Docker);
+        assert!(id.starts_with("3f4b2c1d"));
+    }
+
+    #[test]
+    fn docker_systemd_driver() {
+        let (runtime, id) =
+            id_from_fixture(include_str!("../fixtures/cgroup/docker-v2.txt")).unwrap();
+        assert_eq!(runtime, Runtime::Docker);
+        assert!(id.starts_with("8d2f1e0c"));
+    }
+
+    #[test]
+    fn podman_rootless() {
+        let (runtime, id) =
+            id_from_fixture(include_str!("../fixtures/cgroup/podman-rootless.txt")).unwrap();
+        assert_eq!(runtime, Runtime::Podman);
+        assert!(id.starts_with("b7c6d5e4"));
+    }
+
+    #[test]
+    fn containerd_kubernetes() {
+        let (runtime, id) =
+            id_from_fixture(include_str!("../fixtures/cgroup/containerd-k8s.txt")).unwrap();
+        assert_eq!(runtime, Runtime::Containerd);
+        assert!(id.starts_with("0a1b2c3d"));
+        let args: Vec<_> = runtime.command().get_args().map(|a| a.to_owned()).collect();
+        assert_eq!(args, ["--namespace", "k8s.io"]);
+        assert_eq!(Runtime::Docker.command().get_args().count(), 0);
+    }
+
+    #[test]
+    fn host_process_has_no_container() {
+        assert!(id_from_fixture(include_str!("../fixtures/cgroup/host-process.txt")).is_none());
+        assert!(id_from_cgroup_path("/docker").is_none());
+        assert!(id_from_cgroup_path("/system.slice/docker.service").is_none());
+    }
+
+    #[test]
+    fn proxy_detection() {
+        let cmd = "/usr/bin/docker-proxy -proto tcp -host-ip 0.0.0.0 -host-port 8080 -container-ip 172.17.0.2 -container-port 80";
+        assert_eq!(proxy_runtime(cmd), Some(Runtime::Docker));
+        assert_eq!(proxy_host_port(cmd), Some(8080));
+        assert_eq!(proxy_runtime("rootlessport"), Some(Runtime::Podman));
+        assert_eq!(proxy_runtime("/usr/sbin/nginx -g daemon off;"), None);
+        assert_eq!(proxy_host_port("rootlessport"), None);
+    }
+
+    #[test]
+    fn ps_line_parsing() {
+        let row = parse_ps_line("abc123\tweb\tnginx:1.25\t0.0.0.0:8080->80/tcp").unwrap();
+        assert_eq!(row.name, "web");
+        assert_eq!(row.image, "nginx:1.25");
+        assert!(parse_ps_line("").is_none());
+        // containers without published ports
+        let row = parse_ps_line("abc123\tdb\tpostgres:16\t").unwrap();
+        assert_eq!(row.ports, "");
+    }
+
+    #[test]
+    fn published_ports() {
+        let ports = "0.0.0.0:8080->80/tcp, :::8080->80/tcp, 127.0.0.1:9000-9002->9000-9002/tcp, 0.0.0.0:5353->53/udp";
+        assert!(publishes(ports, 8080));
+        assert!(publishes(ports, 9001));
+        assert!(!publishes(ports, 80));
+        assert!(!publishes(ports, 5353)); // udp only
+        assert!(!publishes("5432/tcp", 5432)); // exposed, not published
+    }
+
+    #[test]
+    fn label_prefers_name() {
+        let mut c = Container {
+            runtime: Runtime::Docker,
+            id: "3f4b2c1d9e8a7b6c5d4e".to_string(),
+            name: None,
+            image: None,
+        };
+        assert_eq!(c.label(), "3f4b2c1d9e8a");
+        c.name = Some("web".to_string());
+        c.image = Some("nginx".to_string());
+        assert_eq!(c.label(), "web (nginx)");
+    }
+}
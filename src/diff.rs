@@ -0,0 +1,258 @@
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use crate::cli::DiffArgs;
+use crate::ports::PortEntry;
+use crate::snapshot::Snapshot;
+
+enum Change<'a> {
+    Added(&'a PortEntry),
+    Removed(&'a PortEntry),
+    /// Same socket, owned by something else now.
+    Changed(&'a PortEntry, &'a PortEntry),
+}
+
+impl Change<'_> {
+    fn port(&self) -> u16 {
+        match self {
+            Change::Added(e) | Change::Removed(e) | Change::Changed(e, _) => e.port,
+        }
+    }
+}
+
+/// Exit codes like diff(1): 0 when the snapshots match, 1 when they
+/// differ, 2 when one can't be read.
+pub fn run(args: &DiffArgs) -> ExitCode {
+    let (before, after) = match (Snapshot::load(&args.before), Snapshot::load(&args.after)) {
+        (Ok(before), Ok(after)) => (before, after),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("portit: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let changes = compare(&before.entries, &after.entries);
+
+    let mut out = io::stdout().lock();
+    let _ = writeln!(
+        out,
+        "--- {}  {} {}",
+        args.before.display(),
+        before.host,
+        before.taken
+    );
+    let _ = writeln!(
+        out,
+        "+++ {}  {} {}",
+        args.after.display(),
+        after.host,
+        after.taken
+    );
+    let _ = report(&changes, &mut out);
+    if changes.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
+}
+
+/// Listeners are matched by owner and socket (port, address, namespace)
+/// first, so a socket shared by several processes pairs each one with
+/// itself. What's left is then matched by socket alone, to report a new
+/// owner as a change. Ordered by port.
+fn compare<'a>(before: &'a [PortEntry], after: &'a [PortEntry]) -> Vec<Change<'a>> {
+    let same_listener = |a: &PortEntry, b: &PortEntry| a.pid == b.pid && same_socket(a, b);
+    let mut changes = Vec::new();
+    let mut left_before = Vec::new();
+    for b in before {
+        match after.iter().find(|a| same_listener(a, b)) {
+            None => left_before.push(b),
+            Some(a) if owner_changed(b, a) => changes.push(Change::Changed(b, a)),
+            Some(_) => {}
+        }
+    }
+    let mut left_after: Vec<&PortEntry> = after
+        .iter()
+        .filter(|a| !before.iter().any(|b| same_listener(a, b)))
+        .collect();
+    for b in left_before {
+        match left_after.iter().position(|a| same_socket(a, b)) {
+            Some(i) => changes.push(Change::Changed(b, left_after.remove(i))),
+            None => changes.push(Change::Removed(b)),
+        }
+    }
+    changes.extend(left_after.into_iter().map(Change::Added));
+    changes.sort_by_key(Change::port);
+    changes
+}
+
+fn same_socket(a: &PortEntry, b: &PortEntry) -> bool {
+    a.port == b.port && a.address == b.address && a.netns == b.netns
+}
+
+fn owner_changed(before: &PortEntry, after: &PortEntry) -> bool {
+    before.pid != after.pid
+        || before.process_name != after.process_name
+        || before.command != after.command
+        || container_id(before) != container_id(after)
+}
+
+fn container_id(e: &PortEntry) -> Option<&str> {
+    e.container.as_ref().map(|c| c.id.as_str())
+}
+
+fn report(changes: &[Change], out: &mut impl Write) -> io::Result<()> {
+    for change in changes {
+        match change {
+            Change::Added(e) => writeln!(out, "+ {}", describe(e))?,
+            Change::Removed(e) => writeln!(out, "- {}", describe(e))?,
+            Change::Changed(b, a) => {
+                writeln!(
+                    out,
+                    "~ {:<5} {} on {}",
+                    a.port,
+                    a.process_name,
+                    a.socket_addr()
+                )?;
+                if b.process_name != a.process_name {
+                    writeln!(out, "    process: {} -> {}", b.process_name, a.process_name)?;
+                }
+                if b.pid != a.pid {
+                    writeln!(out, "    pid: {} -> {}", b.pid, a.pid)?;
+                }
+                if b.command != a.command {
+                    writeln!(out, "    command: {}", b.command)?;
+                    writeln!(out, "          -> {}", a.command)?;
+                }
+                if container_id(b) != container_id(a) {
+                    writeln!(out, "    container: {} -> {}", container(b), container(a))?;
+                }
+            }
+        }
+    }
+
+    if changes.is_empty() {
+        return writeln!(out, "No changes");
+    }
+    let count = |f: fn(&Change) -> bool| changes.iter().filter(|c| f(c)).count();
+    writeln!(
+        out,
+        "\n{} added, {} removed, {} changed",
+        count(|c| matches!(c, Change::Added(_))),
+        count(|c| matches!(c, Change::Removed(_))),
+        count(|c| matches!(c, Change::Changed(..)))
+    )
+}
+
+fn describe(e: &PortEntry) -> String {
+    let mut s = format!(
+        "{:<5} {} (PID {}) on {}",
+        e.port,
+        e.process_name,
+        e.pid,
+        e.socket_addr()
+    );
+    if let Some(c) = &e.container {
+        s.push_str(&format!(" [container {}]", c.label()));
+    } else if let Some(unit) = &e.unit {
+        s.push_str(&format!(" [unit {}]", unit.label()));
+    }
+    s
+}
+
+fn container(e: &PortEntry) -> String {
+    match &e.container {
+        Some(c) => format!("{} {}", c.name.as_deref().unwrap_or("-"), c.short_id()),
+        None => "none".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshots() -> (Snapshot, Snapshot) {
+        (
+            Snapshot::parse(include_str!("../fixtures/snapshot/before.json")).unwrap(),
+            Snapshot::parse(include_str!("../fixtures/snapshot/after.json")).unwrap(),
+        )
+    }
+
+    #[test]
+    fn compare_fixtures() {
+        let (before, after) = snapshots();
+        let changes = compare(&before.entries, &after.entries);
+        let summary: Vec<(char, u16)> = changes
+            .iter()
+            .map(|c| match c {
+                Change::Added(e) => ('+', e.port),
+                Change::Removed(e) => ('-', e.port),
+                Change::Changed(_, e) => ('~', e.port),
+            })
+            .collect();
+        assert_eq!(summary, [('-', 5432), ('~', 8080), ('+', 9090)]);
+    }
+
+    #[test]
+    fn report_fixtures() {
+        let (before, after) = snapshots();
+        let mut out = Vec::new();
+        report(&compare(&before.entries, &after.entries), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "- 5432  postgres (PID 3001) on 127.0.0.1:5432");
+        assert_eq!(lines[1], "~ 8080  docker-proxy on *:8080");
+        assert_eq!(lines[2], "    pid: 2001 -> 2077");
+        assert!(
+            lines[3].starts_with("    command: ")
+                && lines[3].ends_with("172.17.0.2 -container-port 80")
+        );
+        assert!(
+            lines[4].starts_with("          -> ")
+                && lines[4].ends_with("172.17.0.3 -container-port 80")
+        );
+        assert_eq!(
+            lines[5],
+            "    container: web 3f4e2a1b9c8d -> web 9a8b7c6d5e4f"
+        );
+        assert_eq!(lines[6], "+ 9090  prometheus (PID 5120) on *:9090");
+        assert_eq!(lines[8], "1 added, 1 removed, 1 changed");
+    }
+
+    #[test]
+    fn shared_socket_pairs_each_owner() {
+        let nginx = |pid| PortEntry {
+            pid,
+            process_name: "nginx".to_string(),
+            port: 80,
+            address: "0.0.0.0".parse().unwrap(),
+            ..Default::default()
+        };
+        let before = [nginx(100), nginx(101), nginx(102)];
+        assert!(compare(&before, &before).is_empty());
+
+        let after = [nginx(100), nginx(102), nginx(103)];
+        let changes = compare(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(changes[0], Change::Changed(b, a) if b.pid == 101 && a.pid == 103));
+
+        let changes = compare(&before, &after[..1]);
+        let removed: Vec<u32> = changes
+            .iter()
+            .map(|c| match c {
+                Change::Removed(e) => e.pid,
+                _ => panic!("expected only removals"),
+            })
+            .collect();
+        assert_eq!(removed, [101, 102]);
+    }
+
+    #[test]
+    fn identical_snapshots() {
+        let (before, _) = snapshots();
+        let changes = compare(&before.entries, &before.entries);
+        assert!(changes.is_empty());
+        let mut out = Vec::new();
+        report(&changes, &mut out).unwrap();
+        assert_eq!(out, b"No changes\n");
+    }
+}
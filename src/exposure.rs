@@ -0,0 +1,319 @@
+use std::net::IpAddr;
+use std::process::Command;
+
+use crate::addr::BindAddr;
+use crate::ports::PortEntry;
+
+/// How far a listener can be reached, narrowest first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Exposure {
+    /// `127.0.0.0/8` and `::1`, this host only.
+    Loopback,
+    /// `169.254.0.0/16` and `fe80::/10`, the directly attached link.
+    LinkLocal,
+    /// RFC 1918 and `fc00::/7` addresses, the local network.
+    Private,
+    /// A specific routable address.
+    Public,
+    /// The wildcard on a host with a routable address, or where the
+    /// interfaces aren't known.
+    All,
+}
+
+impl Exposure {
+    pub const ALL: [Exposure; 5] = [
+        Exposure::Loopback,
+        Exposure::LinkLocal,
+        Exposure::Private,
+        Exposure::Public,
+        Exposure::All,
+    ];
+
+    /// Short badge text for the Exposure column.
+    pub fn badge(self) -> &'static str {
+        match self {
+            Exposure::Loopback => "local",
+            Exposure::LinkLocal => "link",
+            Exposure::Private => "lan",
+            Exposure::Public => "public",
+            Exposure::All => "all",
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Exposure::Loopback => "loopback only",
+            Exposure::LinkLocal => "link-local",
+            Exposure::Private => "private network",
+            Exposure::Public => "public address",
+            Exposure::All => "all interfaces",
+        }
+    }
+}
+
+/// How far wildcard binds reach, from the addresses of the host's
+/// interfaces.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Reach {
+    /// `0.0.0.0`, through the IPv4 addresses.
+    pub v4: Exposure,
+    /// `::`, which is dual-stack, through every address.
+    pub v6: Exposure,
+}
+
+impl Reach {
+    /// `None` without any interface to go by.
+    pub fn of(interfaces: &[Interface]) -> Option<Reach> {
+        if interfaces.is_empty() {
+            return None;
+        }
+        let widest = |address: BindAddr| match reachable_through(&address, interfaces)
+            .iter()
+            .map(|i| classify_ip(&i.addr))
+            .max()
+        {
+            Some(Exposure::Public) => Exposure::All,
+            Some(exposure) => exposure,
+            None => Exposure::Loopback,
+        };
+        Some(Reach {
+            v4: widest(BindAddr::AnyV4),
+            v6: widest(BindAddr::AnyV6),
+        })
+    }
+}
+
+/// Classifies a bind address by the range it falls in, and a wildcard by
+/// what it reaches, or as `All` when that isn't known.
+pub fn classify(address: &BindAddr, reach: Option<Reach>) -> Exposure {
+    match (address, reach) {
+        (BindAddr::AnyV4, Some(reach)) => reach.v4,
+        (BindAddr::AnyV6, Some(reach)) => reach.v6,
+        (BindAddr::AnyV4 | BindAddr::AnyV6, None) => Exposure::All,
+        (BindAddr::Ip { addr, .. }, _) => classify_ip(addr),
+    }
+}
+
+fn classify_ip(addr: &IpAddr) -> Exposure {
+    match addr {
+        IpAddr::V4(ip) if ip.is_loopback() => Exposure::Loopback,
+        IpAddr::V4(ip) if ip.is_link_local() => Exposure::LinkLocal,
+        IpAddr::V4(ip) if ip.is_private() => Exposure::Private,
+        IpAddr::V6(ip) if ip.is_loopback() => Exposure::Loopback,
+        IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80 => Exposure::LinkLocal,
+        IpAddr::V6(ip) if ip.segments()[0] & 0xfe00 == 0xfc00 => Exposure::Private,
+        _ => Exposure::Public,
+    }
+}
+
+/// Sets how far wildcard binds reach for the listeners in portit's own
+/// namespace. Other namespaces have interfaces of their own.
+pub fn annotate(entries: &mut [PortEntry]) {
+    let own = |e: &PortEntry| e.netns.is_none();
+    if !entries.iter().any(|e| own(e) && e.address.is_wildcard()) {
+        return;
+    }
+    let reach = Reach::of(&interfaces());
+    for e in entries.iter_mut().filter(|e| own(e)) {
+        e.reach = reach;
+    }
+}
+
+/// An address assigned to a local network interface.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub addr: IpAddr,
+}
+
+/// Addresses of the host's interfaces, from `ip addr` on Linux or
+/// `ifconfig` elsewhere.
+pub fn interfaces() -> Vec<Interface> {
+    if let Ok(output) = Command::new("ip").args(["-o", "addr", "show"]).output()
+        && output.status.success()
+    {
+        return parse_ip_addr(&String::from_utf8_lossy(&output.stdout));
+    }
+    match Command::new("ifconfig").output() {
+        Ok(output) => parse_ifconfig(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The interfaces a listener on `address` is reachable through: every one
+/// for a wildcard bind, otherwise the one holding the address.
+pub fn reachable_through<'a>(
+    address: &BindAddr,
+    interfaces: &'a [Interface],
+) -> Vec<&'a Interface> {
+    interfaces
+        .iter()
+        .filter(|i| match address {
+            BindAddr::AnyV4 => i.addr.is_ipv4(),
+            BindAddr::AnyV6 => true,
+            BindAddr::Ip { addr, .. } => *addr == i.addr,
+        })
+        .collect()
+}
+
+/// `ip -o addr show`: `2: eth0    inet 10.0.0.5/24 brd ... scope global eth0`.
+fn parse_ip_addr(output: &str) -> Vec<Interface> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if !matches!(fields.get(2), Some(&"inet" | &"inet6")) {
+                return None;
+            }
+            let addr = fields.get(3)?.split('/').next()?;
+            Some(Interface {
+                name: fields[1].trim_end_matches(':').to_string(),
+                addr: addr.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// `ifconfig`: an unindented `en0: flags=...` line per interface, then
+/// indented `inet 192.168.1.5 netmask ...` and `inet6 fe80::1%en0 ...` lines.
+fn parse_ifconfig(output: &str) -> Vec<Interface> {
+    let mut interfaces = Vec::new();
+    let mut name = "";
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            name = line.split(':').next().unwrap_or_default();
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        if !matches!(fields.next(), Some("inet" | "inet6")) {
+            continue;
+        }
+        let addr = fields.next().unwrap_or_default();
+        let addr = addr.trim_start_matches("addr:").split('%').next();
+        if let Some(Ok(addr)) = addr.map(str::parse) {
+            interfaces.push(Interface {
+                name: name.to_string(),
+                addr,
+            });
+        }
+    }
+    interfaces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_addresses() {
+        for (address, exposure) in [
+            ("*", Exposure::All),
+            ("0.0.0.0", Exposure::All),
+            ("::", Exposure::All),
+            ("127.0.0.1", Exposure::Loopback),
+            ("127.0.0.53", Exposure::Loopback),
+            ("::1", Exposure::Loopback),
+            ("::ffff:127.0.0.1", Exposure::Loopback),
+            ("169.254.10.1", Exposure::LinkLocal),
+            ("fe80::1%en0", Exposure::LinkLocal),
+            ("192.168.1.5", Exposure::Private),
+            ("10.0.0.2", Exposure::Private),
+            ("172.17.0.1", Exposure::Private),
+            ("fd12:3456::1", Exposure::Private),
+            ("203.0.113.7", Exposure::Public),
+            ("2001:db8::1", Exposure::Public),
+        ] {
+            assert_eq!(
+                classify(&address.parse().unwrap(), None),
+                exposure,
+                "{}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn wildcards_reach_the_widest_interface() {
+        let reach = Reach::of(&interfaces());
+        assert_eq!(
+            reach,
+            Some(Reach {
+                v4: Exposure::Private,
+                v6: Exposure::Private,
+            })
+        );
+        assert_eq!(classify(&BindAddr::AnyV4, reach), Exposure::Private);
+        assert_eq!(
+            classify(&"127.0.0.1".parse().unwrap(), reach),
+            Exposure::Loopback
+        );
+
+        let public = Interface {
+            name: "eth1".to_string(),
+            addr: "2001:db8::5".parse().unwrap(),
+        };
+        let reach = Reach::of(&[interfaces().remove(0), public]).unwrap();
+        assert_eq!((reach.v4, reach.v6), (Exposure::Loopback, Exposure::All));
+        assert_eq!(Reach::of(&[]), None);
+    }
+
+    fn interfaces() -> Vec<Interface> {
+        parse_ip_addr(include_str!("../fixtures/exposure/ip-addr.txt"))
+    }
+
+    #[test]
+    fn parse_ip_addr_fixture() {
+        let interfaces = interfaces();
+        let names: Vec<(&str, String)> = interfaces
+            .iter()
+            .map(|i| (i.name.as_str(), i.addr.to_string()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("lo", "127.0.0.1".to_string()),
+                ("eth0", "192.168.1.5".to_string()),
+                ("docker0", "172.17.0.1".to_string()),
+                ("lo", "::1".to_string()),
+                ("eth0", "fe80::a00:27ff:fe4e:66a1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ifconfig_fixture() {
+        let interfaces = parse_ifconfig(include_str!("../fixtures/exposure/ifconfig.txt"));
+        let names: Vec<(&str, String)> = interfaces
+            .iter()
+            .map(|i| (i.name.as_str(), i.addr.to_string()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("lo0", "127.0.0.1".to_string()),
+                ("lo0", "::1".to_string()),
+                ("lo0", "fe80::1".to_string()),
+                ("en0", "fe80::1c2b:3a4f:9e8d:7c6b".to_string()),
+                ("en0", "192.168.1.23".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reachable_interfaces() {
+        let interfaces = interfaces();
+        let names = |address: &str| -> Vec<String> {
+            reachable_through(&address.parse().unwrap(), &interfaces)
+                .iter()
+                .map(|i| format!("{} {}", i.name, i.addr))
+                .collect()
+        };
+        assert_eq!(
+            names("0.0.0.0"),
+            ["lo 127.0.0.1", "eth0 192.168.1.5", "docker0 172.17.0.1"]
+        );
+        assert_eq!(names("::").len(), 5);
+        assert_eq!(names("192.168.1.5"), ["eth0 192.168.1.5"]);
+        assert!(names("10.9.9.9").is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener, UdpSocket};
+use std::ops::RangeInclusive;
+use std::process::{Command, ExitCode};
+
+use crate::cli::FreeArgs;
+use crate::ports::{self, ScanOptions};
+use crate::service;
+
+/// Used when the OS doesn't say, per RFC 6335.
+const DEFAULT_EPHEMERAL: RangeInclusive<u16> = 49152..=65535;
+
+/// Prints `args.count` free ports, one per line. Exit codes: 0 when enough
+/// were found, 1 when the range ran out.
+pub fn run(args: &FreeArgs) -> ExitCode {
+    let listening: HashSet<u16> = if args.udp {
+        HashSet::new()
+    } else {
+        ports::list_listening_ports(&ScanOptions::default())
+            .iter()
+            .map(|e| e.port)
+            .collect()
+    };
+    let ephemeral = args.avoid_ephemeral.then(ephemeral_range);
+
+    let candidates = candidates(args, ephemeral.as_ref(), random_offset());
+    let found: Vec<u16> = candidates
+        .into_iter()
+        .filter(|p| !listening.contains(p) && can_bind(*p, args.udp))
+        .take(args.count)
+        .collect();
+
+    for port in &found {
+        println!("{}", port);
+    }
+    if found.len() < args.count {
+        eprintln!(
+            "portit: only {} of {} free ports in {}-{}",
+            found.len(),
+            args.count,
+            args.range.start(),
+            args.range.end()
+        );
+        return ExitCode::from(1);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Ports of the range worth trying, starting at `offset` and wrapping so
+/// concurrent callers don't all race for the same first port.
+fn candidates(args: &FreeArgs, ephemeral: Option<&RangeInclusive<u16>>, offset: u64) -> Vec<u16> {
+    let ports: Vec<u16> = args
+        .range
+        .clone()
+        .filter(|p| !(args.avoid_well_known && is_well_known(*p)))
+        .filter(|p| !ephemeral.is_some_and(|r| r.contains(p)))
+        .collect();
+    if ports.is_empty() {
+        return ports;
+    }
+    let start = (offset % ports.len() as u64) as usize;
+    ports[start..]
+        .iter()
+        .chain(&ports[..start])
+        .copied()
+        .collect()
+}
+
+/// System ports, and registered ones common enough to have a name.
+fn is_well_known(port: u16) -> bool {
+    port < 1024 || service::well_known(port).is_some()
+}
+
+fn random_offset() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// A port counts as free only if both wildcard binds succeed, which also
+/// catches listeners lsof can't see. A missing IPv6 stack isn't a conflict.
+fn can_bind(port: u16, udp: bool) -> bool {
+    let v4 = if udp {
+        UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).map(drop)
+    } else {
+        TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).map(drop)
+    };
+    if v4.is_err() {
+        return false;
+    }
+    let v6 = if udp {
+        UdpSocket::bind((Ipv6Addr::UNSPECIFIED, port)).map(drop)
+    } else {
+        TcpListener::bind((Ipv6Addr::UNSPECIFIED, port)).map(drop)
+    };
+    !matches!(v6, Err(e) if e.kind() == ErrorKind::AddrInUse)
+}
+
+/// The range the OS hands out for outgoing connections.
+fn ephemeral_range() -> RangeInclusive<u16> {
+    if let Ok(content) = fs::read_to_string("/proc/sys/net/ipv4/ip_local_port_range")
+        && let Some(range) = parse_port_range(&content)
+    {
+        return range;
+    }
+    let sysctl = Command::new("sysctl")
+        .args([
+            "-n",
+            "net.inet.ip.portrange.first",
+            "net.inet.ip.portrange.last",
+        ])
+        .output();
+    if let Ok(output) = sysctl
+        && output.status.success()
+        && let Some(range) = parse_port_range(&String::from_utf8_lossy(&output.stdout))
+    {
+        return range;
+    }
+    DEFAULT_EPHEMERAL
+}
+
+/// Two whitespace-separated ports, as in ip_local_port_range.
+fn parse_port_range(s: &str) -> Option<RangeInclusive<u16>> {
+    let mut parts = s.split_whitespace().map(str::parse::<u16>);
+    let (first, last) = (parts.next()?.ok()?, parts.next()?.ok()?);
+    (first <= last).then_some(first..=last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(range: RangeInclusive<u16>) -> FreeArgs {
+        FreeArgs {
+            count: 1,
+            udp: false,
+            range,
+            avoid_well_known: false,
+            avoid_ephemeral: false,
+        }
+    }
+
+    #[test]
+    fn candidates_wrap_around_offset() {
+        assert_eq!(candidates(&args(10..=14), None, 0), [10, 11, 12, 13, 14]);
+        assert_eq!(candidates(&args(10..=14), None, 7), [12, 13, 14, 10, 11]);
+    }
+
+    #[test]
+    fn candidates_skip_avoided_ranges() {
+        let mut a = args(1020..=1030);
+        a.avoid_well_known = true;
+        assert_eq!(candidates(&a, None, 0).first(), Some(&1024));
+
+        let mut a = args(6370..=6380);
+        a.avoid_well_known = true;
+        assert!(!candidates(&a, None, 0).contains(&6379));
+
+        let eph = 6375..=6380;
+        assert_eq!(
+            candidates(&args(6370..=6380), Some(&eph), 0),
+            [6370, 6371, 6372, 6373, 6374]
+        );
+        assert!(candidates(&args(6376..=6378), Some(&eph), 3).is_empty());
+    }
+
+    #[test]
+    fn parse_ip_local_port_range() {
+        assert_eq!(parse_port_range("32768\t60999\n"), Some(32768..=60999));
+        assert_eq!(parse_port_range("49152\n65535\n"), Some(49152..=65535));
+        assert_eq!(parse_port_range("60999 32768"), None);
+        assert_eq!(parse_port_range(""), None);
+    }
+
+    #[test]
+    fn bound_port_is_not_free() {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(!can_bind(port, false));
+        drop(listener);
+        assert!(can_bind(port, false));
+
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        assert!(!can_bind(port, true));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ports::PortEntry;
+
+/// Older records are dropped from memory past this; the log file keeps them.
+const MAX_RECORDS: usize = 1000;
+
+pub enum Event {
+    Opened(PortEntry),
+    Closed(PortEntry),
+    Signal {
+        pid: u32,
+        name: String,
+        signal: &'static str,
+        result: Result<(), String>,
+    },
+    /// A `docker stop`, `systemctl restart` and the like.
+    Command {
+        command: String,
+        result: Result<(), String>,
+    },
+    LogFailed(String),
+}
+
+impl Event {
+    pub fn describe(&self) -> String {
+        match self {
+            Event::Opened(e) => format!("opened  {}", listener(e)),
+            Event::Closed(e) => format!("closed  {}", listener(e)),
+            Event::Signal {
+                pid,
+                name,
+                signal,
+                result,
+            } => match result {
+                Ok(()) => format!("{} {} (PID {}): sent", signal, name, pid),
+                Err(e) => format!("{} {} (PID {}): failed: {}", signal, name, pid, e),
+            },
+            Event::Command { command, result } => match result {
+                Ok(()) => format!("{}: ok", command),
+                Err(e) => format!("{}: failed: {}", command, e),
+            },
+            Event::LogFailed(e) => format!("log file write failed, logging stopped: {}", e),
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        match self {
+            Event::Signal { result, .. } | Event::Command { result, .. } => result.is_err(),
+            Event::LogFailed(_) => true,
+            Event::Opened(_) | Event::Closed(_) => false,
+        }
+    }
+}
+
+fn listener(e: &PortEntry) -> String {
+    let mut s = format!(
+        "{} {} (PID {}) on {}",
+        e.port,
+        e.process_name,
+        e.pid,
+        e.socket_addr()
+    );
+    if let Some(ns) = &e.netns {
+        s.push_str(&format!(" in netns {}", ns));
+    }
+    s
+}
+
+pub struct Record {
+    pub time: SystemTime,
+    pub event: Event,
+}
+
+impl Record {
+    pub fn line(&self) -> String {
+        format!("{}  {}", format_time(self.time), self.event.describe())
+    }
+}
+
+/// Listeners seen coming and going, and everything portit stopped.
+#[derive(Default)]
+pub struct History {
+    records: VecDeque<Record>,
+    log: Option<File>,
+}
+
+impl History {
+    /// Also appends every record to `log`, one line each.
+    pub fn with_log(log: File) -> History {
+        History {
+            records: VecDeque::new(),
+            log: Some(log),
+        }
+    }
+
+    pub fn record(&mut self, event: Event) {
+        self.push(SystemTime::now(), event);
+    }
+
+    fn push(&mut self, time: SystemTime, event: Event) {
+        let record = Record { time, event };
+        if let Some(log) = &mut self.log
+            && let Err(e) = writeln!(log, "{}", record.line())
+        {
+            self.log = None;
+            self.push(time, Event::LogFailed(e.to_string()));
+        }
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Records listeners that went away or appeared between two scans.
+    pub fn observe(&mut self, old: &[PortEntry], new: &[PortEntry]) {
+        let now = SystemTime::now();
+        for event in diff(old, new) {
+            self.push(now, event);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn newest_first(&self) -> impl Iterator<Item = &Record> {
+        self.records.iter().rev()
+    }
+}
+
+/// Listeners that went away, then those that appeared, between two scans.
+pub fn diff(old: &[PortEntry], new: &[PortEntry]) -> Vec<Event> {
+    let closed = old
+        .iter()
+        .filter(|e| !new.iter().any(|n| same_listener(e, n)))
+        .map(|e| Event::Closed(e.clone()));
+    let opened = new
+        .iter()
+        .filter(|e| !old.iter().any(|o| same_listener(e, o)))
+        .map(|e| Event::Opened(e.clone()));
+    closed.chain(opened).collect()
+}
+
+fn same_listener(a: &PortEntry, b: &PortEntry) -> bool {
+    a.pid == b.pid && a.port == b.port && a.address == b.address && a.netns == b.netns
+}
+
+pub fn open_log(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// RFC 3339 in UTC, since std has no notion of the local time zone.
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date, after Howard
+/// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    fn entry(pid: u32, port: u16) -> PortEntry {
+        PortEntry {
+            pid,
+            process_name: "node".to_string(),
+            port,
+            address: "*".parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(format_time(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_time(at(1_700_000_000)), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn observe_reports_changes_only() {
+        let mut history = History::default();
+        let old = [entry(10, 3000), entry(11, 5432)];
+        let new = [entry(11, 5432), entry(12, 3000)];
+        history.observe(&old, &new);
+
+        let lines: Vec<String> = history.newest_first().map(|r| r.event.describe()).collect();
+        assert_eq!(
+            lines,
+            [
+                "opened  3000 node (PID 12) on *:3000",
+                "closed  3000 node (PID 10) on *:3000",
+            ]
+        );
+
+        history.observe(&new, &new);
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn keeps_newest_records() {
+        let mut history = History::default();
+        for port in 0..MAX_RECORDS as u16 + 5 {
+            history.push(at(0), Event::Opened(entry(1, port)));
+        }
+        assert_eq!(history.len(), MAX_RECORDS);
+        let newest = history.newest_first().next().unwrap();
+        assert!(matches!(&newest.event, Event::Opened(e) if e.port == MAX_RECORDS as u16 + 4));
+    }
+
+    #[test]
+    fn appends_to_log_file() {
+        let path = std::env::temp_dir().join(format!("portit-history-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut history = History::with_log(open_log(&path).unwrap());
+        history.push(
+            at(1_700_000_000),
+            Event::Signal {
+                pid: 42,
+                name: "node".to_string(),
+                signal: "SIGTERM",
+                result: Ok(()),
+            },
+        );
+        history.push(
+            at(1_700_000_001),
+            Event::Command {
+                command: "systemctl stop nginx.service".to_string(),
+                result: Err("Access denied".to_string()),
+            },
+        );
+        let content = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            content,
+            "2023-11-14T22:13:20Z  SIGTERM node (PID 42): sent\n\
+             2023-11-14T22:13:21Z  systemctl stop nginx.service: failed: Access denied\n"
+        );
+        assert!(history.newest_first().next().unwrap().event.failed());
+    }
+}
@@ -1,27 +1,146 @@
+mod addr;
 mod app;
+mod cgroup;
+mod check;
+mod cli;
+mod clipboard;
+mod container;
+mod diff;
+mod exposure;
+mod free;
+mod history;
+mod json;
+mod monitor;
+mod netns;
+mod policy;
 mod ports;
+mod probe;
+mod procfs;
+mod remote;
+mod service;
+mod snapshot;
+#[cfg(target_os = "linux")]
+mod sockdiag;
+mod systemd;
 mod ui;
+mod usage;
+mod wait;
 
 use std::io;
+use std::process::ExitCode;
 
 use crossterm::{cursor, execute, terminal};
 
-fn main() -> io::Result<()> {
+fn main() -> ExitCode {
+    let cli = cli::parse(std::env::args().skip(1));
+    if let Ok(cli) = &cli
+        && let Some(scope) = cli.netns()
+        && let Err(e) = netns::check(scope)
+    {
+        eprintln!("portit: {}", e);
+        return ExitCode::from(2);
+    }
+    let args = match cli {
+        Ok(cli::Cli::Tui(args)) => args,
+        Ok(cli::Cli::Wait(args)) => return wait::run(&args),
+        Ok(cli::Cli::Check(args)) => return check::run(&args),
+        Ok(cli::Cli::Free(args)) => return free::run(&args),
+        Ok(cli::Cli::Monitor(args)) => return monitor::run(&args),
+        Ok(cli::Cli::Snapshot(args)) => return snapshot::run(&args),
+        Ok(cli::Cli::Diff(args)) => return diff::run(&args),
+        Ok(cli::Cli::Audit(args)) => return policy::run(&args),
+        Ok(cli::Cli::Help(usage)) => {
+            println!("{}", usage);
+            return ExitCode::SUCCESS;
+        }
+        Ok(cli::Cli::Version) => {
+            println!("portit {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("portit: {}\n\n{}", e.message, e.usage);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run_tui(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("portit: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_tui(args: cli::Args) -> io::Result<()> {
+    let log = match &args.log {
+        Some(path) => Some(
+            history::open_log(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+        ),
+        None => None,
+    };
+
+    // a broken default policy shouldn't keep the TUI from starting
+    let mut policy_warning = None;
+    let policy = match &args.policy {
+        Some(path) => Some(policy::Policy::load(path).map_err(io::Error::other)?),
+        None => match policy::default_path().map(|path| policy::Policy::load(&path)) {
+            Some(Ok(policy)) => Some(policy),
+            Some(Err(e)) => {
+                policy_warning = Some(format!("Policy ignored, {}", e));
+                None
+            }
+            None => None,
+        },
+    };
+    let snapshot = match &args.load {
+        Some(path) => Some(snapshot::Snapshot::load(path).map_err(io::Error::other)?),
+        None => None,
+    };
+
+    let remote_host = match &args.host {
+        Some(host) => Some(remote::connect(host)?),
+        None => None,
+    };
+
     app::App::install_panic_hook();
     terminal::enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, cursor::Hide)?;
 
-    let mut app = app::App::new();
+    let scan = ports::ScanOptions {
+        netns: args.netns,
+        backend: args.backend,
+        host: args.host,
+    };
+    let mut app = match (snapshot, &args.load) {
+        (Some(snapshot), Some(path)) => {
+            app::App::from_snapshot(path.display().to_string(), snapshot)
+        }
+        _ => {
+            let mut app = app::App::new(scan);
+            if args.grab_banners {
+                app.grab_banners();
+            }
+            app
+        }
+    };
+    app.remote_host = remote_host;
+    app.fullscreen = args.fullscreen;
+    app.clipboard_fallback = args.clipboard_fallback;
+    if let Some(policy) = policy {
+        app.set_policy(policy);
+    }
+    app.status_msg = policy_warning;
+    if let Some(log) = log {
+        app.history = history::History::with_log(log);
+    }
     let result = app.run(&mut stdout);
 
-    // move cursor below our area so shell prompt is clean
-    if app.height > 0 {
-        let _ = execute!(stdout, cursor::MoveTo(0, app.start_row + app.height as u16));
-    }
+    app.restore_screen(&mut stdout);
     let _ = execute!(stdout, cursor::Show);
     let _ = terminal::disable_raw_mode();
-    println!();
 
     result
 }
@@ -0,0 +1,385 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process::{Command, ExitCode, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::cli::{self, MonitorArgs};
+use crate::history::{self, Event};
+use crate::json;
+use crate::ports::{self, PortEntry, ScanOptions};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runs until killed, reporting listeners that appear or go away. Exits 2
+/// only if the allowlist can't be read.
+pub fn run(args: &MonitorArgs) -> ExitCode {
+    let allowlist = match &args.allowlist {
+        Some(path) => match Allowlist::load(path) {
+            Ok(allowlist) => allowlist,
+            Err(e) => {
+                eprintln!("portit: {}", e);
+                return ExitCode::from(2);
+            }
+        },
+        None => Allowlist::default(),
+    };
+    let scan = ScanOptions {
+        netns: args.netns.clone(),
+        ..Default::default()
+    };
+
+    let mut monitor = Monitor {
+        args,
+        allowlist,
+        notify_failed: false,
+    };
+    let mut previous = ports::list_listening_ports(&scan);
+    eprintln!(
+        "portit: watching {} listeners, checking every {:?}",
+        previous.len(),
+        args.interval
+    );
+    let mut out = io::stdout();
+    loop {
+        thread::sleep(args.interval);
+        let current = ports::list_listening_ports(&scan);
+        monitor.step(&previous, &current, &mut out);
+        previous = current;
+    }
+}
+
+struct Monitor<'a> {
+    args: &'a MonitorArgs,
+    allowlist: Allowlist,
+    notify_failed: bool,
+}
+
+impl Monitor<'_> {
+    fn step(&mut self, previous: &[PortEntry], current: &[PortEntry], out: &mut impl Write) {
+        let now = SystemTime::now();
+        for event in history::diff(previous, current) {
+            let (opened, entry) = match &event {
+                Event::Opened(e) => (true, e),
+                Event::Closed(e) => (false, e),
+                _ => continue,
+            };
+            if self.allowlist.allows(entry) {
+                continue;
+            }
+            let line = json_line(now, opened, entry);
+            if !self.args.quiet {
+                let _ = writeln!(out, "{}", line);
+                let _ = out.flush();
+            }
+            if self.args.notify && !self.notify_failed && !notify(opened, entry) {
+                eprintln!("portit: notify-send failed, desktop notifications disabled");
+                self.notify_failed = true;
+            }
+            if let Some(webhook) = &self.args.webhook
+                && let Err(e) = webhook.post(&line)
+            {
+                eprintln!("portit: webhook {}: {}", webhook.url, e);
+            }
+        }
+    }
+}
+
+/// One event as a JSON object, leaving out fields the listener doesn't have.
+fn json_line(time: SystemTime, opened: bool, e: &PortEntry) -> String {
+    let mut fields = vec![
+        ("time", json::string(&history::format_time(time))),
+        (
+            "event",
+            json::string(if opened { "opened" } else { "closed" }),
+        ),
+        ("port", e.port.to_string()),
+        ("address", json::string(&e.address.to_string())),
+        ("pid", e.pid.to_string()),
+        ("process", json::string(&e.process_name)),
+    ];
+    if !e.command.is_empty() {
+        fields.push(("command", json::string(&e.command)));
+    }
+    if let Some(service) = &e.service {
+        fields.push(("service", json::string(service)));
+    }
+    if let Some(container) = &e.container {
+        fields.push(("container", json::string(&container.label())));
+    }
+    if let Some(unit) = &e.unit {
+        fields.push(("unit", json::string(&unit.name)));
+    }
+    if let Some(netns) = &e.netns {
+        fields.push(("netns", json::string(netns)));
+    }
+    json::object(&fields)
+}
+
+fn notify(opened: bool, e: &PortEntry) -> bool {
+    let summary = if opened {
+        format!("New listener on port {}", e.port)
+    } else {
+        format!("Listener on port {} closed", e.port)
+    };
+    let body = format!("{} (PID {}) on {}", e.process_name, e.pid, e.socket_addr());
+    Command::new("notify-send")
+        .args(["-a", "portit", &summary, &body])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// A plain `http://` endpoint, typically a local relay into chat or a
+/// SIEM. TLS would need a dependency.
+#[derive(Debug)]
+pub struct Webhook {
+    pub url: String,
+    authority: String,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Webhook {
+    pub fn parse(url: &str) -> Result<Webhook, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("webhook must be an http:// URL: {}", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !authority.ends_with(']') => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid webhook port: {}", url))?,
+            ),
+            _ => (authority, 80),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("webhook URL has no host: {}", url));
+        }
+        Ok(Webhook {
+            url: url.to_string(),
+            authority: authority.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// POSTs `body` as JSON, succeeding on any 2xx reply.
+    fn post(&self, body: &str) -> Result<(), String> {
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or("host has no address")?;
+        let mut stream =
+            TcpStream::connect_timeout(&addr, WEBHOOK_TIMEOUT).map_err(|e| e.to_string())?;
+        let _ = stream.set_read_timeout(Some(WEBHOOK_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(WEBHOOK_TIMEOUT));
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: portit/{}\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.path,
+            self.authority,
+            env!("CARGO_PKG_VERSION"),
+            body.len(),
+            body
+        )
+        .map_err(|e| e.to_string())?;
+
+        let mut reply = [0u8; 64];
+        let n = stream.read(&mut reply).map_err(|e| e.to_string())?;
+        let reply = String::from_utf8_lossy(&reply[..n]);
+        let status = reply.lines().next().unwrap_or_default();
+        match status.split_whitespace().nth(1) {
+            Some(code) if code.starts_with('2') => Ok(()),
+            _ => Err(format!("unexpected reply: {}", status)),
+        }
+    }
+}
+
+/// Listeners not worth an event, one rule per line: `PROCESS`, `PORT`,
+/// `FROM-TO` or `PROCESS:PORTS`, with `*` for any process.
+#[derive(Debug, Default)]
+struct Allowlist {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Rule {
+    process: Option<String>,
+    ports: Option<RangeInclusive<u16>>,
+}
+
+impl Allowlist {
+    fn load(path: &Path) -> Result<Allowlist, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Allowlist::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(content: &str) -> Result<Allowlist, String> {
+        let mut rules = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            rules.push(parse_rule(line).map_err(|e| format!("line {}: {}", i + 1, e))?);
+        }
+        Ok(Allowlist { rules })
+    }
+
+    fn allows(&self, e: &PortEntry) -> bool {
+        self.rules.iter().any(|rule| {
+            rule.process.as_ref().is_none_or(|p| *p == e.process_name)
+                && rule.ports.as_ref().is_none_or(|r| r.contains(&e.port))
+        })
+    }
+}
+
+fn parse_rule(s: &str) -> Result<Rule, String> {
+    let (process, ports) = match s.rsplit_once(':') {
+        Some((process, ports)) => (process, Some(ports)),
+        None if s.chars().all(|c| c.is_ascii_digit() || c == '-') => ("*", Some(s)),
+        None => (s, None),
+    };
+    let ports = match ports {
+        Some(spec) => {
+            let ports = cli::parse_port_spec(spec)?;
+            Some(ports[0]..=ports[ports.len() - 1])
+        }
+        None => None,
+    };
+    Ok(Rule {
+        process: (process != "*").then(|| process.to_string()),
+        ports,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::UNIX_EPOCH;
+
+    fn entry(name: &str, port: u16) -> PortEntry {
+        PortEntry {
+            pid: 42,
+            process_name: name.to_string(),
+            port,
+            address: "*".parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn args() -> MonitorArgs {
+        MonitorArgs {
+            interval: Duration::from_secs(2),
+            allowlist: None,
+            notify: false,
+            webhook: None,
+            quiet: false,
+            netns: None,
+        }
+    }
+
+    #[test]
+    fn allowlist_rules() {
+        let allowlist =
+            Allowlist::parse(include_str!("../fixtures/monitor/allowlist.txt")).unwrap();
+        assert_eq!(allowlist.rules.len(), 4);
+        assert!(allowlist.allows(&entry("sshd", 22)));
+        assert!(allowlist.allows(&entry("sshd", 2222)));
+        assert!(allowlist.allows(&entry("postgres", 5432)));
+        assert!(!allowlist.allows(&entry("postgres", 5433)));
+        assert!(allowlist.allows(&entry("node", 3001)));
+        assert!(allowlist.allows(&entry("python3", 8080)));
+        assert!(!allowlist.allows(&entry("nc", 4444)));
+    }
+
+    #[test]
+    fn allowlist_errors_name_the_line() {
+        let err = Allowlist::parse("sshd\nnode:99999\n").unwrap_err();
+        assert_eq!(err, "line 2: invalid port: 99999");
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut e = entry("python3", 8000);
+        e.command = "python3 -m http.server \"8000\"".to_string();
+        e.netns = Some("sandbox".to_string());
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            json_line(time, true, &e),
+            r#"{"time":"2023-11-14T22:13:20Z","event":"opened","port":8000,"address":"*","pid":42,"process":"python3","command":"python3 -m http.server \"8000\"","netns":"sandbox"}"#
+        );
+    }
+
+    #[test]
+    fn step_skips_allowed_listeners() {
+        let args = args();
+        let mut monitor = Monitor {
+            args: &args,
+            allowlist: Allowlist::parse("sshd").unwrap(),
+            notify_failed: false,
+        };
+        let mut out = Vec::new();
+        let previous = [entry("sshd", 22), entry("node", 3000)];
+        let current = [entry("sshd", 2222), entry("nc", 4444)];
+        monitor.step(&previous, &current, &mut out);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""event":"closed","port":3000"#));
+        assert!(lines[1].contains(r#""event":"opened","port":4444"#));
+    }
+
+    #[test]
+    fn webhook_urls() {
+        let hook = Webhook::parse("http://127.0.0.1:9000/hooks/portit").unwrap();
+        assert_eq!((hook.host.as_str(), hook.port), ("127.0.0.1", 9000));
+        assert_eq!(hook.path, "/hooks/portit");
+        let hook = Webhook::parse("http://[::1]").unwrap();
+        assert_eq!(
+            (hook.host.as_str(), hook.port, hook.path.as_str()),
+            ("::1", 80, "/")
+        );
+        assert!(Webhook::parse("https://example.com/").is_err());
+        assert!(Webhook::parse("http://:80/").is_err());
+    }
+
+    #[test]
+    fn webhook_posts_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !String::from_utf8_lossy(&request).ends_with("{\"a\":1}") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let hook = Webhook::parse(&format!("http://127.0.0.1:{}/events", port)).unwrap();
+        assert_eq!(hook.post("{\"a\":1}"), Ok(()));
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /events HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Length: 7\r\n"));
+    }
+}
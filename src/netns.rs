@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::addr::BindAddr;
+use crate::ports::{PortEntry, Socket, SocketStats};
+use crate::procfs::{self, TcpSocket};
+
+/// Where `ip netns add` bind-mounts named namespaces.
+const NAMED_DIR: &str = "/run/netns";
+/// Name given to the namespace portit itself runs in.
+pub const HOST: &str = "host";
+
+/// Which network namespaces to scan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetnsScope {
+    All,
+    Named(String),
+}
+
+impl NetnsScope {
+    pub fn parse(s: &str) -> NetnsScope {
+        if s == "all" {
+            NetnsScope::All
+        } else {
+            NetnsScope::Named(s.to_string())
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            NetnsScope::All => "all",
+            NetnsScope::Named(name) => name,
+        }
+    }
+
+    /// Whether portit's own namespace is one of those scanned.
+    pub fn includes_own(&self) -> bool {
+        match self {
+            NetnsScope::All => true,
+            NetnsScope::Named(name) => name == HOST,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Namespace {
+    /// `host`, the `ip netns` name, or `net:[<inode>]` for unnamed ones.
+    pub name: String,
+    pub inode: u64,
+    /// A process inside, whose /proc/<pid>/net shows the namespace.
+    pub pid: Option<u32>,
+    pub path: Option<PathBuf>,
+}
+
+/// Host namespace first, then named ones, then those only reachable
+/// through a process (containers, sandboxes).
+pub fn list() -> Vec<Namespace> {
+    list_with(&procfs::pids())
+}
+
+/// Fails for a name no namespace has, which would otherwise scan as an
+/// empty one.
+pub fn check(scope: &NetnsScope) -> Result<(), String> {
+    let NetnsScope::Named(name) = scope else {
+        return Ok(());
+    };
+    let names: Vec<String> = list().into_iter().map(|ns| ns.name).collect();
+    if names.contains(name) {
+        return Ok(());
+    }
+    Err(format!(
+        "no network namespace named {} (there are: {})",
+        name,
+        names.join(", ")
+    ))
+}
+
+fn list_with(pids: &[u32]) -> Vec<Namespace> {
+    let mut first_pid: HashMap<u64, u32> = HashMap::new();
+    let mut order = Vec::new();
+    for &pid in pids {
+        if let Some(inode) = ns_inode(pid)
+            && !first_pid.contains_key(&inode)
+        {
+            first_pid.insert(inode, pid);
+            order.push(inode);
+        }
+    }
+
+    let mut namespaces = Vec::new();
+    let own_pid = std::process::id();
+    if let Some(inode) = ns_inode(own_pid) {
+        namespaces.push(Namespace {
+            name: HOST.to_string(),
+            inode,
+            pid: Some(own_pid),
+            path: None,
+        });
+    }
+
+    let mut named: Vec<(String, PathBuf, u64)> = fs::read_dir(NAMED_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| {
+            let inode = e.metadata().ok()?.ino();
+            Some((
+                e.file_name().to_string_lossy().into_owned(),
+                e.path(),
+                inode,
+            ))
+        })
+        .collect();
+    named.sort();
+    for (name, path, inode) in named {
+        if namespaces.iter().any(|ns| ns.inode == inode) {
+            continue;
+        }
+        namespaces.push(Namespace {
+            name,
+            inode,
+            pid: first_pid.get(&inode).copied(),
+            path: Some(path),
+        });
+    }
+
+    for inode in order {
+        if namespaces.iter().any(|ns| ns.inode == inode) {
+            continue;
+        }
+        namespaces.push(Namespace {
+            name: format!("net:[{}]", inode),
+            inode,
+            pid: first_pid.get(&inode).copied(),
+            path: None,
+        });
+    }
+    namespaces
+}
+
+fn ns_inode(pid: u32) -> Option<u64> {
+    let link = fs::read_link(format!("/proc/{}/ns/net", pid)).ok()?;
+    parse_ns_link(&link.to_string_lossy())
+}
+
+/// Inode from a namespace link target like `net:[4026531840]`.
+fn parse_ns_link(target: &str) -> Option<u64> {
+    target
+        .strip_prefix("net:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+/// Listeners in the namespaces `scope` selects, tagged with the
+/// namespace's name. Reads procfs directly since lsof only sees its own
+/// namespace.
+pub fn listening_ports(scope: &NetnsScope) -> Vec<PortEntry> {
+    let pids = procfs::pids();
+    let owners = procfs::socket_owners(&pids);
+
+    let mut entries = Vec::new();
+    for ns in list_with(&pids) {
+        if let NetnsScope::Named(name) = scope
+            && *name != ns.name
+        {
+            continue;
+        }
+        let sockets = match (ns.pid, &ns.path) {
+            (Some(pid), _) => procfs::listening(pid),
+            (None, Some(path)) => listening_via_nsenter(path),
+            (None, None) => continue,
+        };
+        for socket in sockets {
+            for &(pid, fd) in owners.get(&socket.inode).into_iter().flatten() {
+                entries.push(PortEntry {
+                    pid,
+                    process_name: procfs::comm(pid).unwrap_or_default(),
+                    port: socket.port,
+                    address: BindAddr::from(socket.addr),
+                    uid: Some(socket.uid),
+                    sockets: vec![Socket {
+                        address: BindAddr::from(socket.addr),
+                        fd: Some(fd),
+                        stats: Some(SocketStats {
+                            queued: socket.rx_queue,
+                            ..Default::default()
+                        }),
+                    }],
+                    netns: Some(ns.name.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Reads the socket tables of a named namespace no process has entered
+/// yet, e.g. one whose listener fd was passed out of it.
+fn listening_via_nsenter(path: &Path) -> Vec<TcpSocket> {
+    let Ok(output) = Command::new("nsenter")
+        .arg(format!("--net={}", path.display()))
+        .args(["cat", "/proc/self/net/tcp", "/proc/self/net/tcp6"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+    else {
+        return Vec::new();
+    };
+    procfs::parse_net_tcp(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .filter(|s| s.state == procfs::TCP_LISTEN)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scope_parsing() {
+        assert_eq!(NetnsScope::parse("all"), NetnsScope::All);
+        assert_eq!(
+            NetnsScope::parse("sandbox"),
+            NetnsScope::Named("sandbox".to_string())
+        );
+        assert_eq!(NetnsScope::parse("sandbox").label(), "sandbox");
+    }
+
+    #[test]
+    fn ns_link_targets() {
+        assert_eq!(parse_ns_link("net:[4026531840]"), Some(4026531840));
+        assert_eq!(parse_ns_link("mnt:[4026531840]"), None);
+        assert_eq!(parse_ns_link("net:[]"), None);
+    }
+
+    #[test]
+    fn host_namespace_comes_first() {
+        // only meaningful where procfs exists
+        if !Path::new("/proc/self/ns/net").exists() {
+            return;
+        }
+        let namespaces = list();
+        assert_eq!(namespaces[0].name, HOST);
+        assert_eq!(namespaces[0].pid, Some(std::process::id()));
+        let inodes: Vec<u64> = namespaces.iter().map(|ns| ns.inode).collect();
+        let mut unique = inodes.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(inodes.len(), unique.len());
+        assert!(check(&NetnsScope::Named(HOST.to_string())).is_ok());
+        assert!(check(&NetnsScope::All).is_ok());
+        let err = check(&NetnsScope::Named("no-such-ns".to_string())).unwrap_err();
+        assert!(err.starts_with("no network namespace named no-such-ns (there are: host"));
+    }
+}
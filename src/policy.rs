@@ -0,0 +1,371 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::addr::BindAddr;
+use crate::cli::{self, AuditArgs};
+use crate::ports::{self, PortEntry, ScanOptions};
+use crate::snapshot::Snapshot;
+
+const SYNTAX: &str = "expected `nothing on PORTS`, `only NAMES may listen on ADDRESS` \
+                      or `ports PORTS must bind ADDRESSES`";
+
+/// Rules listeners must follow, one per line:
+///
+/// ```text
+/// nothing on 23, 6000-6063
+/// only sshd, nginx may listen on public
+/// ports 8000-8999 must bind localhost
+/// ```
+#[derive(Debug)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    line: usize,
+    text: String,
+    kind: RuleKind,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum RuleKind {
+    Nothing(Vec<RangeInclusive<u16>>),
+    Only {
+        processes: Vec<String>,
+        addr: AddrMatch,
+    },
+    Bind {
+        ports: Vec<RangeInclusive<u16>>,
+        addrs: Vec<AddrMatch>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum AddrMatch {
+    /// `*`, `0.0.0.0` or `::`.
+    Wildcard,
+    /// Wildcard or any non-loopback address.
+    Public,
+    /// `127.0.0.0/8` and `::1`.
+    Loopback,
+    Exact(IpAddr),
+}
+
+impl AddrMatch {
+    fn parse(s: &str) -> Result<AddrMatch, String> {
+        Ok(match s {
+            "public" => AddrMatch::Public,
+            "localhost" | "loopback" => AddrMatch::Loopback,
+            _ => match s.parse()? {
+                BindAddr::AnyV4 | BindAddr::AnyV6 => AddrMatch::Wildcard,
+                BindAddr::Ip { addr, .. } => AddrMatch::Exact(addr),
+            },
+        })
+    }
+
+    fn matches(&self, addr: &BindAddr) -> bool {
+        match self {
+            AddrMatch::Wildcard => addr.is_wildcard(),
+            AddrMatch::Public => !addr.is_loopback(),
+            AddrMatch::Loopback => addr.is_loopback(),
+            AddrMatch::Exact(exact) => !addr.is_wildcard() && addr.ip() == *exact,
+        }
+    }
+}
+
+impl Rule {
+    fn violated_by(&self, e: &PortEntry) -> bool {
+        let in_ports = |ports: &[RangeInclusive<u16>]| ports.iter().any(|r| r.contains(&e.port));
+        match &self.kind {
+            RuleKind::Nothing(ports) => in_ports(ports),
+            RuleKind::Only { processes, addr } => {
+                e.addresses().into_iter().any(|a| addr.matches(a))
+                    && !processes.contains(&e.process_name)
+            }
+            RuleKind::Bind { ports, addrs } => {
+                in_ports(ports)
+                    && e.addresses()
+                        .into_iter()
+                        .any(|bound| !addrs.iter().any(|a| a.matches(bound)))
+            }
+        }
+    }
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Policy, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Policy::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Policy, String> {
+        let mut rules = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let text = line.split('#').next().unwrap_or_default().trim();
+            if text.is_empty() {
+                continue;
+            }
+            let kind = parse_rule(text).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            rules.push(Rule {
+                line: i + 1,
+                text: text.to_string(),
+                kind,
+            });
+        }
+        Ok(Policy { rules })
+    }
+
+    /// Sets `violations` on each entry to the rules it breaks.
+    pub fn annotate(&self, entries: &mut [PortEntry]) {
+        for e in entries {
+            e.violations = self
+                .rules
+                .iter()
+                .filter(|rule| rule.violated_by(e))
+                .map(|rule| format!("{} (line {})", rule.text, rule.line))
+                .collect();
+        }
+    }
+}
+
+fn parse_rule(text: &str) -> Result<RuleKind, String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words.as_slice() {
+        ["nothing", "on", ports @ ..] if !ports.is_empty() => {
+            Ok(RuleKind::Nothing(parse_ports(&ports.join(" "))?))
+        }
+        ["only", names @ .., "may", "listen", "on", addr] if !names.is_empty() => {
+            Ok(RuleKind::Only {
+                processes: list(&names.join(" ")).map(str::to_string).collect(),
+                addr: AddrMatch::parse(addr)?,
+            })
+        }
+        ["ports" | "port", ports, "must", "bind", addrs @ ..] if !addrs.is_empty() => {
+            Ok(RuleKind::Bind {
+                ports: parse_ports(ports)?,
+                addrs: list(&addrs.join(" "))
+                    .map(AddrMatch::parse)
+                    .collect::<Result<_, _>>()?,
+            })
+        }
+        _ => Err(SYNTAX.to_string()),
+    }
+}
+
+fn list(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+/// A comma-separated list of ports and ranges.
+fn parse_ports(s: &str) -> Result<Vec<RangeInclusive<u16>>, String> {
+    list(s)
+        .map(|spec| {
+            let ports = cli::parse_port_spec(spec)?;
+            Ok(ports[0]..=ports[ports.len() - 1])
+        })
+        .collect()
+}
+
+/// `$XDG_CONFIG_HOME/portit/policy`, or `~/.config/portit/policy`, if it
+/// exists.
+pub fn default_path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    let path = config.join("portit").join("policy");
+    path.is_file().then_some(path)
+}
+
+/// Exit codes: 0 when every listener follows the policy, 1 on violations,
+/// 2 when the policy or snapshot can't be read.
+pub fn run(args: &AuditArgs) -> ExitCode {
+    let Some(path) = args.policy.clone().or_else(default_path) else {
+        eprintln!("portit: no policy file, pass --policy FILE or create ~/.config/portit/policy");
+        return ExitCode::from(2);
+    };
+    let loaded = Policy::load(&path).and_then(|policy| {
+        let entries = match &args.load {
+            Some(snapshot) => Snapshot::load(snapshot)?.entries,
+            None => ports::list_listening_ports(&ScanOptions {
+                netns: args.netns.clone(),
+                ..Default::default()
+            }),
+        };
+        Ok((policy, entries))
+    });
+    let (policy, mut entries) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("portit: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    policy.annotate(&mut entries);
+    let violating = entries.iter().filter(|e| !e.violations.is_empty()).count();
+    if !args.quiet {
+        let _ = report(&entries, &mut io::stdout().lock());
+    }
+    if violating == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
+}
+
+fn report(entries: &[PortEntry], out: &mut impl Write) -> io::Result<()> {
+    let violating: Vec<&PortEntry> = entries
+        .iter()
+        .filter(|e| !e.violations.is_empty())
+        .collect();
+    for e in &violating {
+        writeln!(
+            out,
+            "{} {} (PID {}) on {}",
+            e.port,
+            e.process_name,
+            e.pid,
+            e.socket_addr()
+        )?;
+        for violation in &e.violations {
+            writeln!(out, "  breaks: {}", violation)?;
+        }
+    }
+    if violating.is_empty() {
+        writeln!(out, "All {} listeners follow the policy", entries.len())
+    } else {
+        writeln!(
+            out,
+            "\n{} of {} listeners break the policy",
+            violating.len(),
+            entries.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, address: &str, port: u16) -> PortEntry {
+        PortEntry {
+            pid: 7,
+            process_name: name.to_string(),
+            port,
+            address: address.parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn fixture() -> Policy {
+        Policy::parse(include_str!("../fixtures/policy/policy.txt")).unwrap()
+    }
+
+    fn violations(policy: &Policy, e: PortEntry) -> Vec<String> {
+        let mut entries = [e];
+        policy.annotate(&mut entries);
+        let [e] = entries;
+        e.violations
+    }
+
+    #[test]
+    fn parse_fixture() {
+        let policy = fixture();
+        let kinds: Vec<&RuleKind> = policy.rules.iter().map(|r| &r.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                &RuleKind::Only {
+                    processes: vec!["sshd".to_string()],
+                    addr: AddrMatch::Public
+                },
+                &RuleKind::Bind {
+                    ports: vec![8000..=8999],
+                    addrs: vec![AddrMatch::Loopback]
+                },
+                &RuleKind::Nothing(vec![23..=23, 6000..=6063]),
+            ]
+        );
+        assert_eq!(policy.rules[2].line, 10);
+    }
+
+    #[test]
+    fn public_listeners() {
+        let policy = fixture();
+        assert!(violations(&policy, entry("sshd", "*", 22)).is_empty());
+        assert!(violations(&policy, entry("postgres", "127.0.0.1", 5432)).is_empty());
+        assert_eq!(
+            violations(&policy, entry("redis-server", "*", 6379)),
+            ["only sshd may listen on public (line 4)"]
+        );
+        assert_eq!(
+            violations(&policy, entry("node", "192.168.1.5", 3000)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn port_rules() {
+        let policy = fixture();
+        assert!(violations(&policy, entry("python3", "::1", 8000)).is_empty());
+        assert_eq!(
+            violations(&policy, entry("python3", "*", 8000)),
+            [
+                "only sshd may listen on public (line 4)",
+                "ports 8000-8999 must bind localhost (line 7)"
+            ]
+        );
+        assert_eq!(
+            violations(&policy, entry("Xorg", "127.0.0.1", 6000)),
+            ["nothing on 23, 6000-6063 (line 10)"]
+        );
+    }
+
+    #[test]
+    fn exact_and_wildcard_addresses() {
+        let policy =
+            Policy::parse("only nginx may listen on 0.0.0.0\nport 9000 must bind 10.0.0.2, [::1]")
+                .unwrap();
+        assert!(violations(&policy, entry("nginx", "*", 80)).is_empty());
+        assert_eq!(violations(&policy, entry("node", "*", 80)).len(), 1);
+        // a specific address isn't the wildcard
+        assert!(violations(&policy, entry("node", "10.0.0.2", 9000)).is_empty());
+        assert!(violations(&policy, entry("node", "::1", 9000)).is_empty());
+        assert_eq!(
+            violations(&policy, entry("node", "10.0.0.3", 9000)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn syntax_errors_name_the_line() {
+        let err = Policy::parse("nothing on 23\nforbid 80\n").unwrap_err();
+        assert_eq!(err, format!("line 2: {}", SYNTAX));
+        let err = Policy::parse("ports 80-70 must bind localhost").unwrap_err();
+        assert_eq!(err, "line 1: invalid port range: 80-70");
+        let err = Policy::parse("only sshd may listen on example.com").unwrap_err();
+        assert_eq!(err, "line 1: invalid address: example.com");
+    }
+
+    #[test]
+    fn report_lists_violations() {
+        let policy = fixture();
+        let mut entries = vec![entry("sshd", "*", 22), entry("telnetd", "*", 23)];
+        policy.annotate(&mut entries);
+        let mut out = Vec::new();
+        report(&entries, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "23 telnetd (PID 7) on *:23\n  \
+             breaks: only sshd may listen on public (line 4)\n  \
+             breaks: nothing on 23, 6000-6063 (line 10)\n\
+             \n1 of 2 listeners break the policy\n"
+        );
+    }
+}
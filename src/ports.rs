@@ -1,33 +1,319 @@
-use std::process::Command;
+use crate::addr::BindAddr;
+use crate::container::{self, Container};
+use crate::exposure::{self, Exposure, Reach};
+use crate::netns::{self, NetnsScope};
+#[cfg(target_os = "linux")]
+use crate::procfs;
+use crate::remote;
+use crate::service;
+#[cfg(target_os = "linux")]
+use crate::sockdiag;
+use crate::systemd::{self, Unit};
+use crate::usage::Usage;
 
+#[derive(Clone, Debug, Default)]
 pub struct PortEntry {
     pub pid: u32,
     pub process_name: String,
     pub port: u16,
-    pub address: String,
+    pub address: BindAddr,
     pub command: String,
+    pub service: Option<String>,
+    /// Container owning the listener, directly or through its port proxy.
+    pub container: Option<Container>,
+    /// systemd service managing the process, when not in a container.
+    pub unit: Option<Unit>,
+    /// Network namespace, set when scanning beyond portit's own.
+    pub netns: Option<String>,
+    /// How far a wildcard bind reaches, when the interfaces are known.
+    pub reach: Option<Reach>,
+    /// Owner of the socket, known when read from the kernel rather than lsof.
+    pub uid: Option<u32>,
+    /// Every socket the process listens on this port with, e.g. both
+    /// `0.0.0.0` and `::`. `address` is the most exposed of them. Empty
+    /// when only `address` is known, as in older snapshots.
+    pub sockets: Vec<Socket>,
+    /// Policy rules the listener breaks, set when a policy is loaded.
+    pub violations: Vec<String>,
+    /// CPU and memory use of the process, set while the TUI samples it.
+    pub usage: Option<Usage>,
 }
 
-pub fn list_listening_ports() -> Vec<PortEntry> {
-    let output = Command::new("lsof")
-        .args(["-iTCP", "-sTCP:LISTEN", "-P", "-n"])
-        .output()
-        .ok();
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Socket {
+    pub address: BindAddr,
+    /// File descriptor number in the owning process.
+    pub fd: Option<u32>,
+    /// Accept queue and traffic, when the kernel tells them.
+    pub stats: Option<SocketStats>,
+}
 
-    let Some(output) = output else {
-        return Vec::new();
+/// Accept queue of a listening socket, like Recv-Q and Send-Q in `ss -l`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SocketStats {
+    /// Connections waiting to be accepted.
+    pub queued: u32,
+    /// The `listen()` backlog, known from sock_diag but not procfs.
+    pub backlog: Option<u32>,
+    /// Totals over the connections of the socket that are open right now,
+    /// from sock_diag. Closed ones are no longer counted.
+    pub traffic: Option<Traffic>,
+}
+
+impl SocketStats {
+    /// Whether the accept queue is at its limit, so the kernel drops new
+    /// connections until the process accepts some.
+    pub fn full(&self) -> bool {
+        self.backlog
+            .is_some_and(|backlog| self.queued > 0 && self.queued >= backlog)
+    }
+
+    /// Like `2/128 queued`, with the backlog when it's known.
+    pub fn queue_label(&self) -> String {
+        match self.backlog {
+            Some(backlog) => format!("{}/{} queued", self.queued, backlog),
+            None => format!("{} queued", self.queued),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub connections: u32,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub retransmits: u64,
+}
+
+impl Traffic {
+    pub fn add(&mut self, other: &Traffic) {
+        self.connections += other.connections;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.retransmits += other.retransmits;
+    }
+}
+
+/// `address:port`, with IPv6 addresses in brackets.
+pub fn socket_addr(address: &BindAddr, port: u16) -> String {
+    if address.is_ipv6() {
+        format!("[{}]:{}", address, port)
+    } else {
+        format!("{}:{}", address, port)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ScanOptions {
+    /// Scan these network namespaces through procfs instead of running lsof.
+    pub netns: Option<NetnsScope>,
+    pub backend: Backend,
+    /// Run lsof and ps on this host through ssh instead of locally.
+    pub host: Option<String>,
+}
+
+/// How listeners in portit's own network namespace are found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// sock_diag where the kernel has it, lsof elsewhere.
+    #[default]
+    Auto,
+    /// Parse the output of `lsof -iTCP -sTCP:LISTEN`.
+    Lsof,
+    /// Ask the kernel through NETLINK_SOCK_DIAG, Linux only.
+    Netlink,
+}
+
+impl Backend {
+    pub fn parse(s: &str) -> Option<Backend> {
+        match s {
+            "auto" => Some(Backend::Auto),
+            "lsof" => Some(Backend::Lsof),
+            "netlink" => Some(Backend::Netlink),
+            _ => None,
+        }
+    }
+}
+
+impl PortEntry {
+    /// The bind address and port, with IPv6 addresses in brackets.
+    pub fn socket_addr(&self) -> String {
+        socket_addr(&self.address, self.port)
+    }
+
+    /// Whether the listener is in portit's own network namespace, so that
+    /// connecting to `connect_host` reaches it.
+    pub fn in_own_netns(&self) -> bool {
+        self.netns.as_deref().is_none_or(|ns| ns == netns::HOST)
+    }
+
+    /// Host to connect to, mapping wildcard binds to localhost.
+    pub fn connect_host(&self) -> String {
+        if self.address.is_wildcard() {
+            "localhost".to_string()
+        } else {
+            self.address.to_string()
+        }
+    }
+
+    /// The distinct bind addresses, most exposed first.
+    pub fn addresses(&self) -> Vec<&BindAddr> {
+        let mut addresses = vec![&self.address];
+        for socket in &self.sockets {
+            if !addresses.contains(&&socket.address) {
+                addresses.push(&socket.address);
+            }
+        }
+        addresses
+    }
+
+    /// The addresses joined for display, like `*, ::`.
+    pub fn addresses_label(&self) -> String {
+        let addresses: Vec<String> = self.addresses().iter().map(|a| a.to_string()).collect();
+        addresses.join(", ")
+    }
+
+    /// Whether some socket's accept queue is full.
+    pub fn backlog_full(&self) -> bool {
+        self.sockets
+            .iter()
+            .any(|s| s.stats.is_some_and(|st| st.full()))
+    }
+
+    pub fn exposure(&self) -> Exposure {
+        exposure::classify(&self.address, self.reach)
+    }
+
+    pub fn url(&self) -> String {
+        let host = self.connect_host();
+        if host.contains(':') {
+            // a zone's `%` is itself escaped in URLs (RFC 6874)
+            format!("http://[{}]:{}", host.replace('%', "%25"), self.port)
+        } else {
+            format!("http://{}:{}", host, self.port)
+        }
+    }
+}
+
+pub fn list_listening_ports(opts: &ScanOptions) -> Vec<PortEntry> {
+    if let Some(host) = &opts.host {
+        return remote_listening(host);
+    }
+    let mut entries = match &opts.netns {
+        Some(scope) => netns::listening_ports(scope),
+        None => own_listening(opts.backend),
     };
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut entries: Vec<PortEntry> = stdout.lines().skip(1).filter_map(parse_lsof_line).collect();
+    exposure::annotate(&mut entries);
+    merge_sockets(&mut entries);
+    entries.sort_by_key(|e| e.port);
+    fetch_commands(&mut entries, None);
+    container::annotate(&mut entries);
+    systemd::annotate(&mut entries);
+    service::identify(&mut entries);
+    entries
+}
 
-    entries.sort_by_key(|e| (e.pid, e.port));
-    entries.dedup_by(|a, b| a.pid == b.pid && a.port == b.port);
+/// Listeners of portit's own namespace, with the accept queue and traffic
+/// of each socket. One sock_diag dump has both the listeners and their
+/// connections.
+#[cfg(target_os = "linux")]
+fn own_listening(backend: Backend) -> Vec<PortEntry> {
+    let sockets = sockdiag::dump(sockdiag::LISTEN_AND_OPEN).ok();
+    let mut entries = match (backend, &sockets) {
+        (Backend::Auto | Backend::Netlink, Some(sockets)) => sockdiag::listening_ports(sockets),
+        (Backend::Netlink, None) => Vec::new(),
+        (Backend::Auto | Backend::Lsof, _) => lsof_listening(None),
+    };
+    socket_stats(&mut entries, sockets.as_deref());
+    entries
+}
+
+/// sock_diag is Linux only, so there's nothing for `netlink` to find, and
+/// no accept queues.
+#[cfg(not(target_os = "linux"))]
+fn own_listening(backend: Backend) -> Vec<PortEntry> {
+    match backend {
+        Backend::Auto | Backend::Lsof => lsof_listening(None),
+        Backend::Netlink => Vec::new(),
+    }
+}
+
+/// Listeners on another machine. Only lsof and ps run there: socket
+/// stats, containers, units and banners would come from this one.
+fn remote_listening(host: &str) -> Vec<PortEntry> {
+    let mut entries = lsof_listening(Some(host));
+    merge_sockets(&mut entries);
     entries.sort_by_key(|e| e.port);
-    fetch_commands(&mut entries);
+    fetch_commands(&mut entries, Some(host));
+    service::identify(&mut entries);
     entries
 }
 
+/// Fills in the accept queue and traffic of each socket from a sock_diag
+/// dump, or just the queue from procfs when netlink isn't there.
+#[cfg(target_os = "linux")]
+fn socket_stats(entries: &mut [PortEntry], sockets: Option<&[sockdiag::DiagSocket]>) {
+    let stats = match sockets {
+        Some(sockets) => sockdiag::listener_stats(sockets),
+        None => procfs::listening(std::process::id())
+            .into_iter()
+            .map(|s| {
+                let stats = SocketStats {
+                    queued: s.rx_queue,
+                    ..Default::default()
+                };
+                ((BindAddr::from(s.addr), s.port), stats)
+            })
+            .collect(),
+    };
+    for e in entries.iter_mut() {
+        for socket in &mut e.sockets {
+            socket.stats = stats.get(&(socket.address.clone(), e.port)).copied();
+        }
+    }
+}
+
+/// Merges the sockets a process listens on one port with into one entry,
+/// keeping each of them in `sockets`.
+fn merge_sockets(entries: &mut Vec<PortEntry>) {
+    entries.sort_by(|a, b| {
+        (a.pid, a.port, &a.netns, &a.address).cmp(&(b.pid, b.port, &b.netns, &b.address))
+    });
+    let mut merged: Vec<PortEntry> = Vec::with_capacity(entries.len());
+    for mut e in entries.drain(..) {
+        if e.sockets.is_empty() {
+            e.sockets.push(Socket {
+                address: e.address.clone(),
+                fd: None,
+                stats: None,
+            });
+        }
+        match merged.last_mut() {
+            Some(last) if (last.pid, last.port, &last.netns) == (e.pid, e.port, &e.netns) => {
+                last.sockets.append(&mut e.sockets);
+                if e.exposure() > last.exposure() {
+                    last.address = e.address;
+                }
+            }
+            _ => merged.push(e),
+        }
+    }
+    *entries = merged;
+}
+
+fn lsof_listening(host: Option<&str>) -> Vec<PortEntry> {
+    let Ok(output) = remote::command(host, "lsof")
+        .args(["-iTCP", "-sTCP:LISTEN", "-P", "-n"])
+        .output()
+    else {
+        return Vec::new();
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().skip(1).filter_map(parse_lsof_line).collect()
+}
+
 fn parse_lsof_line(line: &str) -> Option<PortEntry> {
     let fields: Vec<&str> = line.split_whitespace().collect();
     if fields.len() < 10 {
@@ -36,40 +322,51 @@ fn parse_lsof_line(line: &str) -> Option<PortEntry> {
     let process_name = fields[0].to_string();
     let pid: u32 = fields[1].parse().ok()?;
     let addr_port = fields[fields.len() - 2];
-    let (address, port) = parse_addr_port(addr_port)?;
+    let (mut address, port) = parse_addr_port(addr_port)?;
+    // lsof prints both wildcards as `*`, the TYPE column tells them apart
+    if address == BindAddr::AnyV4 && fields[4] == "IPv6" {
+        address = BindAddr::AnyV6;
+    }
+    // FD is the number followed by the access mode, e.g. `22u`
+    let fd = fields[3].trim_end_matches(|c: char| !c.is_ascii_digit());
 
     Some(PortEntry {
         pid,
         process_name,
         port,
+        sockets: vec![Socket {
+            address: address.clone(),
+            fd: fd.parse().ok(),
+            stats: None,
+        }],
         address,
-        command: String::new(),
+        ..Default::default()
     })
 }
 
-fn parse_addr_port(s: &str) -> Option<(String, u16)> {
-    // IPv6: [::1]:3000
+fn parse_addr_port(s: &str) -> Option<(BindAddr, u16)> {
+    // IPv6: [::1]:3000, [fe80::1%lo0]:3000
     if s.starts_with('[')
         && let Some(i) = s.rfind("]:")
     {
-        let addr = s[1..i].to_string();
+        let addr = s[1..i].parse().ok()?;
         let port = s[i + 2..].parse().ok()?;
         return Some((addr, port));
     }
     // IPv4 or wildcard: 127.0.0.1:80, *:3000
     let colon = s.rfind(':')?;
-    let addr = s[..colon].to_string();
+    let addr = s[..colon].parse().ok()?;
     let port = s[colon + 1..].parse().ok()?;
     Some((addr, port))
 }
 
-fn fetch_commands(entries: &mut [PortEntry]) {
+fn fetch_commands(entries: &mut [PortEntry], host: Option<&str>) {
     if entries.is_empty() {
         return;
     }
 
     let pids: Vec<String> = entries.iter().map(|e| e.pid.to_string()).collect();
-    let Ok(output) = Command::new("ps")
+    let Ok(output) = remote::command(host, "ps")
         .args(["-ww", "-p", &pids.join(","), "-o", "pid=,command="])
         .output()
     else {
@@ -98,12 +395,29 @@ fn fetch_commands(entries: &mut [PortEntry]) {
     }
 }
 
-pub fn kill_process(pid: u32, force: bool) -> bool {
+pub fn signal_name(force: bool) -> &'static str {
+    if force { "SIGKILL" } else { "SIGTERM" }
+}
+
+/// Signals `pid`, on `host` through ssh if given, returning the complaint
+/// of kill or ssh on failure rather than letting it reach the terminal.
+pub fn kill_process(pid: u32, force: bool, host: Option<&str>) -> Result<(), String> {
     let sig = if force { "-KILL" } else { "-TERM" };
-    Command::new("kill")
+    let output = remote::command(host, "kill")
         .args([sig, &pid.to_string()])
-        .status()
-        .is_ok_and(|s| s.success())
+        .output()
+        .map_err(|e| format!("kill: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // ssh may print a login banner before its own error
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .rfind(|l| !l.trim().is_empty())
+        .unwrap_or("kill failed")
+        .trim()
+        .to_string())
 }
 
 #[cfg(test)]
@@ -113,28 +427,28 @@ mod tests {
     #[test]
     fn parse_ipv4_addr_port() {
         let (addr, port) = parse_addr_port("127.0.0.1:8080").unwrap();
-        assert_eq!(addr, "127.0.0.1");
+        assert_eq!(addr.to_string(), "127.0.0.1");
         assert_eq!(port, 8080);
     }
 
     #[test]
     fn parse_wildcard_addr_port() {
         let (addr, port) = parse_addr_port("*:3000").unwrap();
-        assert_eq!(addr, "*");
+        assert_eq!(addr, BindAddr::AnyV4);
         assert_eq!(port, 3000);
     }
 
     #[test]
     fn parse_ipv6_addr_port() {
         let (addr, port) = parse_addr_port("[::1]:443").unwrap();
-        assert_eq!(addr, "::1");
+        assert_eq!(addr.to_string(), "::1");
         assert_eq!(port, 443);
     }
 
     #[test]
     fn parse_ipv6_full_addr() {
         let (addr, port) = parse_addr_port("[::]:9090").unwrap();
-        assert_eq!(addr, "::");
+        assert_eq!(addr, BindAddr::AnyV6);
         assert_eq!(port, 9090);
     }
 
@@ -143,6 +457,37 @@ mod tests {
         assert!(parse_addr_port("no-colon").is_none());
         assert!(parse_addr_port("127.0.0.1:notnum").is_none());
         assert!(parse_addr_port("").is_none());
+        assert!(parse_addr_port("localhost:80").is_none());
+    }
+
+    fn entry(address: &str, port: u16) -> PortEntry {
+        PortEntry {
+            pid: 1,
+            process_name: "node".to_string(),
+            port,
+            address: address.parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn socket_addr_brackets_ipv6() {
+        assert_eq!(entry("127.0.0.1", 80).socket_addr(), "127.0.0.1:80");
+        assert_eq!(entry("*", 80).socket_addr(), "*:80");
+        assert_eq!(entry("::1", 443).socket_addr(), "[::1]:443");
+    }
+
+    #[test]
+    fn url_maps_wildcards_to_localhost() {
+        assert_eq!(entry("*", 3000).url(), "http://localhost:3000");
+        assert_eq!(entry("0.0.0.0", 3000).url(), "http://localhost:3000");
+        assert_eq!(entry("::", 3000).url(), "http://localhost:3000");
+        assert_eq!(entry("127.0.0.1", 8080).url(), "http://127.0.0.1:8080");
+        assert_eq!(entry("::1", 8080).url(), "http://[::1]:8080");
+        assert_eq!(
+            entry("fe80::1%eth0", 8080).url(),
+            "http://[fe80::1%25eth0]:8080"
+        );
     }
 
     #[test]
@@ -151,18 +496,25 @@ mod tests {
         let entry = parse_lsof_line(line).unwrap();
         assert_eq!(entry.process_name, "node");
         assert_eq!(entry.pid, 1234);
-        assert_eq!(entry.address, "127.0.0.1");
+        assert_eq!(entry.address.to_string(), "127.0.0.1");
         assert_eq!(entry.port, 3000);
         assert_eq!(entry.command, "");
     }
 
+    #[test]
+    fn parse_lsof_line_fd() {
+        let line = "node       1234 user   22u  IPv4 0x1234  0t0  TCP 127.0.0.1:3000 (LISTEN)";
+        let entry = parse_lsof_line(line).unwrap();
+        assert_eq!(entry.sockets[0].fd, Some(22));
+    }
+
     #[test]
     fn parse_lsof_line_wildcard() {
         let line = "nginx      5678 root   10u  IPv4 0xabcd  0t0  TCP *:80 (LISTEN)";
         let entry = parse_lsof_line(line).unwrap();
         assert_eq!(entry.process_name, "nginx");
         assert_eq!(entry.pid, 5678);
-        assert_eq!(entry.address, "*");
+        assert_eq!(entry.address, BindAddr::AnyV4);
         assert_eq!(entry.port, 80);
     }
 
@@ -170,10 +522,43 @@ mod tests {
     fn parse_lsof_line_ipv6() {
         let line = "node       1234 user   22u  IPv6 0x1234  0t0  TCP [::1]:8080 (LISTEN)";
         let entry = parse_lsof_line(line).unwrap();
-        assert_eq!(entry.address, "::1");
+        assert_eq!(entry.address.to_string(), "::1");
         assert_eq!(entry.port, 8080);
     }
 
+    #[test]
+    fn parse_lsof_line_ipv6_wildcard() {
+        let line = "nginx      5678 root   11u  IPv6 0xabcd  0t0  TCP *:80 (LISTEN)";
+        assert_eq!(parse_lsof_line(line).unwrap().address, BindAddr::AnyV6);
+    }
+
+    #[test]
+    fn sockets_merge_per_port() {
+        let mut entries = vec![
+            entry("::", 80),
+            entry("127.0.0.1", 8080),
+            entry("*", 80),
+            entry("10.0.0.2", 8080),
+            entry("::1", 8080),
+        ];
+        merge_sockets(&mut entries);
+        let rows: Vec<(String, String)> = entries
+            .iter()
+            .map(|e| (e.socket_addr(), e.addresses_label()))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("*:80".to_string(), "*, ::".to_string()),
+                (
+                    "10.0.0.2:8080".to_string(),
+                    "10.0.0.2, 127.0.0.1, ::1".to_string()
+                ),
+            ]
+        );
+        assert_eq!(entries[1].sockets.len(), 3);
+    }
+
     #[test]
     fn parse_lsof_line_too_few_fields() {
         assert!(parse_lsof_line("short line").is_none());
@@ -185,4 +570,35 @@ mod tests {
         let line = "node       notpid user   22u  IPv4 0x1234  0t0  TCP *:80 (LISTEN)";
         assert!(parse_lsof_line(line).is_none());
     }
+
+    /// Times both backends against a couple of thousand listeners:
+    /// `cargo test --release bench_backends -- --ignored --nocapture`.
+    /// On an x86_64 Linux VM a scan took 66-79ms with lsof and 15-16ms
+    /// with netlink.
+    #[test]
+    #[ignore]
+    fn bench_backends() {
+        use std::net::TcpListener;
+        use std::time::{Duration, Instant};
+
+        let listeners: Vec<_> = (0..2000)
+            .filter_map(|_| TcpListener::bind("127.0.0.1:0").ok())
+            .collect();
+        for backend in [Backend::Lsof, Backend::Netlink] {
+            let mut total = Duration::ZERO;
+            let mut found = 0;
+            for _ in 0..5 {
+                let start = Instant::now();
+                found = own_listening(backend).len();
+                total += start.elapsed();
+            }
+            println!(
+                "{:?}: {} listeners ({} opened), {:?} per scan",
+                backend,
+                found,
+                listeners.len(),
+                total / 5
+            );
+        }
+    }
 }
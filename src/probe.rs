@@ -0,0 +1,237 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct ProbeResult {
+    pub connect: Result<(SocketAddr, Duration), String>,
+    pub http: Option<Result<HttpInfo, String>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HttpInfo {
+    pub status: u16,
+    pub reason: String,
+    pub server: Option<String>,
+    pub latency: Duration,
+}
+
+impl ProbeResult {
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        match &self.connect {
+            Ok((addr, took)) => lines.push(format!("TCP connect to {} in {}", addr, millis(*took))),
+            Err(e) => lines.push(format!("TCP connect failed: {}", e)),
+        }
+        match &self.http {
+            Some(Ok(info)) => {
+                lines.push(format!(
+                    "HTTP {} {} in {}",
+                    info.status,
+                    info.reason,
+                    millis(info.latency)
+                ));
+                lines.push(format!(
+                    "Server: {}",
+                    info.server.as_deref().unwrap_or("(not sent)")
+                ));
+            }
+            Some(Err(e)) => lines.push(format!("HTTP HEAD failed: {}", e)),
+            None => {}
+        }
+        lines
+    }
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.1} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Connects to `host:port`, then optionally sends `HEAD /` and reads the
+/// status line and `Server` header.
+pub fn probe(host: &str, port: u16, http: bool) -> ProbeResult {
+    let (mut stream, addr, took) = match connect(host, port) {
+        Ok(connected) => connected,
+        Err(e) => {
+            return ProbeResult {
+                connect: Err(e),
+                http: None,
+            };
+        }
+    };
+
+    let http = http.then(|| head(&mut stream, host, port));
+    ProbeResult {
+        connect: Ok((addr, took)),
+        http,
+    }
+}
+
+fn connect(host: &str, port: u16) -> Result<(TcpStream, SocketAddr, Duration), String> {
+    let addrs: Vec<SocketAddr> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .collect();
+
+    let mut last_err = format!("could not resolve {}", host);
+    for addr in addrs {
+        let start = Instant::now();
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => return Ok((stream, addr, start.elapsed())),
+            Err(e) => last_err = e.to_string(),
+        }
+    }
+    Err(last_err)
+}
+
+fn head(stream: &mut TcpStream, host: &str, port: u16) -> Result<HttpInfo, String> {
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| e.to_string())?;
+
+    let start = Instant::now();
+    let request = format!(
+        "HEAD / HTTP/1.1\r\nHost: {}:{}\r\nUser-Agent: portit\r\nConnection: close\r\n\r\n",
+        host, port
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < 16 * 1024 {
+        match stream.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if buf.is_empty() => return Err(e.to_string()),
+            Err(_) => break,
+        }
+    }
+    let latency = start.elapsed();
+
+    let text = String::from_utf8_lossy(&buf);
+    let (status, reason, server) =
+        parse_response_head(&text).ok_or_else(|| "not an HTTP response".to_string())?;
+    Ok(HttpInfo {
+        status,
+        reason,
+        server,
+        latency,
+    })
+}
+
+fn parse_response_head(text: &str) -> Option<(u16, String, Option<String>)> {
+    let mut lines = text.lines();
+    let status_line = lines.next()?;
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next()?.starts_with("HTTP/") {
+        return None;
+    }
+    let status = parts.next()?.parse().ok()?;
+    let reason = parts.next().unwrap_or("").trim().to_string();
+
+    let server = lines
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("server"))
+        .map(|(_, value)| value.trim().to_string());
+
+    Some((status, reason, server))
+}
+
+/// Opens `url` with the platform's default handler, reaping it in the
+/// background once it exits.
+pub fn open_url(url: &str) -> bool {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    let child = Command::new(opener)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    thread::spawn(move || child.wait());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn parse_status_and_server() {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nserver: nginx/1.25\r\n\r\n";
+        let (status, reason, server) = parse_response_head(head).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(reason, "OK");
+        assert_eq!(server.as_deref(), Some("nginx/1.25"));
+    }
+
+    #[test]
+    fn parse_without_server_header() {
+        let (status, reason, server) =
+            parse_response_head("HTTP/1.0 404 Not Found\r\n\r\n").unwrap();
+        assert_eq!(status, 404);
+        assert_eq!(reason, "Not Found");
+        assert!(server.is_none());
+    }
+
+    #[test]
+    fn parse_rejects_non_http() {
+        assert!(parse_response_head("SSH-2.0-OpenSSH_9.6\r\n").is_none());
+        assert!(parse_response_head("+PONG\r\n").is_none());
+        assert!(parse_response_head("").is_none());
+    }
+
+    #[test]
+    fn probe_local_http_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 512];
+            let _ = conn.read(&mut buf);
+            conn.write_all(b"HTTP/1.1 204 No Content\r\nServer: test\r\n\r\n")
+                .unwrap();
+        });
+
+        let result = probe("127.0.0.1", port, true);
+        server.join().unwrap();
+        assert!(result.connect.is_ok());
+        let info = result.http.unwrap().unwrap();
+        assert_eq!(info.status, 204);
+        assert_eq!(info.server.as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn probe_tcp_only() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let result = probe("127.0.0.1", port, false);
+        assert!(result.connect.is_ok());
+        assert!(result.http.is_none());
+        assert_eq!(result.lines().len(), 1);
+    }
+
+    #[test]
+    fn probe_closed_port() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let result = probe("127.0.0.1", port, true);
+        assert!(result.connect.is_err());
+        assert!(result.lines()[0].starts_with("TCP connect failed"));
+    }
+}
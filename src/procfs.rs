@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// `st` value of a listening socket in /proc/net/tcp.
+pub const TCP_LISTEN: u8 = 0x0A;
+
+/// A row of /proc/net/tcp or /proc/net/tcp6.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TcpSocket {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub state: u8,
+    /// For a LISTEN socket, connections waiting to be accepted. procfs
+    /// doesn't show the backlog limit, only sock_diag does.
+    pub rx_queue: u32,
+    pub uid: u32,
+    pub inode: u64,
+}
+
+/// Parses the contents of /proc/net/tcp or tcp6, skipping the header.
+pub fn parse_net_tcp(content: &str) -> Vec<TcpSocket> {
+    content.lines().skip(1).filter_map(parse_tcp_line).collect()
+}
+
+fn parse_tcp_line(line: &str) -> Option<TcpSocket> {
+    // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 10 {
+        return None;
+    }
+    let (addr, port) = fields[1].split_once(':')?;
+    let (_, rx_queue) = fields[4].split_once(':')?;
+    Some(TcpSocket {
+        addr: parse_hex_addr(addr)?,
+        port: u16::from_str_radix(port, 16).ok()?,
+        state: u8::from_str_radix(fields[3], 16).ok()?,
+        rx_queue: u32::from_str_radix(rx_queue, 16).ok()?,
+        uid: fields[7].parse().ok()?,
+        inode: fields[9].parse().ok()?,
+    })
+}
+
+/// The kernel prints addresses as 32-bit words in host byte order.
+fn parse_hex_addr(hex: &str) -> Option<IpAddr> {
+    let mut bytes = Vec::with_capacity(16);
+    for i in (0..hex.len()).step_by(8) {
+        let word = u32::from_str_radix(hex.get(i..i + 8)?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::new(
+            bytes[0], bytes[1], bytes[2], bytes[3],
+        ))),
+        16 => {
+            let octets: [u8; 16] = bytes.try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+/// Listening TCP sockets of the network namespace `pid` lives in.
+pub fn listening(pid: u32) -> Vec<TcpSocket> {
+    ["tcp", "tcp6"]
+        .iter()
+        .filter_map(|proto| fs::read_to_string(format!("/proc/{}/net/{}", pid, proto)).ok())
+        .flat_map(|content| parse_net_tcp(&content))
+        .filter(|s| s.state == TCP_LISTEN)
+        .collect()
+}
+
+/// PIDs of all visible processes.
+pub fn pids() -> Vec<u32> {
+    let Ok(dir) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    let mut pids: Vec<u32> = dir
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    pids.sort_unstable();
+    pids
+}
+
+pub fn comm(pid: u32) -> Option<String> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+/// Login name of `uid` from /etc/passwd.
+pub fn user_name(uid: u32) -> Option<String> {
+    parse_passwd(&fs::read_to_string("/etc/passwd").ok()?, uid)
+}
+
+fn parse_passwd(content: &str, uid: u32) -> Option<String> {
+    content.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        (fields.nth(1)?.parse() == Ok(uid)).then(|| name.to_string())
+    })
+}
+
+/// Maps socket inodes to every process holding them open, with the fd
+/// number, so the workers of a pre-fork server all show like in lsof.
+/// Processes whose fds we may not read are missing, like in lsof too.
+pub fn socket_owners(pids: &[u32]) -> HashMap<u64, Vec<(u32, u32)>> {
+    let mut owners: HashMap<u64, Vec<(u32, u32)>> = HashMap::new();
+    for &pid in pids {
+        let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            if let Ok(target) = fs::read_link(fd.path())
+                && let Some(inode) = socket_inode(&target.to_string_lossy())
+                && let Some(num) = fd.file_name().to_str().and_then(|n| n.parse().ok())
+            {
+                owners.entry(inode).or_default().push((pid, num));
+            }
+        }
+    }
+    owners
+}
+
+/// Inode from an fd link target like `socket:[12345]`.
+fn socket_inode(target: &str) -> Option<u64> {
+    target
+        .strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ipv4_table() {
+        let sockets = parse_net_tcp(include_str!("../fixtures/proc/net-tcp.txt"));
+        assert_eq!(sockets.len(), 3);
+        assert_eq!(sockets[0].addr, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(sockets[0].port, 5432);
+        assert_eq!(sockets[0].state, TCP_LISTEN);
+        assert_eq!(sockets[0].inode, 23456);
+        assert_eq!(sockets[1].addr, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(sockets[1].port, 8080);
+        assert_eq!(sockets[1].rx_queue, 3);
+        assert_eq!(sockets[1].uid, 1000);
+        // established connection
+        assert_eq!(sockets[2].state, 0x01);
+    }
+
+    #[test]
+    fn parse_ipv6_table() {
+        let sockets = parse_net_tcp(include_str!("../fixtures/proc/net-tcp6.txt"));
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(sockets[0].addr, IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        assert_eq!(sockets[0].port, 22);
+        assert_eq!(sockets[1].addr, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(sockets[1].port, 3000);
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        assert!(parse_net_tcp("header\nnot a socket line\n").is_empty());
+        assert!(parse_hex_addr("0100007").is_none());
+    }
+
+    #[test]
+    fn passwd_names() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      postgres:x:112:120:PostgreSQL administrator:/var/lib/postgresql:/bin/bash\n";
+        assert_eq!(parse_passwd(passwd, 112).as_deref(), Some("postgres"));
+        assert_eq!(parse_passwd(passwd, 0).as_deref(), Some("root"));
+        assert_eq!(parse_passwd(passwd, 1000), None);
+    }
+
+    #[test]
+    fn every_fd_owns_its_socket() {
+        use std::os::fd::AsRawFd;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let clone = listener.try_clone().unwrap();
+        let fds = [listener.as_raw_fd() as u32, clone.as_raw_fd() as u32];
+        let link = fs::read_link(format!("/proc/self/fd/{}", fds[0])).unwrap();
+        let inode = socket_inode(&link.to_string_lossy()).unwrap();
+        let pid = std::process::id();
+        let mut owners = socket_owners(&[pid]).remove(&inode).unwrap();
+        owners.sort();
+        assert_eq!(owners, [(pid, fds[0]), (pid, fds[1])]);
+    }
+
+    #[test]
+    fn socket_link_targets() {
+        assert_eq!(socket_inode("socket:[23456]"), Some(23456));
+        assert_eq!(socket_inode("pipe:[23456]"), None);
+        assert_eq!(socket_inode("/dev/null"), None);
+    }
+}
@@ -0,0 +1,83 @@
+use std::io;
+use std::process::{Command, Stdio};
+
+#[cfg(not(test))]
+const SSH: &str = "ssh";
+/// A fake that answers lsof and ps from the fixtures next to it, as host
+/// `devbox`.
+#[cfg(test)]
+const SSH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/remote/ssh");
+
+/// `program` run on `host` through the local ssh client, or locally when
+/// there's no host. Batch mode makes ssh fail instead of prompting for a
+/// password over the TUI, so the host needs a key or an agent.
+pub fn command(host: Option<&str>, program: &str) -> Command {
+    let Some(host) = host else {
+        return Command::new(program);
+    };
+    let mut cmd = Command::new(SSH);
+    cmd.args([
+        "-o",
+        "BatchMode=yes",
+        "-o",
+        "ConnectTimeout=5",
+        "--",
+        host,
+        program,
+    ])
+    .stdin(Stdio::null());
+    cmd
+}
+
+/// Checks that `host` can be reached before the TUI takes over the
+/// terminal, returning its own hostname for the header.
+pub fn connect(host: &str) -> io::Result<String> {
+    let output = command(Some(host), "hostname")
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("ssh: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("{}: {}", host, stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::{self, ScanOptions};
+
+    #[test]
+    fn connect_returns_remote_hostname() {
+        assert_eq!(connect("devbox").unwrap(), "devbox.internal");
+        let err = connect("nowhere").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("nowhere: ssh: Could not resolve")
+        );
+    }
+
+    #[test]
+    fn lists_remote_listeners() {
+        let entries = ports::list_listening_ports(&ScanOptions {
+            host: Some("devbox".to_string()),
+            ..Default::default()
+        });
+        let summary: Vec<(u16, u32, &str)> = entries
+            .iter()
+            .map(|e| (e.port, e.pid, e.process_name.as_str()))
+            .collect();
+        assert_eq!(summary, vec![(5432, 812, "postgres"), (8080, 4242, "node")]);
+        assert_eq!(entries[0].sockets.len(), 2);
+        assert_eq!(entries[1].command, "node server.js --port 8080");
+        assert_eq!(entries[0].service.as_deref(), Some("postgres"));
+    }
+
+    #[test]
+    fn kills_on_remote_host() {
+        assert_eq!(ports::kill_process(4242, false, Some("devbox")), Ok(()));
+        assert!(ports::kill_process(4243, true, Some("devbox")).is_err());
+        let err = ports::kill_process(4242, false, Some("nowhere")).unwrap_err();
+        assert!(err.starts_with("ssh: Could not resolve hostname nowhere"));
+    }
+}
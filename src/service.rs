@@ -0,0 +1,419 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::ports::PortEntry;
+
+const TIMEOUT: Duration = Duration::from_millis(300);
+/// Listeners probed at once when grabbing banners.
+const PARALLEL: usize = 32;
+
+const WELL_KNOWN: &[(u16, &str)] = &[
+    (21, "ftp"),
+    (22, "ssh"),
+    (23, "telnet"),
+    (25, "smtp"),
+    (53, "dns"),
+    (80, "http"),
+    (110, "pop3"),
+    (111, "rpcbind"),
+    (143, "imap"),
+    (443, "https"),
+    (445, "smb"),
+    (465, "smtps"),
+    (587, "submission"),
+    (631, "ipp"),
+    (993, "imaps"),
+    (995, "pop3s"),
+    (1433, "mssql"),
+    (1521, "oracle"),
+    (2049, "nfs"),
+    (2375, "docker"),
+    (2376, "docker-tls"),
+    (2379, "etcd"),
+    (3306, "mysql"),
+    (3389, "rdp"),
+    (4222, "nats"),
+    (5432, "postgres"),
+    (5672, "amqp"),
+    (5900, "vnc"),
+    (6379, "redis"),
+    (6443, "kube-api"),
+    (8080, "http-alt"),
+    (8443, "https-alt"),
+    (9090, "prometheus"),
+    (9092, "kafka"),
+    (9200, "elasticsearch"),
+    (11211, "memcached"),
+    (15672, "rabbitmq-ui"),
+    (27017, "mongodb"),
+];
+
+pub fn well_known(port: u16) -> Option<&'static str> {
+    WELL_KNOWN
+        .binary_search_by_key(&port, |(p, _)| *p)
+        .ok()
+        .map(|i| WELL_KNOWN[i].1)
+}
+
+/// Fills in `service` from the well-known port table.
+pub fn identify(entries: &mut [PortEntry]) {
+    for entry in entries.iter_mut() {
+        entry.service = well_known(entry.port).map(str::to_string);
+    }
+}
+
+/// A listener, by process and port.
+type Listener = (u32, u16);
+
+/// Services identified from what listeners actually speak. Banners are
+/// grabbed on a background thread, once per listener, so a refresh never
+/// waits on a slow or silent one.
+pub struct Banners {
+    /// `None` when nothing was recognised.
+    known: HashMap<Listener, Option<String>>,
+    /// Being grabbed right now.
+    pending: HashSet<Listener>,
+    tx: Sender<(Listener, Option<String>)>,
+    rx: Receiver<(Listener, Option<String>)>,
+}
+
+impl Banners {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            known: HashMap::new(),
+            pending: HashSet::new(),
+            tx,
+            rx,
+        }
+    }
+
+    /// Starts grabbing from the listeners in `entries` not seen before,
+    /// and forgets the ones that are gone. Listeners in other network
+    /// namespaces can't be reached from here and keep the table's guess.
+    pub fn request(&mut self, entries: &[PortEntry]) {
+        self.known
+            .retain(|key, _| entries.iter().any(|e| (e.pid, e.port) == *key));
+        let mut targets = Vec::new();
+        for entry in entries.iter().filter(|e| e.in_own_netns()) {
+            let key = (entry.pid, entry.port);
+            if !self.known.contains_key(&key) && self.pending.insert(key) {
+                targets.push((key, entry.connect_host()));
+            }
+        }
+        if targets.is_empty() {
+            return;
+        }
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            for chunk in targets.chunks(PARALLEL) {
+                thread::scope(|scope| {
+                    for (key, host) in chunk {
+                        let tx = &tx;
+                        scope.spawn(move || {
+                            let _ = tx.send((*key, grab_banner(host, key.1)));
+                        });
+                    }
+                });
+            }
+        });
+    }
+
+    /// Takes in the banners grabbed since the last call, returning whether
+    /// there were any.
+    pub fn receive(&mut self) -> bool {
+        let mut any = false;
+        for (key, service) in self.rx.try_iter() {
+            self.pending.remove(&key);
+            self.known.insert(key, service);
+            any = true;
+        }
+        any
+    }
+
+    /// Replaces the table's guess wherever a banner was recognised.
+    pub fn annotate(&self, entries: &mut [PortEntry]) {
+        for entry in entries {
+            if let Some(Some(service)) = self.known.get(&(entry.pid, entry.port)) {
+                entry.service = Some(service.clone());
+            }
+        }
+    }
+}
+
+/// Identifies the protocol behind `host:port`. Protocols that greet first
+/// (SSH, SMTP, MySQL...) are read passively; the rest get a TLS
+/// ClientHello, an HTTP request and a Redis PING in turn, each on a fresh
+/// connection.
+pub fn grab_banner(host: &str, port: u16) -> Option<String> {
+    let addr = (host, port).to_socket_addrs().ok()?.next()?;
+
+    let greeting = exchange(&addr, None)?;
+    if !greeting.is_empty() {
+        return Some(classify_greeting(&greeting));
+    }
+
+    if exchange(&addr, Some(&client_hello())).is_some_and(|r| is_tls_response(&r)) {
+        return Some("tls".to_string());
+    }
+
+    let request = format!("HEAD / HTTP/1.0\r\nHost: {}\r\n\r\n", host);
+    if let Some(response) = exchange(&addr, Some(request.as_bytes()))
+        && let Some(service) = classify_http(&response)
+    {
+        return Some(service);
+    }
+
+    let ping = b"*1\r\n$4\r\nPING\r\n";
+    if exchange(&addr, Some(ping)).is_some_and(|r| is_redis_reply(&r)) {
+        return Some("redis".to_string());
+    }
+
+    None
+}
+
+/// Connects, optionally sends `payload`, and returns whatever arrives
+/// before the timeout. `None` means the connection itself failed.
+fn exchange(addr: &SocketAddr, payload: Option<&[u8]>) -> Option<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(addr, TIMEOUT).ok()?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+    if let Some(payload) = payload {
+        stream.write_all(payload).ok()?;
+    }
+
+    let mut buf = [0u8; 512];
+    match stream.read(&mut buf) {
+        Ok(n) => Some(buf[..n].to_vec()),
+        Err(_) => Some(Vec::new()),
+    }
+}
+
+fn classify_greeting(data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(data);
+    if let Some(rest) = text.strip_prefix("SSH-") {
+        // SSH-2.0-OpenSSH_9.6 -> ssh (OpenSSH_9.6)
+        let software = rest.lines().next().unwrap_or("").split_once('-');
+        return match software {
+            Some((_, sw)) if !sw.is_empty() => format!("ssh ({})", sw.trim()),
+            _ => "ssh".to_string(),
+        };
+    }
+    if text.starts_with("220") && text.to_ascii_lowercase().contains("smtp") {
+        return "smtp".to_string();
+    }
+    if text.starts_with("220") && text.to_ascii_lowercase().contains("ftp") {
+        return "ftp".to_string();
+    }
+    if text.starts_with("* OK") {
+        return "imap".to_string();
+    }
+    if text.starts_with("+OK") {
+        return "pop3".to_string();
+    }
+    // MySQL handshake: 3-byte length, sequence 0, protocol version 10
+    if data.len() > 5 && data[3] == 0 && data[4] == 10 {
+        return "mysql".to_string();
+    }
+    "banner".to_string()
+}
+
+fn classify_http(data: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(data);
+    if !text.starts_with("HTTP/") {
+        return None;
+    }
+    let server = text
+        .lines()
+        .skip(1)
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("server"))
+        .map(|(_, value)| value.trim().to_string());
+    Some(match server {
+        Some(server) => format!("http ({})", server),
+        None => "http".to_string(),
+    })
+}
+
+fn is_tls_response(data: &[u8]) -> bool {
+    // handshake (ServerHello) or alert record, TLS 1.0-1.3 record version
+    data.len() >= 3 && (data[0] == 0x16 || data[0] == 0x15) && data[1] == 0x03 && data[2] <= 0x04
+}
+
+fn is_redis_reply(data: &[u8]) -> bool {
+    data.starts_with(b"+PONG") || data.starts_with(b"-NOAUTH") || data.starts_with(b"-ERR")
+}
+
+/// Minimal TLS 1.2 ClientHello, enough for a server to answer with a
+/// ServerHello or an alert.
+fn client_hello() -> Vec<u8> {
+    let ciphers: [u16; 6] = [0xc02b, 0xc02f, 0xc030, 0x009c, 0x002f, 0x1301];
+
+    let mut body = vec![0x03, 0x03];
+    body.extend_from_slice(&[0x42; 32]); // random
+    body.push(0); // session id
+    body.extend_from_slice(&((ciphers.len() * 2) as u16).to_be_bytes());
+    for c in ciphers {
+        body.extend_from_slice(&c.to_be_bytes());
+    }
+    body.extend_from_slice(&[1, 0]); // null compression
+
+    let mut handshake = vec![0x01];
+    handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![0x16, 0x03, 0x01];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn well_known_table_is_sorted() {
+        assert!(WELL_KNOWN.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn well_known_lookup() {
+        assert_eq!(well_known(22), Some("ssh"));
+        assert_eq!(well_known(5432), Some("postgres"));
+        assert_eq!(well_known(6379), Some("redis"));
+        assert_eq!(well_known(3001), None);
+    }
+
+    #[test]
+    fn classify_ssh_banner() {
+        assert_eq!(
+            classify_greeting(b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3\r\n"),
+            "ssh (OpenSSH_9.6p1 Ubuntu-3)"
+        );
+        assert_eq!(classify_greeting(b"SSH-2.0-\r\n"), "ssh");
+    }
+
+    #[test]
+    fn classify_other_greetings() {
+        assert_eq!(classify_greeting(b"220 mail ESMTP Postfix\r\n"), "smtp");
+        assert_eq!(classify_greeting(b"220 (vsFTPd 3.0.5)\r\n"), "ftp");
+        assert_eq!(
+            classify_greeting(b"* OK [CAPABILITY IMAP4rev1]\r\n"),
+            "imap"
+        );
+        assert_eq!(
+            classify_greeting(&[0x4a, 0, 0, 0, 0x0a, b'8', b'.', b'0']),
+            "mysql"
+        );
+        assert_eq!(classify_greeting(b"hello\n"), "banner");
+    }
+
+    #[test]
+    fn classify_http_response() {
+        assert_eq!(
+            classify_http(b"HTTP/1.1 200 OK\r\nServer: nginx\r\n\r\n").as_deref(),
+            Some("http (nginx)")
+        );
+        assert_eq!(
+            classify_http(b"HTTP/1.0 400 Bad Request\r\n\r\n").as_deref(),
+            Some("http")
+        );
+        assert_eq!(classify_http(b"+PONG\r\n"), None);
+    }
+
+    #[test]
+    fn tls_and_redis_replies() {
+        assert!(is_tls_response(&[0x16, 0x03, 0x03, 0x00, 0x5a]));
+        assert!(is_tls_response(&[0x15, 0x03, 0x01, 0x00, 0x02]));
+        assert!(!is_tls_response(b"HTTP/1.1 400"));
+        assert!(is_redis_reply(b"+PONG\r\n"));
+        assert!(is_redis_reply(b"-NOAUTH Authentication required.\r\n"));
+        assert!(!is_redis_reply(b"HTTP/1.1"));
+    }
+
+    #[test]
+    fn client_hello_lengths() {
+        let hello = client_hello();
+        let record_len = u16::from_be_bytes([hello[3], hello[4]]) as usize;
+        assert_eq!(record_len, hello.len() - 5);
+        assert_eq!(hello[5], 0x01); // ClientHello
+        let body_len = u32::from_be_bytes([0, hello[6], hello[7], hello[8]]) as usize;
+        assert_eq!(body_len, hello.len() - 9);
+    }
+
+    #[test]
+    fn grab_ssh_style_greeting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.write_all(b"SSH-2.0-test\r\n").unwrap();
+        });
+        assert_eq!(
+            grab_banner("127.0.0.1", port).as_deref(),
+            Some("ssh (test)")
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn grab_http_after_silent_greeting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            for _ in 0..3 {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 512];
+                let n = conn.read(&mut buf).unwrap_or(0);
+                if buf[..n].starts_with(b"HEAD") {
+                    conn.write_all(b"HTTP/1.0 200 OK\r\n\r\n").unwrap();
+                    return;
+                }
+            }
+        });
+        assert_eq!(grab_banner("127.0.0.1", port).as_deref(), Some("http"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn banners_grab_only_in_own_namespace() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            conn.write_all(b"SSH-2.0-test\r\n").unwrap();
+        });
+        let entry = |pid: u32, netns: Option<&str>| PortEntry {
+            pid,
+            port,
+            address: "127.0.0.1".parse().unwrap(),
+            netns: netns.map(str::to_string),
+            ..Default::default()
+        };
+        let mut entries = [entry(1, Some("blue")), entry(2, None)];
+        let mut banners = Banners::new();
+        banners.request(&entries);
+        assert!(!banners.pending.is_empty());
+        server.join().unwrap();
+        while !banners.pending.is_empty() {
+            thread::sleep(Duration::from_millis(10));
+            banners.receive();
+        }
+        banners.annotate(&mut entries);
+        assert_eq!(entries[0].service, None);
+        assert_eq!(entries[1].service.as_deref(), Some("ssh (test)"));
+
+        // grabbed once per listener, and forgotten once it's gone
+        banners.request(&entries);
+        assert!(banners.pending.is_empty());
+        banners.request(&entries[..1]);
+        assert!(banners.known.is_empty());
+    }
+}
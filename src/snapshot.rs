@@ -0,0 +1,301 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, ExitCode};
+use std::time::SystemTime;
+
+use crate::cli::SnapshotArgs;
+use crate::container::{Container, Runtime};
+use crate::history;
+use crate::json::{self, Value};
+use crate::ports::{self, PortEntry, ScanOptions, Socket};
+use crate::systemd::Unit;
+
+/// What was listening on a host at one point in time.
+pub struct Snapshot {
+    pub host: String,
+    /// RFC 3339, UTC.
+    pub taken: String,
+    pub entries: Vec<PortEntry>,
+}
+
+impl Snapshot {
+    pub fn capture(entries: Vec<PortEntry>) -> Snapshot {
+        Snapshot {
+            host: hostname(),
+            taken: history::format_time(SystemTime::now()),
+            entries,
+        }
+    }
+
+    /// One listener per line, so snapshots diff well with plain tools too.
+    pub fn to_json(&self) -> String {
+        let listeners: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| format!("    {}", entry_json(e)))
+            .collect();
+        format!(
+            "{{\n  \"portit\": {},\n  \"host\": {},\n  \"taken\": {},\n  \"listeners\": [\n{}\n  ]\n}}\n",
+            json::string(env!("CARGO_PKG_VERSION")),
+            json::string(&self.host),
+            json::string(&self.taken),
+            listeners.join(",\n")
+        )
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_json()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Snapshot::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(content: &str) -> Result<Snapshot, String> {
+        let value = json::parse(content)?;
+        let listeners = value
+            .get("listeners")
+            .and_then(Value::as_array)
+            .ok_or("not a portit snapshot")?;
+        let entries = listeners
+            .iter()
+            .enumerate()
+            .map(|(i, v)| entry_from_json(v).map_err(|e| format!("listener {}: {}", i + 1, e)))
+            .collect::<Result<_, _>>()?;
+        let text = |key| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        Ok(Snapshot {
+            host: text("host"),
+            taken: text("taken"),
+            entries,
+        })
+    }
+}
+
+fn entry_json(e: &PortEntry) -> String {
+    let mut fields = vec![
+        ("port", e.port.to_string()),
+        ("address", json::string(&e.address.to_string())),
+        ("pid", e.pid.to_string()),
+        ("process", json::string(&e.process_name)),
+        ("command", json::string(&e.command)),
+    ];
+    if let Some(service) = &e.service {
+        fields.push(("service", json::string(service)));
+    }
+    if let Some(c) = &e.container {
+        let mut container = vec![
+            ("runtime", json::string(c.runtime.cli())),
+            ("id", json::string(&c.id)),
+        ];
+        if let Some(name) = &c.name {
+            container.push(("name", json::string(name)));
+        }
+        if let Some(image) = &c.image {
+            container.push(("image", json::string(image)));
+        }
+        fields.push(("container", json::object(&container)));
+    }
+    if let Some(unit) = &e.unit {
+        let mut object = vec![("name", json::string(&unit.name))];
+        if let Some(uid) = unit.user {
+            object.push(("user", uid.to_string()));
+        }
+        fields.push(("unit", json::object(&object)));
+    }
+    if let Some(netns) = &e.netns {
+        fields.push(("netns", json::string(netns)));
+    }
+    if e.sockets.len() > 1 {
+        let sockets: Vec<String> = e
+            .sockets
+            .iter()
+            .map(|s| {
+                let mut socket = vec![("address", json::string(&s.address.to_string()))];
+                if let Some(fd) = s.fd {
+                    socket.push(("fd", fd.to_string()));
+                }
+                json::object(&socket)
+            })
+            .collect();
+        fields.push(("sockets", format!("[{}]", sockets.join(","))));
+    }
+    json::object(&fields)
+}
+
+fn entry_from_json(v: &Value) -> Result<PortEntry, String> {
+    let number = |key: &str| {
+        v.get(key)
+            .and_then(Value::as_u64)
+            .ok_or_else(|| format!("missing {}", key))
+    };
+    let text = |key: &str| v.get(key).and_then(Value::as_str).map(str::to_string);
+    let port = number("port")?;
+    let pid = number("pid")?;
+
+    let container = match v.get("container") {
+        Some(c) => {
+            let runtime = c.get("runtime").and_then(Value::as_str).unwrap_or_default();
+            Some(Container {
+                runtime: Runtime::from_cli(runtime)
+                    .ok_or_else(|| format!("unknown container runtime: {}", runtime))?,
+                id: c
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .ok_or("missing container id")?
+                    .to_string(),
+                name: c.get("name").and_then(Value::as_str).map(str::to_string),
+                image: c.get("image").and_then(Value::as_str).map(str::to_string),
+            })
+        }
+        None => None,
+    };
+    let sockets = match v.get("sockets").and_then(Value::as_array) {
+        Some(sockets) => sockets
+            .iter()
+            .map(|s| {
+                Ok(Socket {
+                    address: s
+                        .get("address")
+                        .and_then(Value::as_str)
+                        .ok_or("missing socket address")?
+                        .parse()?,
+                    fd: s
+                        .get("fd")
+                        .and_then(Value::as_u64)
+                        .and_then(|fd| u32::try_from(fd).ok()),
+                    stats: None,
+                })
+            })
+            .collect::<Result<_, String>>()?,
+        None => Vec::new(),
+    };
+    let unit = match v.get("unit") {
+        Some(u) => Some(Unit {
+            name: u
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or("missing unit name")?
+                .to_string(),
+            user: u
+                .get("user")
+                .and_then(Value::as_u64)
+                .and_then(|uid| u32::try_from(uid).ok()),
+        }),
+        None => None,
+    };
+
+    Ok(PortEntry {
+        pid: u32::try_from(pid).map_err(|_| format!("invalid pid: {}", pid))?,
+        process_name: text("process").unwrap_or_default(),
+        port: u16::try_from(port).map_err(|_| format!("invalid port: {}", port))?,
+        address: text("address").ok_or("missing address")?.parse()?,
+        command: text("command").unwrap_or_default(),
+        service: text("service"),
+        container,
+        unit,
+        netns: text("netns"),
+        sockets,
+        ..Default::default()
+    })
+}
+
+fn hostname() -> String {
+    if let Ok(name) = fs::read_to_string("/proc/sys/kernel/hostname") {
+        return name.trim().to_string();
+    }
+    Command::new("hostname")
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// `portit snapshot`: writes the current listeners to a file or stdout.
+pub fn run(args: &SnapshotArgs) -> ExitCode {
+    let scan = ScanOptions {
+        netns: args.netns.clone(),
+        ..Default::default()
+    };
+    let snapshot = Snapshot::capture(ports::list_listening_ports(&scan));
+    match &args.output {
+        Some(path) => {
+            if let Err(e) = snapshot.save(path) {
+                eprintln!("portit: {}", e);
+                return ExitCode::FAILURE;
+            }
+            eprintln!(
+                "portit: saved {} listeners to {}",
+                snapshot.entries.len(),
+                path.display()
+            );
+        }
+        None => print!("{}", snapshot.to_json()),
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_fixture() {
+        let snapshot = Snapshot::parse(include_str!("../fixtures/snapshot/before.json")).unwrap();
+        assert_eq!(snapshot.host, "web-1");
+        assert_eq!(snapshot.taken, "2026-03-02T09:00:00Z");
+        assert_eq!(snapshot.entries.len(), 4);
+        let web = &snapshot.entries[1];
+        assert_eq!((web.port, web.pid), (8080, 2001));
+        let container = web.container.as_ref().unwrap();
+        assert_eq!(container.runtime, Runtime::Docker);
+        assert_eq!(container.label(), "web (nginx:1.27)");
+        assert_eq!(
+            snapshot.entries[3].unit.as_ref().unwrap().name,
+            "redis-server.service"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let before = Snapshot::parse(include_str!("../fixtures/snapshot/before.json")).unwrap();
+        let again = Snapshot::parse(&before.to_json()).unwrap();
+        assert_eq!(again.host, before.host);
+        assert_eq!(again.entries.len(), before.entries.len());
+        for (a, b) in before.entries.iter().zip(&again.entries) {
+            assert_eq!(entry_json(a), entry_json(b));
+        }
+    }
+
+    #[test]
+    fn sockets_round_trip() {
+        let listener = r#"{"port": 80, "address": "*", "pid": 9, "process": "nginx", "command": "nginx",
+            "sockets": [{"address": "*", "fd": 6}, {"address": "::"}]}"#;
+        let snapshot = Snapshot::parse(&format!("{{\"listeners\": [{}]}}", listener)).unwrap();
+        let e = &snapshot.entries[0];
+        assert_eq!(e.addresses_label(), "*, ::");
+        assert_eq!(e.sockets[0].fd, Some(6));
+        assert_eq!(e.sockets[1].fd, None);
+        let again = Snapshot::parse(&snapshot.to_json()).unwrap();
+        assert_eq!(again.entries[0].sockets, e.sockets);
+    }
+
+    #[test]
+    fn rejects_other_json() {
+        assert_eq!(
+            Snapshot::parse("{\"ports\": []}").err().as_deref(),
+            Some("not a portit snapshot")
+        );
+        assert_eq!(
+            Snapshot::parse("{\"listeners\": [{\"pid\": 1, \"address\": \"*\"}]}")
+                .err()
+                .as_deref(),
+            Some("listener 1: missing port")
+        );
+    }
+}
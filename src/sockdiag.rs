@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use crate::addr::BindAddr;
+use crate::ports::{PortEntry, Socket, SocketStats, Traffic};
+use crate::procfs::{self, TCP_LISTEN};
+
+const AF_INET: u8 = 2;
+const AF_INET6: u8 = 10;
+const AF_NETLINK: i32 = 16;
+const SOCK_DGRAM: i32 = 2;
+const SOCK_CLOEXEC: i32 = 0o2000000;
+const NETLINK_SOCK_DIAG: i32 = 4;
+const IPPROTO_TCP: u8 = 6;
+
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const SOCK_DIAG_BY_FAMILY: u16 = 20;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_DUMP: u16 = 0x300;
+const NLMSG_HDRLEN: usize = 16;
+
+/// Attribute carrying `struct tcp_info`.
+const INET_DIAG_INFO: u16 = 2;
+/// `struct inet_diag_msg` up to its attributes.
+const DIAG_MSG_LEN: usize = 72;
+/// `struct tcp_info` up to `tcpi_bytes_received`, Linux 4.1 and later.
+const TCP_INFO_LEN: usize = 136;
+
+const TCP_ESTABLISHED: u8 = 1;
+const TCP_FIN_WAIT1: u8 = 4;
+const TCP_FIN_WAIT2: u8 = 5;
+const TCP_CLOSE_WAIT: u8 = 8;
+const TCP_LAST_ACK: u8 = 9;
+const TCP_CLOSING: u8 = 11;
+
+/// Listeners and the connections that still have a `tcp_info` to count
+/// toward them, as a mask of `1 << state`. TIME_WAIT and SYN_RECV have
+/// none, and on a busy host there are many of them.
+pub const LISTEN_AND_OPEN: u32 = 1 << TCP_LISTEN
+    | 1 << TCP_ESTABLISHED
+    | 1 << TCP_FIN_WAIT1
+    | 1 << TCP_FIN_WAIT2
+    | 1 << TCP_CLOSE_WAIT
+    | 1 << TCP_LAST_ACK
+    | 1 << TCP_CLOSING;
+
+/// A TCP socket as inet_diag reports it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DiagSocket {
+    pub addr: IpAddr,
+    pub port: u16,
+    pub state: u8,
+    /// Connections waiting to be accepted on a LISTEN socket, unread bytes
+    /// on others.
+    pub rqueue: u32,
+    /// The backlog limit of a LISTEN socket, unsent bytes on others.
+    pub wqueue: u32,
+    pub uid: u32,
+    pub inode: u64,
+    /// From `tcp_info`, missing for sockets in TIME_WAIT or SYN_RECV.
+    pub traffic: Option<Traffic>,
+}
+
+mod sys {
+    use std::ffi::{c_int, c_void};
+
+    unsafe extern "C" {
+        pub fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+        pub fn send(fd: c_int, buf: *const c_void, len: usize, flags: c_int) -> isize;
+        pub fn recv(fd: c_int, buf: *mut c_void, len: usize, flags: c_int) -> isize;
+    }
+}
+
+/// TCP sockets of portit's network namespace whose state is in `states`,
+/// IPv4 first.
+pub fn dump(states: u32) -> io::Result<Vec<DiagSocket>> {
+    // SAFETY: plain syscall; the fd is owned and closed by `OwnedFd`.
+    let fd = unsafe { sys::socket(AF_NETLINK, SOCK_DGRAM | SOCK_CLOEXEC, NETLINK_SOCK_DIAG) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: `fd` was just opened and nothing else owns it.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut sockets = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    for (seq, family) in [AF_INET, AF_INET6].into_iter().enumerate() {
+        let req = request(family, states, seq as u32 + 1);
+        // SAFETY: `req` is valid for reads of its length.
+        let sent = unsafe { sys::send(fd.as_raw_fd(), req.as_ptr().cast(), req.len(), 0) };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        loop {
+            // SAFETY: `buf` is valid for writes of its length.
+            let n = unsafe { sys::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if parse_messages(&buf[..n as usize], &mut sockets)? {
+                break;
+            }
+        }
+    }
+    Ok(sockets)
+}
+
+/// The LISTEN sockets of a dump, with the process holding each found
+/// through procfs like `netns::listening_ports` does.
+pub fn listening_ports(sockets: &[DiagSocket]) -> Vec<PortEntry> {
+    let owners = procfs::socket_owners(&procfs::pids());
+    let mut names: HashMap<u32, String> = HashMap::new();
+    sockets
+        .iter()
+        .filter(|s| s.state == TCP_LISTEN)
+        .flat_map(|s| {
+            owners
+                .get(&s.inode)
+                .into_iter()
+                .flatten()
+                .map(move |&o| (s, o))
+        })
+        .map(|(s, (pid, fd))| {
+            let process_name = names
+                .entry(pid)
+                .or_insert_with(|| procfs::comm(pid).unwrap_or_default())
+                .clone();
+            let address = BindAddr::from(s.addr);
+            PortEntry {
+                pid,
+                process_name,
+                port: s.port,
+                address: address.clone(),
+                uid: Some(s.uid),
+                sockets: vec![Socket {
+                    address,
+                    fd: Some(fd),
+                    stats: Some(SocketStats {
+                        queued: s.rqueue,
+                        backlog: Some(s.wqueue),
+                        traffic: None,
+                    }),
+                }],
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// An `nlmsghdr` followed by an `inet_diag_req_v2` asking for `tcp_info`.
+fn request(family: u8, states: u32, seq: u32) -> Vec<u8> {
+    let mut req = Vec::with_capacity(NLMSG_HDRLEN + 56);
+    req.extend_from_slice(&((NLMSG_HDRLEN + 56) as u32).to_ne_bytes());
+    req.extend_from_slice(&SOCK_DIAG_BY_FAMILY.to_ne_bytes());
+    req.extend_from_slice(&(NLM_F_REQUEST | NLM_F_DUMP).to_ne_bytes());
+    req.extend_from_slice(&seq.to_ne_bytes());
+    req.extend_from_slice(&0u32.to_ne_bytes());
+    req.extend_from_slice(&[family, IPPROTO_TCP, 1 << (INET_DIAG_INFO - 1), 0]);
+    req.extend_from_slice(&states.to_ne_bytes());
+    // an all-zero inet_diag_sockid matches every socket
+    req.resize(NLMSG_HDRLEN + 56, 0);
+    req
+}
+
+/// Appends the sockets in one datagram of a dump, returning whether it
+/// ended the dump.
+fn parse_messages(mut buf: &[u8], sockets: &mut Vec<DiagSocket>) -> io::Result<bool> {
+    while buf.len() >= NLMSG_HDRLEN {
+        let len = u32_at(buf, 0) as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            break;
+        }
+        let payload = &buf[NLMSG_HDRLEN..len];
+        match u16::from_ne_bytes([buf[4], buf[5]]) {
+            NLMSG_DONE => return Ok(true),
+            NLMSG_ERROR => {
+                let errno =
+                    i32::from_ne_bytes(payload.get(..4).unwrap_or(&[0; 4]).try_into().unwrap());
+                return Err(io::Error::from_raw_os_error(-errno));
+            }
+            SOCK_DIAG_BY_FAMILY => sockets.extend(parse_diag_msg(payload)),
+            _ => {}
+        }
+        buf = &buf[align(len).min(buf.len())..];
+    }
+    Ok(false)
+}
+
+/// `struct inet_diag_msg` and its attributes. Ports and addresses are in
+/// network byte order, everything else in host order.
+fn parse_diag_msg(msg: &[u8]) -> Option<DiagSocket> {
+    if msg.len() < DIAG_MSG_LEN {
+        return None;
+    }
+    let addr = match msg[0] {
+        AF_INET => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&msg[8..12]).ok()?)),
+        AF_INET6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&msg[8..24]).ok()?)),
+        _ => return None,
+    };
+    let mut traffic = None;
+    let mut attrs = &msg[DIAG_MSG_LEN..];
+    while attrs.len() >= 4 {
+        let len = u16::from_ne_bytes([attrs[0], attrs[1]]) as usize;
+        if len < 4 || len > attrs.len() {
+            break;
+        }
+        if u16::from_ne_bytes([attrs[2], attrs[3]]) == INET_DIAG_INFO {
+            traffic = parse_tcp_info(&attrs[4..len]);
+        }
+        attrs = &attrs[align(len).min(attrs.len())..];
+    }
+    Some(DiagSocket {
+        addr,
+        port: u16::from_be_bytes([msg[4], msg[5]]),
+        state: msg[1],
+        rqueue: u32_at(msg, 56),
+        wqueue: u32_at(msg, 60),
+        uid: u32_at(msg, 64),
+        inode: u32_at(msg, 68) as u64,
+        traffic,
+    })
+}
+
+/// `tcpi_total_retrans`, `tcpi_bytes_acked` and `tcpi_bytes_received`.
+fn parse_tcp_info(info: &[u8]) -> Option<Traffic> {
+    if info.len() < TCP_INFO_LEN {
+        return None;
+    }
+    Some(Traffic {
+        connections: 1,
+        retransmits: u32_at(info, 100) as u64,
+        bytes_sent: u64_at(info, 120),
+        bytes_received: u64_at(info, 128),
+    })
+}
+
+/// Accept queues of the LISTEN sockets in `sockets`, by bind address and
+/// port, with the traffic of their connections that are open right now.
+/// Closed connections are gone from the dump and no longer count.
+pub fn listener_stats(sockets: &[DiagSocket]) -> HashMap<(BindAddr, u16), SocketStats> {
+    let mut stats: HashMap<(BindAddr, u16), SocketStats> = sockets
+        .iter()
+        .filter(|s| s.state == TCP_LISTEN)
+        .map(|s| {
+            let stats = SocketStats {
+                queued: s.rqueue,
+                backlog: Some(s.wqueue),
+                traffic: Some(Traffic::default()),
+            };
+            ((BindAddr::from(s.addr), s.port), stats)
+        })
+        .collect();
+    for s in sockets.iter().filter(|s| s.state != TCP_LISTEN) {
+        let Some(traffic) = &s.traffic else {
+            continue;
+        };
+        // the listener bound to this exact address, or else the wildcard
+        // of its family; IPv4 clients of a dual-stack `::` come last
+        let local = BindAddr::from(s.addr);
+        let wildcard = if local.is_ipv6() {
+            BindAddr::AnyV6
+        } else {
+            BindAddr::AnyV4
+        };
+        let key = [local, wildcard, BindAddr::AnyV6]
+            .into_iter()
+            .map(|addr| (addr, s.port))
+            .find(|key| stats.contains_key(key));
+        if let Some(key) = key
+            && let Some(total) = stats.get_mut(&key).and_then(|l| l.traffic.as_mut())
+        {
+            total.add(traffic);
+        }
+    }
+    stats
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buf: &[u8], at: usize) -> u64 {
+    u64::from_ne_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// An `inet_diag_msg` as the kernel would send it, with `tcp_info`
+    /// when `traffic` is given.
+    fn diag_msg(
+        addr: IpAddr,
+        port: u16,
+        state: u8,
+        queues: (u32, u32),
+        traffic: Option<Traffic>,
+    ) -> Vec<u8> {
+        let mut msg = vec![0u8; DIAG_MSG_LEN];
+        msg[1] = state;
+        msg[4..6].copy_from_slice(&port.to_be_bytes());
+        match addr {
+            IpAddr::V4(ip) => {
+                msg[0] = AF_INET;
+                msg[8..12].copy_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                msg[0] = AF_INET6;
+                msg[8..24].copy_from_slice(&ip.octets());
+            }
+        }
+        msg[56..60].copy_from_slice(&queues.0.to_ne_bytes());
+        msg[60..64].copy_from_slice(&queues.1.to_ne_bytes());
+        msg[68..72].copy_from_slice(&4242u32.to_ne_bytes());
+        if let Some(t) = traffic {
+            let mut info = vec![0u8; 232];
+            info[100..104].copy_from_slice(&(t.retransmits as u32).to_ne_bytes());
+            info[120..128].copy_from_slice(&t.bytes_sent.to_ne_bytes());
+            info[128..136].copy_from_slice(&t.bytes_received.to_ne_bytes());
+            msg.extend_from_slice(&((4 + info.len()) as u16).to_ne_bytes());
+            msg.extend_from_slice(&INET_DIAG_INFO.to_ne_bytes());
+            msg.extend_from_slice(&info);
+        }
+        msg
+    }
+
+    fn nlmsg(kind: u16, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&((NLMSG_HDRLEN + payload.len()) as u32).to_ne_bytes());
+        buf.extend_from_slice(&kind.to_ne_bytes());
+        buf.extend_from_slice(&[0; 10]);
+        buf.extend_from_slice(payload);
+        buf.resize(align(buf.len()), 0);
+        buf
+    }
+
+    fn traffic(sent: u64, received: u64, retransmits: u64) -> Traffic {
+        Traffic {
+            connections: 1,
+            bytes_sent: sent,
+            bytes_received: received,
+            retransmits,
+        }
+    }
+
+    #[test]
+    fn parse_dump() {
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut buf = nlmsg(
+            SOCK_DIAG_BY_FAMILY,
+            &diag_msg(localhost, 8080, TCP_LISTEN, (2, 128), None),
+        );
+        buf.extend(nlmsg(
+            SOCK_DIAG_BY_FAMILY,
+            &diag_msg(localhost, 8080, 1, (0, 0), Some(traffic(1000, 200, 3))),
+        ));
+        let mut sockets = Vec::new();
+        assert!(!parse_messages(&buf, &mut sockets).unwrap());
+        assert_eq!(sockets.len(), 2);
+        assert_eq!(
+            (sockets[0].port, sockets[0].rqueue, sockets[0].wqueue),
+            (8080, 2, 128)
+        );
+        assert_eq!(sockets[0].inode, 4242);
+        assert_eq!(sockets[1].traffic, Some(traffic(1000, 200, 3)));
+
+        assert!(parse_messages(&nlmsg(NLMSG_DONE, &[0; 4]), &mut sockets).unwrap());
+        let err = parse_messages(&nlmsg(NLMSG_ERROR, &(-13i32).to_ne_bytes()), &mut sockets);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn traffic_goes_to_the_accepting_listener() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let sockets = [
+            DiagSocket {
+                addr: ip("0.0.0.0"),
+                port: 80,
+                state: TCP_LISTEN,
+                rqueue: 129,
+                wqueue: 128,
+                uid: 0,
+                inode: 1,
+                traffic: None,
+            },
+            DiagSocket {
+                addr: ip("::"),
+                port: 80,
+                state: TCP_LISTEN,
+                rqueue: 0,
+                wqueue: 128,
+                uid: 0,
+                inode: 2,
+                traffic: None,
+            },
+        ];
+        let connection = |addr: &str, t: Traffic| DiagSocket {
+            addr: ip(addr),
+            port: 80,
+            state: 1,
+            rqueue: 0,
+            wqueue: 0,
+            uid: 0,
+            inode: 3,
+            traffic: Some(t),
+        };
+        let mut all: Vec<DiagSocket> = sockets.into_iter().collect();
+        all.push(connection("10.0.0.5", traffic(100, 10, 0)));
+        all.push(connection("10.0.0.5", traffic(50, 5, 2)));
+        all.push(connection("2001:db8::5", traffic(7, 7, 0)));
+        let stats = listener_stats(&all);
+        let v4 = stats[&(BindAddr::AnyV4, 80)];
+        assert!(v4.full());
+        assert_eq!(
+            v4.traffic,
+            Some(Traffic {
+                connections: 2,
+                bytes_sent: 150,
+                bytes_received: 15,
+                retransmits: 2
+            })
+        );
+        let v6 = stats[&(BindAddr::AnyV6, 80)];
+        assert!(!v6.full());
+        assert_eq!(v6.traffic.unwrap().connections, 1);
+    }
+
+    #[test]
+    fn dump_finds_own_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // netlink may be unavailable in a sandbox
+        let Ok(sockets) = dump(1 << TCP_LISTEN) else {
+            return;
+        };
+        let own = sockets.iter().find(|s| s.port == port).unwrap();
+        assert_eq!(own.addr, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(own.rqueue, 0);
+        assert!(own.wqueue > 0);
+        assert!(own.inode > 0);
+    }
+}
@@ -0,0 +1,235 @@
+use std::process::{Command, Stdio};
+
+use crate::cgroup;
+use crate::ports::PortEntry;
+
+/// A systemd service, either system-wide or in a user's manager.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unit {
+    pub name: String,
+    /// The uid whose manager runs it, for services under
+    /// `user@<uid>.service`, controlled with `systemctl --user`.
+    pub user: Option<u32>,
+}
+
+impl Unit {
+    pub fn label(&self) -> String {
+        if self.user.is_some() {
+            format!("{} (user)", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnitAction {
+    Stop,
+    Restart,
+    Status,
+}
+
+impl UnitAction {
+    pub fn verb(self) -> &'static str {
+        match self {
+            UnitAction::Stop => "stop",
+            UnitAction::Restart => "restart",
+            UnitAction::Status => "status",
+        }
+    }
+}
+
+/// Service owning a process with the given cgroup path. Scopes (login
+/// sessions, containers) aren't services and yield `None`, as does the
+/// user manager itself.
+pub fn unit_from_cgroup_path(path: &str) -> Option<Unit> {
+    let mut user = None;
+    let mut unit = None;
+    for segment in path.split('/') {
+        if let Some(uid) = user_manager_uid(segment) {
+            user = Some(uid);
+        } else if segment.ends_with(".service") {
+            unit = Some(Unit {
+                name: segment.to_string(),
+                user,
+            });
+        }
+    }
+    unit
+}
+
+fn user_manager_uid(segment: &str) -> Option<u32> {
+    let uid = segment.strip_prefix("user@")?.strip_suffix(".service")?;
+    if uid.is_empty() || !uid.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    uid.parse().ok()
+}
+
+/// Sets `unit` on entries whose process belongs to a systemd service.
+/// Container processes are left alone; their runtime's unit isn't theirs.
+pub fn annotate(entries: &mut [PortEntry]) {
+    for entry in entries.iter_mut() {
+        entry.unit = if entry.container.is_some() {
+            None
+        } else {
+            cgroup::paths(entry.pid)
+                .iter()
+                .find_map(|p| unit_from_cgroup_path(p))
+        };
+    }
+}
+
+mod sys {
+    unsafe extern "C" {
+        pub fn geteuid() -> u32;
+    }
+}
+
+fn systemctl(unit: &Unit, action: UnitAction) -> Command {
+    let mut cmd = Command::new("systemctl");
+    // plain --user reaches only the manager of whoever runs portit
+    // SAFETY: geteuid can't fail and has no side effects.
+    match unit.user {
+        Some(uid) if uid != unsafe { sys::geteuid() } => {
+            cmd.args([format!("--machine={}@", uid).as_str(), "--user"]);
+        }
+        Some(_) => {
+            cmd.arg("--user");
+        }
+        None => {}
+    }
+    cmd.args(["--no-ask-password", "--no-pager", action.verb(), &unit.name])
+        .stdin(Stdio::null());
+    cmd
+}
+
+/// The systemctl command line for `action` on `unit`, for the history.
+pub fn command_line(unit: &Unit, action: UnitAction) -> String {
+    let cmd = systemctl(unit, action);
+    let mut line = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd
+        .get_args()
+        .filter(|a| !a.to_string_lossy().starts_with("--no-"))
+    {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    line
+}
+
+/// Stops or restarts `unit`, returning systemctl's complaint on failure.
+pub fn run_action(unit: &Unit, action: UnitAction) -> Result<(), String> {
+    let output = systemctl(unit, action)
+        .output()
+        .map_err(|e| format!("systemctl: {}", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr
+        .lines()
+        .next()
+        .unwrap_or("systemctl failed")
+        .to_string())
+}
+
+/// Output of `systemctl status`, which exits non-zero for inactive units
+/// but still prints them.
+pub fn status(unit: &Unit) -> Vec<String> {
+    match systemctl(unit, UnitAction::Status).output() {
+        Ok(output) => {
+            let text = if output.stdout.is_empty() {
+                output.stderr
+            } else {
+                output.stdout
+            };
+            String::from_utf8_lossy(&text)
+                .lines()
+                .map(|l| l.replace('\t', "    "))
+                .collect()
+        }
+        Err(e) => vec![format!("systemctl: {}", e)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_from_fixture(content: &str) -> Option<Unit> {
+        cgroup::parse(content)
+            .iter()
+            .find_map(|p| unit_from_cgroup_path(p))
+    }
+
+    #[test]
+    fn system_service() {
+        let unit = unit_from_fixture(include_str!("../fixtures/cgroup/systemd-service.txt"));
+        assert_eq!(
+            unit,
+            Some(Unit {
+                name: "nginx.service".to_string(),
+                user: None,
+            })
+        );
+    }
+
+    #[test]
+    fn user_service() {
+        let unit = unit_from_fixture(include_str!("../fixtures/cgroup/systemd-user-service.txt"));
+        assert_eq!(
+            unit,
+            Some(Unit {
+                name: "vite-dev.service".to_string(),
+                user: Some(1000),
+            })
+        );
+        assert_eq!(unit.unwrap().label(), "vite-dev.service (user)");
+    }
+
+    #[test]
+    fn other_users_manager_is_named() {
+        let unit = |user| Unit {
+            name: "vite-dev.service".to_string(),
+            user,
+        };
+        // SAFETY: see `systemctl`.
+        let euid = unsafe { sys::geteuid() };
+        assert_eq!(
+            command_line(&unit(None), UnitAction::Stop),
+            "systemctl stop vite-dev.service"
+        );
+        assert_eq!(
+            command_line(&unit(Some(euid)), UnitAction::Stop),
+            "systemctl --user stop vite-dev.service"
+        );
+        assert_eq!(
+            command_line(&unit(Some(euid + 1)), UnitAction::Restart),
+            format!(
+                "systemctl --machine={}@ --user restart vite-dev.service",
+                euid + 1
+            )
+        );
+    }
+
+    #[test]
+    fn template_instance() {
+        assert_eq!(
+            unit_from_cgroup_path("/system.slice/postgresql@16-main.service")
+                .unwrap()
+                .name,
+            "postgresql@16-main.service"
+        );
+    }
+
+    #[test]
+    fn scopes_are_not_units() {
+        assert!(unit_from_fixture(include_str!("../fixtures/cgroup/host-process.txt")).is_none());
+        assert!(unit_from_fixture(include_str!("../fixtures/cgroup/docker-v2.txt")).is_none());
+        assert!(
+            unit_from_cgroup_path("/user.slice/user-1000.slice/user@1000.service/init.scope")
+                .is_none()
+        );
+    }
+}
@@ -8,40 +8,86 @@ use crossterm::{
     terminal::{self, Clear, ClearType},
 };
 
-use crate::app::{ACTIONS, ActionMenu, App};
+use crate::app::keymap::{self, Context};
+use crate::app::{ActionMenu, App, Column, InfoPopup, Row};
+use crate::exposure::{self, Exposure};
+use crate::history::Event;
+use crate::ports::{PortEntry, SocketStats};
 
 const PID_W: usize = 8;
 const PROC_W: usize = 14;
 const PROTO_W: usize = 6;
 const ADDR_W: usize = 18;
+const EXPOSURE_W: usize = 10;
 const PORT_W: usize = 6;
-const FIXED_W: usize = 1 + PID_W + PROC_W + PROTO_W + ADDR_W + PORT_W;
+const SERVICE_W: usize = 12;
+const CONTAINER_W: usize = 24;
+const UNIT_W: usize = 22;
+const NETNS_W: usize = 17;
+const CPU_W: usize = 15;
+const RSS_W: usize = 15;
+const MIN_CMD_W: usize = 8;
+/// Columns left out, first to last, when the terminal is too narrow for
+/// all of them. PID, Port and Command always stay.
+const DROP_ORDER: [Column; 10] = [
+    Column::Proto,
+    Column::Service,
+    Column::Unit,
+    Column::Rss,
+    Column::Cpu,
+    Column::Container,
+    Column::Netns,
+    Column::Address,
+    Column::Exposure,
+    Column::Process,
+];
 const FILTER_HELP: &str = " Type to filter \u{00b7} Enter to apply \u{00b7} Esc to cancel";
-const MAIN_HELP: &str = " q quit \u{00b7} j/k nav \u{00b7} Enter select \u{00b7} / filter \u{00b7} K kill \u{00b7} F force \u{00b7} r refresh";
+const PORT_HELP: &str = " Type a port number \u{00b7} Enter to jump \u{00b7} Esc to cancel";
+const MAIN_HELP: &str =
+    " q quit \u{00b7} j/k nav \u{00b7} Enter select \u{00b7} / filter \u{00b7} ? help";
+const HELP_HELP: &str = " j/k scroll \u{00b7} ? / q / Esc close";
+const HISTORY_HELP: &str = " j/k scroll \u{00b7} H / q / Esc close";
+const NO_HISTORY: &str =
+    "   Nothing yet. Listeners that open or close between refreshes, and every kill, show up here.";
+const HELP_KEYS_W: usize = 18;
 
 pub fn render(w: &mut impl Write, app: &App) -> io::Result<()> {
     let (cols, _) = terminal::size()?;
-    let cols = cols as usize;
+    draw(w, app, cols as usize)
+}
 
-    if cols < FIXED_W + 8 {
+fn draw(w: &mut impl Write, app: &App, cols: usize) -> io::Result<()> {
+    let columns = fit_columns(&app.columns, cols);
+    let fixed_w = fixed_width(&columns);
+    if cols < fixed_w + MIN_CMD_W {
         return Ok(());
     }
 
-    let cmd_w = cols.saturating_sub(FIXED_W).max(8);
+    let cmd_w = cols - fixed_w;
 
     queue!(w, cursor::MoveTo(0, app.start_row))?;
 
     render_header(w, cols, app)?;
-    render_col_headers(w, cols, cmd_w)?;
-    render_rows(w, cols, cmd_w, app)?;
+    if app.show_help {
+        render_help(w, cols, app)?;
+        render_footer(w, cols, app)?;
+        return w.flush();
+    }
+    if app.show_history {
+        render_history(w, cols, app)?;
+        render_footer(w, cols, app)?;
+        return w.flush();
+    }
+    render_col_headers(w, cols, &columns, cmd_w, app)?;
+    render_rows(w, cols, &columns, cmd_w, app)?;
     render_footer(w, cols, app)?;
 
-    let sel_y = app.start_row as usize + 2 + app.selected - app.scroll_offset;
-
-    if let Some((pid, ref name)) = app.confirm_kill {
-        render_confirm_popup(w, cols, sel_y, app.confirm_force, pid, name)?;
+    if let Some(confirm) = &app.confirm {
+        render_confirm_popup(w, cols, app, &confirm.prompt())?;
     } else if let Some(menu) = &app.action_menu {
-        render_action_popup(w, cols, sel_y, menu)?;
+        render_action_popup(w, cols, app, menu)?;
+    } else if let Some(info) = &app.info {
+        render_info_popup(w, cols, app, info)?;
     }
 
     w.flush()
@@ -50,10 +96,62 @@ pub fn render(w: &mut impl Write, app: &App) -> io::Result<()> {
 fn render_header(w: &mut impl Write, cols: usize, app: &App) -> io::Result<()> {
     let title = if let Some(msg) = &app.status_msg {
         format!(" portit \u{2014} {}", msg)
+    } else if app.show_help {
+        " portit \u{2014} keybindings".to_string()
+    } else if app.show_history {
+        format!(
+            " portit \u{2014} history \u{00b7} {} events",
+            app.history.len()
+        )
     } else if app.filter_mode {
         format!(" portit \u{2014} filter: {}\u{258c}", app.filter)
+    } else if let Some(input) = &app.port_prompt {
+        format!(" portit \u{2014} go to port: {}\u{258c}", input)
+    } else if let Some(count) = app.pending_count {
+        format!(
+            " portit \u{2014} {} ports \u{00b7} {}",
+            app.filtered_entries.len(),
+            count
+        )
     } else if !app.filter.is_empty() {
         format!(" portit \u{2014} filter: [{}]", app.filter)
+    } else if let Some(exposure) = app.exposure_filter {
+        format!(
+            " portit \u{2014} {} ports \u{00b7} exposure {}",
+            app.filtered_entries.len(),
+            exposure.badge()
+        )
+    } else if let Some(snapshot) = &app.snapshot {
+        format!(
+            " portit \u{2014} {} ports \u{00b7} snapshot {} \u{00b7} {} {}",
+            app.filtered_entries.len(),
+            snapshot.file,
+            snapshot.host,
+            snapshot.taken
+        )
+    } else if let Some(host) = &app.remote_host {
+        format!(
+            " portit \u{2014} {} ports \u{00b7} host {}",
+            app.filtered_entries.len(),
+            host
+        )
+    } else if let Some(scope) = app.netns_scope() {
+        format!(
+            " portit \u{2014} {} ports \u{00b7} netns {}",
+            app.filtered_entries.len(),
+            scope.label()
+        )
+    } else if app.grouped {
+        let processes = app
+            .rows
+            .iter()
+            .filter(|r| matches!(r, Row::Process { .. }))
+            .count();
+        format!(
+            " portit \u{2014} {} ports \u{00b7} {} processes",
+            app.filtered_entries.len(),
+            processes
+        )
     } else {
         format!(" portit \u{2014} {} ports", app.filtered_entries.len())
     };
@@ -69,8 +167,27 @@ fn render_header(w: &mut impl Write, cols: usize, app: &App) -> io::Result<()> {
     )
 }
 
-fn render_col_headers(w: &mut impl Write, cols: usize, cmd_w: usize) -> io::Result<()> {
-    let line = format_row("PID", "Process", "Proto", "Address", "Port", "Command", cmd_w);
+fn render_col_headers(
+    w: &mut impl Write,
+    cols: usize,
+    columns: &[Column],
+    cmd_w: usize,
+    app: &App,
+) -> io::Result<()> {
+    let titles: Vec<String> = columns
+        .iter()
+        .map(|&column| {
+            let name = column_title(column);
+            if app.sort.column != column {
+                name.to_string()
+            } else if app.sort.descending {
+                format!("{name}\u{25bc}")
+            } else {
+                format!("{name}\u{25b2}")
+            }
+        })
+        .collect();
+    let line = format_row(columns, &titles, cmd_w);
 
     queue!(
         w,
@@ -84,24 +201,33 @@ fn render_col_headers(w: &mut impl Write, cols: usize, cmd_w: usize) -> io::Resu
     )
 }
 
-fn render_rows(w: &mut impl Write, cols: usize, cmd_w: usize, app: &App) -> io::Result<()> {
+fn render_rows(
+    w: &mut impl Write,
+    cols: usize,
+    columns: &[Column],
+    cmd_w: usize,
+    app: &App,
+) -> io::Result<()> {
     let visible = app.visible_rows;
-    let end = (app.scroll_offset + visible).min(app.filtered_entries.len());
+    let end = (app.scroll_offset + visible).min(app.rows.len());
+    let badge_start = column_start(columns, Column::Exposure, cmd_w);
 
     for i in app.scroll_offset..end {
-        let idx = app.filtered_entries[i];
-        let e = &app.entries[idx];
-        let line = format_row(
-            &e.pid.to_string(),
-            &e.process_name,
-            "TCP",
-            &e.address,
-            &e.port.to_string(),
-            &e.command,
-            cmd_w,
-        );
+        let RowView {
+            cells,
+            marker,
+            flagged,
+            exposure,
+        } = row_view(app, columns, &app.rows[i]);
+        let mut line = format_row(columns, &cells, cmd_w);
+        line.replace_range(..1, marker);
+        let badge = badge_start.map(|start| Badge {
+            start,
+            width: EXPOSURE_W,
+            color: exposure_color(exposure),
+        });
 
-        render_row_line(w, cols, &line, i == app.selected)?;
+        render_row_line(w, cols, &line, i == app.selected, flagged, badge)?;
     }
 
     // clear leftover rows if entries < visible
@@ -112,52 +238,391 @@ fn render_rows(w: &mut impl Write, cols: usize, cmd_w: usize, app: &App) -> io::
     Ok(())
 }
 
+/// What a table row shows, before layout.
+struct RowView {
+    cells: Vec<String>,
+    /// Drawn in the leading column: `!` for policy violations, or the
+    /// expand arrow of a process row.
+    marker: &'static str,
+    flagged: bool,
+    exposure: Exposure,
+}
+
+fn row_view(app: &App, columns: &[Column], row: &Row) -> RowView {
+    match row {
+        Row::Entry(idx) => {
+            let e = &app.entries[*idx];
+            let flagged = !e.violations.is_empty() || e.backlog_full();
+            RowView {
+                cells: columns
+                    .iter()
+                    .map(|&c| match c {
+                        // the process row above already says whose it is
+                        Column::Pid | Column::Process if app.grouped => String::new(),
+                        _ => cell_text(e, c),
+                    })
+                    .collect(),
+                marker: if flagged {
+                    "!"
+                } else if app.sockets_expanded(e) {
+                    "\u{25be}"
+                } else if e.sockets.len() > 1 {
+                    "\u{25b8}"
+                } else {
+                    " "
+                },
+                flagged,
+                exposure: e.exposure(),
+            }
+        }
+        Row::Socket { entry, socket } => {
+            let entry = &app.entries[*entry];
+            let socket = &entry.sockets[*socket];
+            let exposure = exposure::classify(&socket.address, entry.reach);
+            let full = socket.stats.is_some_and(|s| s.full());
+            RowView {
+                cells: columns
+                    .iter()
+                    .map(|&c| match c {
+                        Column::Address => socket.address.to_string(),
+                        Column::Exposure => exposure.badge().to_string(),
+                        Column::Command => {
+                            let fd = socket.fd.map(|fd| format!("fd {}", fd));
+                            let queue = socket.stats.as_ref().map(SocketStats::queue_label);
+                            [fd, queue]
+                                .into_iter()
+                                .flatten()
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        }
+                        _ => String::new(),
+                    })
+                    .collect(),
+                marker: if full { "!" } else { " " },
+                flagged: full,
+                exposure,
+            }
+        }
+        Row::Process {
+            entries, expanded, ..
+        } => {
+            let members: Vec<&PortEntry> = entries.iter().map(|&i| &app.entries[i]).collect();
+            let exposure = members.iter().map(|e| e.exposure()).max();
+            RowView {
+                cells: columns
+                    .iter()
+                    .map(|&c| process_cell_text(&members, c))
+                    .collect(),
+                marker: if *expanded { "\u{25be}" } else { "\u{25b8}" },
+                flagged: members
+                    .iter()
+                    .any(|e| !e.violations.is_empty() || e.backlog_full()),
+                exposure: exposure.unwrap_or(Exposure::Loopback),
+            }
+        }
+    }
+}
+
 fn render_footer(w: &mut impl Write, cols: usize, app: &App) -> io::Result<()> {
     queue!(w, Clear(ClearType::CurrentLine))?;
-    let text = if app.filter_mode { FILTER_HELP } else { MAIN_HELP };
+    let text = if app.show_help {
+        HELP_HELP
+    } else if app.show_history {
+        HISTORY_HELP
+    } else if app.filter_mode {
+        FILTER_HELP
+    } else if app.port_prompt.is_some() {
+        PORT_HELP
+    } else if let Some(warning) = selected_warning(app) {
+        return render_status_line(w, cols, &format!(" ! {}", warning));
+    } else {
+        MAIN_HELP
+    };
     render_status_line(w, cols, text)
 }
 
-fn format_row(
-    pid: &str,
-    proc: &str,
-    proto: &str,
-    addr: &str,
-    port: &str,
-    cmd: &str,
-    cmd_w: usize,
-) -> String {
-    format!(
-        " {pid:<PID_W$}{proc:<PROC_W$}{proto:<PROTO_W$}{addr:<ADDR_W$}{port:<PORT_W$}{cmd:<cw$}",
-        pid = truncate(pid, PID_W),
-        proc = truncate(proc, PROC_W),
-        proto = truncate(proto, PROTO_W),
-        addr = truncate(addr, ADDR_W),
-        port = truncate(port, PORT_W),
-        cmd = truncate(cmd, cmd_w),
-        cw = cmd_w,
-    )
+/// The first policy rule the selected listener breaks, or its full queue.
+fn selected_warning(app: &App) -> Option<String> {
+    let entry = app.selected_entry()?;
+    if let Some(violation) = entry.violations.first() {
+        return Some(format!("breaks {}", violation));
+    }
+    let stats = entry
+        .sockets
+        .iter()
+        .filter_map(|s| s.stats)
+        .find(|s| s.full())?;
+    Some(format!(
+        "accept queue full ({}), new connections are being dropped",
+        stats.queue_label()
+    ))
+}
+
+pub enum HelpLine {
+    Heading(&'static str),
+    Binding(String, &'static str),
+    Note(&'static str),
+    Blank,
+}
+
+/// The help overlay's content, built from the same table `handle_key` uses.
+pub fn help_lines() -> Vec<HelpLine> {
+    let mut lines = Vec::new();
+    for context in Context::ALL {
+        if !lines.is_empty() {
+            lines.push(HelpLine::Blank);
+        }
+        lines.push(HelpLine::Heading(context.title()));
+        for binding in keymap::bindings(context) {
+            lines.push(HelpLine::Binding(binding.keys_label(), binding.desc));
+        }
+        if let Some(note) = context.fallback() {
+            lines.push(HelpLine::Note(note));
+        }
+        if let Some(note) = context.mouse() {
+            lines.push(HelpLine::Note(note));
+        }
+    }
+    lines
+}
+
+fn render_help(w: &mut impl Write, cols: usize, app: &App) -> io::Result<()> {
+    let lines = help_lines();
+    let body = app.visible_rows + 1;
+    let scroll = app.help_scroll.min(lines.len().saturating_sub(body));
+
+    for i in 0..body {
+        queue!(w, Clear(ClearType::CurrentLine))?;
+        match lines.get(scroll + i) {
+            Some(HelpLine::Heading(title)) => queue!(
+                w,
+                SetForegroundColor(Color::Yellow),
+                SetAttribute(Attribute::Bold),
+                Print(pad_line(&format!(" {}", title), cols)),
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+            )?,
+            Some(HelpLine::Binding(keys, desc)) => {
+                let line = format!("   {:<HELP_KEYS_W$}{}", truncate(keys, HELP_KEYS_W), desc);
+                queue!(w, Print(pad_line(&line, cols)))?;
+            }
+            Some(HelpLine::Note(note)) => queue!(
+                w,
+                SetForegroundColor(Color::DarkGrey),
+                Print(pad_line(&format!("   {}", note), cols)),
+                ResetColor,
+            )?,
+            Some(HelpLine::Blank) | None => {}
+        }
+        queue!(w, cursor::MoveToNextLine(1))?;
+    }
+    Ok(())
+}
+
+/// Newest event first, failures in red.
+fn render_history(w: &mut impl Write, cols: usize, app: &App) -> io::Result<()> {
+    let body = app.visible_rows + 1;
+    let scroll = app
+        .history_scroll
+        .min(app.history.len().saturating_sub(body));
+    let mut records = app.history.newest_first().skip(scroll);
+
+    for i in 0..body {
+        queue!(w, Clear(ClearType::CurrentLine))?;
+        match records.next() {
+            Some(record) => {
+                let color = match record.event {
+                    _ if record.event.failed() => Color::Red,
+                    Event::Opened(_) => Color::Green,
+                    Event::Closed(_) => Color::DarkYellow,
+                    _ => Color::Reset,
+                };
+                queue!(
+                    w,
+                    SetForegroundColor(color),
+                    Print(pad_line(&format!(" {}", record.line()), cols)),
+                    ResetColor,
+                )?;
+            }
+            None if i == 0 => queue!(
+                w,
+                SetForegroundColor(Color::DarkGrey),
+                Print(pad_line(NO_HISTORY, cols)),
+                ResetColor,
+            )?,
+            None => {}
+        }
+        queue!(w, cursor::MoveToNextLine(1))?;
+    }
+    Ok(())
+}
+
+fn column_title(column: Column) -> &'static str {
+    match column {
+        Column::Pid => "PID",
+        Column::Process => "Process",
+        Column::Proto => "Proto",
+        Column::Address => "Address",
+        Column::Exposure => "Exposure",
+        Column::Port => "Port",
+        Column::Service => "Service",
+        Column::Container => "Container",
+        Column::Unit => "Unit",
+        Column::Netns => "Netns",
+        Column::Cpu => "CPU%",
+        Column::Rss => "RSS",
+        Column::Command => "Command",
+    }
+}
+
+/// Width of a fixed column; `Command` takes whatever is left.
+fn column_width(column: Column, cmd_w: usize) -> usize {
+    match column {
+        Column::Pid => PID_W,
+        Column::Process => PROC_W,
+        Column::Proto => PROTO_W,
+        Column::Address => ADDR_W,
+        Column::Exposure => EXPOSURE_W,
+        Column::Port => PORT_W,
+        Column::Service => SERVICE_W,
+        Column::Container => CONTAINER_W,
+        Column::Unit => UNIT_W,
+        Column::Netns => NETNS_W,
+        Column::Cpu => CPU_W,
+        Column::Rss => RSS_W,
+        Column::Command => cmd_w,
+    }
+}
+
+fn fixed_width(columns: &[Column]) -> usize {
+    1 + columns.iter().map(|&c| column_width(c, 0)).sum::<usize>()
+}
+
+/// The columns shown `cols` wide, leaving out optional ones until the
+/// Command column gets at least its minimum width.
+fn fit_columns(columns: &[Column], cols: usize) -> Vec<Column> {
+    let mut fitted = columns.to_vec();
+    for column in DROP_ORDER {
+        if fixed_width(&fitted) + MIN_CMD_W <= cols {
+            break;
+        }
+        fitted.retain(|&c| c != column);
+    }
+    fitted
+}
+
+fn cell_text(e: &PortEntry, column: Column) -> String {
+    match column {
+        Column::Pid => e.pid.to_string(),
+        Column::Process => e.process_name.clone(),
+        Column::Proto => "TCP".to_string(),
+        Column::Address => e.addresses_label(),
+        Column::Exposure => e.exposure().badge().to_string(),
+        Column::Port => e.port.to_string(),
+        Column::Service => e.service.clone().unwrap_or_default(),
+        Column::Container => e.container.as_ref().map(|c| c.label()).unwrap_or_default(),
+        Column::Unit => e.unit.as_ref().map(|u| u.label()).unwrap_or_default(),
+        Column::Netns => e.netns.clone().unwrap_or_default(),
+        Column::Cpu => e.usage.as_ref().map(|u| u.cpu_label()).unwrap_or_default(),
+        Column::Rss => e.usage.as_ref().map(|u| u.rss_label()).unwrap_or_default(),
+        Column::Command => e.command.clone(),
+    }
+}
+
+/// A process row of the grouped view: what its listeners share, their
+/// count in place of an address, and the widest exposure.
+fn process_cell_text(members: &[&PortEntry], column: Column) -> String {
+    let first = members[0];
+    match column {
+        Column::Address => match members.len() {
+            1 => "1 port".to_string(),
+            n => format!("{} ports", n),
+        },
+        Column::Exposure => members
+            .iter()
+            .map(|e| e.exposure())
+            .max()
+            .map(|x| x.badge().to_string())
+            .unwrap_or_default(),
+        Column::Port | Column::Service => String::new(),
+        _ => cell_text(first, column),
+    }
+}
+
+fn format_row(columns: &[Column], cells: &[String], cmd_w: usize) -> String {
+    let mut line = String::from(" ");
+    for (&column, cell) in columns.iter().zip(cells) {
+        let width = column_width(column, cmd_w);
+        line.push_str(&format!("{:<width$}", truncate(cell, width)));
+    }
+    line
 }
 
 fn truncate(s: &str, max: usize) -> &str {
-    if s.len() <= max {
-        s
-    } else {
-        &s[..max]
+    match s.char_indices().nth(max) {
+        Some((i, _)) => &s[..i],
+        None => s,
     }
 }
 
 fn pad_line(s: &str, width: usize) -> String {
-    if s.len() >= width {
-        s[..width].to_string()
-    } else {
-        format!("{s:<width$}")
+    format!("{:<width$}", truncate(s, width))
+}
+
+/// A colored cell within an otherwise plain row, in characters.
+struct Badge {
+    start: usize,
+    width: usize,
+    color: Color,
+}
+
+fn exposure_color(exposure: Exposure) -> Color {
+    match exposure {
+        Exposure::Loopback => Color::Green,
+        Exposure::LinkLocal => Color::Cyan,
+        Exposure::Private => Color::Yellow,
+        Exposure::Public => Color::Magenta,
+        Exposure::All => Color::Red,
     }
 }
 
-fn render_row_line(w: &mut impl Write, cols: usize, line: &str, selected: bool) -> io::Result<()> {
+/// Where `column` starts in a row, after the leading marker.
+fn column_start(columns: &[Column], column: Column, cmd_w: usize) -> Option<usize> {
+    let i = columns.iter().position(|c| *c == column)?;
+    Some(
+        1 + columns[..i]
+            .iter()
+            .map(|&c| column_width(c, cmd_w))
+            .sum::<usize>(),
+    )
+}
+
+fn render_row_line(
+    w: &mut impl Write,
+    cols: usize,
+    line: &str,
+    selected: bool,
+    flagged: bool,
+    badge: Option<Badge>,
+) -> io::Result<()> {
     queue!(w, Clear(ClearType::CurrentLine))?;
-    if selected {
+    if flagged {
+        queue!(w, SetForegroundColor(Color::Red))?;
+    }
+    if let Some(badge) = badge.filter(|_| !selected && !flagged) {
+        let padded = pad_line(line, cols);
+        let chars: Vec<char> = padded.chars().collect();
+        let start = badge.start.min(chars.len());
+        let end = (badge.start + badge.width).min(chars.len());
+        queue!(
+            w,
+            Print(chars[..start].iter().collect::<String>()),
+            SetForegroundColor(badge.color),
+            Print(chars[start..end].iter().collect::<String>()),
+            ResetColor,
+            Print(chars[end..].iter().collect::<String>()),
+        )?;
+    } else if selected {
         queue!(
             w,
             SetAttribute(Attribute::Reverse),
@@ -167,7 +632,7 @@ fn render_row_line(w: &mut impl Write, cols: usize, line: &str, selected: bool)
     } else {
         queue!(w, Print(pad_line(line, cols)))?;
     }
-    queue!(w, cursor::MoveToNextLine(1))
+    queue!(w, ResetColor, cursor::MoveToNextLine(1))
 }
 
 fn render_status_line(w: &mut impl Write, cols: usize, text: &str) -> io::Result<()> {
@@ -194,6 +659,116 @@ fn popup_layout(cols: usize, sel_y: usize, inner_w: usize) -> PopupLayout {
     }
 }
 
+/// Screen area covered by a popup, border included.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+}
+
+impl Rect {
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+}
+
+fn selected_row_y(app: &App) -> usize {
+    app.start_row as usize + 2 + app.selected.saturating_sub(app.scroll_offset)
+}
+
+/// Index into `rows` of the table row drawn at screen row `y`.
+pub fn row_at(app: &App, y: u16) -> Option<usize> {
+    let top = app.start_row as usize + 2;
+    let y = y as usize;
+    if y < top || y >= top + app.visible_rows {
+        return None;
+    }
+    let index = app.scroll_offset + (y - top);
+    (index < app.rows.len()).then_some(index)
+}
+
+/// Sortable column whose header is drawn at `(x, y)`.
+pub fn column_at(app: &App, cols: usize, x: u16, y: u16) -> Option<Column> {
+    if y as usize != app.start_row as usize + 1 {
+        return None;
+    }
+    let mut start = 1;
+    for column in fit_columns(&app.columns, cols) {
+        if (x as usize) < start {
+            return None;
+        }
+        let width = column_width(column, usize::MAX - start);
+        if (x as usize) < start + width {
+            return column.sortable().then_some(column);
+        }
+        start += width;
+    }
+    None
+}
+
+fn action_popup_width(menu: &ActionMenu) -> usize {
+    menu.items
+        .iter()
+        .map(|a| a.label().len() + 4)
+        .max()
+        .unwrap_or(16)
+}
+
+pub fn action_popup_rect(app: &App, cols: usize) -> Rect {
+    let Some(menu) = &app.action_menu else {
+        return Rect {
+            x: 0,
+            y: 0,
+            w: 0,
+            h: 0,
+        };
+    };
+    let inner_w = action_popup_width(menu);
+    let sel_y = selected_row_y(app);
+    let layout = popup_layout(cols, sel_y, inner_w);
+    let h = action_menu_window(app, menu).1 + 2;
+    Rect {
+        x: layout.x,
+        y: popup_y(app, sel_y, h),
+        w: (inner_w + 2) as u16,
+        h: h as u16,
+    }
+}
+
+/// The first action menu item shown and how many fit in the app's area,
+/// scrolled to keep the selection in view.
+fn action_menu_window(app: &App, menu: &ActionMenu) -> (usize, usize) {
+    let shown = menu.items.len().min(app.height.saturating_sub(2).max(1));
+    (menu.selected.saturating_sub(shown.saturating_sub(1)), shown)
+}
+
+/// Places a popup below the selected row, or above it when it would run
+/// past the bottom of the app's area, but never outside the area: inline,
+/// anything drawn beyond it is left behind in the scrollback.
+fn popup_y(app: &App, sel_y: usize, rows: usize) -> u16 {
+    let top = app.start_row as usize;
+    let end = top + app.height;
+    let below = sel_y + 1;
+    let y = if below + rows > end && sel_y >= rows {
+        sel_y - rows
+    } else {
+        below
+    };
+    y.min(end.saturating_sub(rows)).max(top) as u16
+}
+
+/// Action menu item drawn at `(x, y)`, ignoring the border.
+pub fn action_item_at(app: &App, cols: usize, x: u16, y: u16) -> Option<usize> {
+    let rect = action_popup_rect(app, cols);
+    if !rect.contains(x, y) || y == rect.y || y == rect.y + rect.h - 1 {
+        return None;
+    }
+    let (first, _) = action_menu_window(app, app.action_menu.as_ref()?);
+    Some(first + (y - rect.y - 1) as usize)
+}
+
 fn popup_top(h_bar: &str) -> String {
     format!("\u{250c}{h_bar}\u{2510}")
 }
@@ -205,14 +780,13 @@ fn popup_bottom(h_bar: &str) -> String {
 fn render_confirm_popup(
     w: &mut impl Write,
     cols: usize,
-    sel_y: usize,
-    force: bool,
-    pid: u32,
-    name: &str,
+    app: &App,
+    prompt: &str,
 ) -> io::Result<()> {
-    let sig = if force { "SIGKILL" } else { "SIGTERM" };
-    let msg = format!(" Kill {} (PID {}) with {}? [y/n] ", name, pid, sig);
-    let layout = popup_layout(cols, sel_y, msg.len());
+    let msg = format!(" {} [y/n] ", prompt);
+    let sel_y = selected_row_y(app);
+    let mut layout = popup_layout(cols, sel_y, msg.chars().count());
+    layout.y = popup_y(app, sel_y, 3);
 
     queue!(
         w,
@@ -228,14 +802,79 @@ fn render_confirm_popup(
     )
 }
 
+fn render_info_popup(
+    w: &mut impl Write,
+    cols: usize,
+    app: &App,
+    info: &InfoPopup,
+) -> io::Result<()> {
+    let title = format!(" {} ", info.title);
+    let inner_w = info
+        .lines
+        .iter()
+        .map(|l| l.chars().count() + 2)
+        .chain([title.chars().count() + 2])
+        .max()
+        .unwrap_or(16)
+        .min(cols.saturating_sub(2));
+    // long output like `systemctl status` is cut to the app's area
+    let lines = fit_lines(&info.lines, app.height.saturating_sub(2).max(1));
+    let sel_y = selected_row_y(app);
+    let mut layout = popup_layout(cols, sel_y, inner_w);
+    layout.y = popup_y(app, sel_y, lines.len() + 2);
+
+    let top_bar = "\u{2500}".repeat(inner_w.saturating_sub(title.chars().count() + 1));
+    queue!(
+        w,
+        cursor::MoveTo(layout.x, layout.y),
+        SetForegroundColor(Color::Cyan),
+        SetBackgroundColor(Color::Black),
+        Print(truncate(
+            &format!("\u{250c}\u{2500}{title}{top_bar}\u{2510}"),
+            inner_w + 2
+        )),
+    )?;
+    for (i, line) in lines.iter().enumerate() {
+        queue!(
+            w,
+            cursor::MoveTo(layout.x, layout.y + 1 + i as u16),
+            Print(format!(
+                "\u{2502} {} \u{2502}",
+                pad_line(line, inner_w.saturating_sub(2))
+            )),
+        )?;
+    }
+    queue!(
+        w,
+        cursor::MoveTo(layout.x, layout.y + 1 + lines.len() as u16),
+        Print(popup_bottom(&layout.h_bar)),
+        SetAttribute(Attribute::Reset),
+    )
+}
+
+/// At most `max` lines, the last replaced by a count of what was cut.
+fn fit_lines(lines: &[String], max: usize) -> Vec<String> {
+    if lines.len() <= max {
+        return lines.to_vec();
+    }
+    let mut fitted = lines[..max - 1].to_vec();
+    fitted.push(format!("\u{2026} {} more lines", lines.len() - max + 1));
+    fitted
+}
+
 fn render_action_popup(
     w: &mut impl Write,
     cols: usize,
-    sel_y: usize,
+    app: &App,
     menu: &ActionMenu,
 ) -> io::Result<()> {
-    let inner_w = ACTIONS.iter().map(|a| a.len() + 4).max().unwrap_or(16);
-    let layout = popup_layout(cols, sel_y, inner_w);
+    let inner_w = action_popup_width(menu);
+    let rect = action_popup_rect(app, cols);
+    let layout = PopupLayout {
+        x: rect.x,
+        y: rect.y,
+        h_bar: "\u{2500}".repeat(inner_w),
+    };
 
     queue!(
         w,
@@ -245,13 +884,15 @@ fn render_action_popup(
         Print(popup_top(&layout.h_bar)),
     )?;
 
-    for (i, action) in ACTIONS.iter().enumerate() {
+    let (first, shown) = action_menu_window(app, menu);
+    let labels = menu.items.iter().map(|a| a.label()).enumerate();
+    for (row, (i, action)) in labels.skip(first).take(shown).enumerate() {
         let marker = if i == menu.selected {
             "\u{25b8} "
         } else {
             "  "
         };
-        queue!(w, cursor::MoveTo(layout.x, layout.y + 1 + i as u16))?;
+        queue!(w, cursor::MoveTo(layout.x, layout.y + 1 + row as u16))?;
         if i == menu.selected {
             queue!(
                 w,
@@ -277,7 +918,7 @@ fn render_action_popup(
 
     queue!(
         w,
-        cursor::MoveTo(layout.x, layout.y + 1 + ACTIONS.len() as u16),
+        cursor::MoveTo(layout.x, layout.y + 1 + shown as u16),
         Print(popup_bottom(&layout.h_bar)),
         SetAttribute(Attribute::Reset),
     )
@@ -324,20 +965,52 @@ mod tests {
         assert_eq!(pad_line("hello world", 5), "hello");
     }
 
+    fn cells(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
     #[test]
     fn format_row_basic() {
-        let row = format_row("1234", "node", "TCP", "127.0.0.1", "3000", "/usr/bin/node app.js", 24);
+        let row = format_row(
+            &Column::DEFAULT,
+            &cells(&[
+                "1234",
+                "node",
+                "TCP",
+                "127.0.0.1",
+                "local",
+                "3000",
+                "http",
+                "/usr/bin/node app.js",
+            ]),
+            24,
+        );
         assert!(row.contains("1234"));
         assert!(row.contains("node"));
         assert!(row.contains("TCP"));
         assert!(row.contains("127.0.0.1"));
+        assert!(row.contains("local"));
         assert!(row.contains("3000"));
+        assert!(row.contains("http"));
         assert!(row.contains("/usr/bin/node app.js"));
     }
 
     #[test]
     fn format_row_truncates_long_command() {
-        let row = format_row("1", "node", "TCP", "0.0.0.0", "80", "/a/very/long/command/path", 8);
+        let row = format_row(
+            &Column::DEFAULT,
+            &cells(&[
+                "1",
+                "node",
+                "TCP",
+                "0.0.0.0",
+                "all",
+                "80",
+                "",
+                "/a/very/long/command/path",
+            ]),
+            8,
+        );
         // cmd_w=8, so command should be truncated
         assert!(!row.contains("/a/very/long/command/path"));
     }
@@ -357,10 +1030,245 @@ mod tests {
         assert_eq!(layout.x, 0); // saturating_sub prevents underflow
     }
 
+    #[test]
+    fn truncate_multibyte() {
+        assert_eq!(truncate("Port\u{25b2}", 4), "Port");
+        assert_eq!(truncate("Port\u{25b2}", 5), "Port\u{25b2}");
+    }
+
+    #[test]
+    fn pad_line_multibyte() {
+        assert_eq!(pad_line("a\u{2014}b", 2), "a\u{2014}");
+        assert_eq!(pad_line("\u{2014}", 3).chars().count(), 3);
+    }
+
+    fn hit_app(n: usize) -> App {
+        let mut app = App::test_app(n);
+        app.start_row = 10;
+        app.visible_rows = 5;
+        app.height = 8;
+        app
+    }
+
+    #[test]
+    fn full_queue_flags_the_row() {
+        let mut app = App::test_app(2);
+        let stats = SocketStats {
+            queued: 4,
+            backlog: Some(4),
+            traffic: None,
+        };
+        app.entries[1].sockets = vec![crate::ports::Socket {
+            address: app.entries[1].address.clone(),
+            fd: None,
+            stats: Some(stats),
+        }];
+        assert!(!row_view(&app, &app.columns, &Row::Entry(0)).flagged);
+        let row = row_view(&app, &app.columns, &Row::Entry(1));
+        assert!(row.flagged);
+        assert_eq!(row.marker, "!");
+        app.selected = 1;
+        assert_eq!(
+            selected_warning(&app).unwrap(),
+            "accept queue full (4/4 queued), new connections are being dropped"
+        );
+    }
+
+    #[test]
+    fn row_at_maps_screen_rows() {
+        let mut app = hit_app(20);
+        assert_eq!(row_at(&app, 11), None); // column headers
+        assert_eq!(row_at(&app, 12), Some(0));
+        assert_eq!(row_at(&app, 16), Some(4));
+        assert_eq!(row_at(&app, 17), None); // footer
+        app.scroll_offset = 7;
+        assert_eq!(row_at(&app, 12), Some(7));
+    }
+
+    #[test]
+    fn row_at_past_last_entry() {
+        let app = hit_app(2);
+        assert_eq!(row_at(&app, 13), Some(1));
+        assert_eq!(row_at(&app, 14), None);
+    }
+
+    #[test]
+    fn default_columns_fit_80_wide() {
+        let app = App::test_app(2);
+        let mut out = Vec::new();
+        draw(&mut out, &app, 80).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("3001"));
+        assert!(out.contains("/usr/bin/pr"));
+        assert!(out.contains("Exposure"));
+        assert!(!out.contains("Proto"));
+        assert_eq!(fit_columns(&app.columns, 83), app.columns);
+    }
+
+    #[test]
+    fn narrow_terminal_drops_optional_columns() {
+        let mut columns = Column::DEFAULT.to_vec();
+        columns.insert(columns.len() - 1, Column::Container);
+        assert_eq!(
+            fit_columns(&columns, 40),
+            [Column::Pid, Column::Process, Column::Port, Column::Command]
+        );
+        assert_eq!(
+            fit_columns(&columns, 10),
+            [Column::Pid, Column::Port, Column::Command]
+        );
+    }
+
+    #[test]
+    fn column_at_header() {
+        let app = hit_app(1);
+        assert_eq!(column_at(&app, 200, 0, 11), None);
+        assert_eq!(column_at(&app, 200, 1, 11), Some(Column::Pid));
+        assert_eq!(
+            column_at(&app, 200, 1 + PID_W as u16, 11),
+            Some(Column::Process)
+        );
+        let proto_x = (1 + PID_W + PROC_W) as u16;
+        assert_eq!(column_at(&app, 200, proto_x, 11), None);
+        let service_x = proto_x + (PROTO_W + ADDR_W + EXPOSURE_W + PORT_W) as u16;
+        assert_eq!(column_at(&app, 200, service_x - 1, 11), Some(Column::Port));
+        assert_eq!(column_at(&app, 200, service_x, 11), Some(Column::Service));
+        let fixed_w = fixed_width(&app.columns) as u16;
+        assert_eq!(column_at(&app, 200, fixed_w + 5, 11), Some(Column::Command));
+        assert_eq!(column_at(&app, 200, 1, 12), None); // not the header row
+    }
+
+    #[test]
+    fn badge_starts_at_its_column() {
+        let start = column_start(&Column::DEFAULT, Column::Exposure, 20).unwrap();
+        let row = format_row(
+            &Column::DEFAULT,
+            &cells(&["1", "node", "TCP", "*", "all", "80", "", "node"]),
+            20,
+        );
+        assert_eq!(&row[start..start + 3], "all");
+        assert_eq!(column_start(&[Column::Port], Column::Exposure, 20), None);
+    }
+
+    #[test]
+    fn column_at_follows_visible_columns() {
+        let mut app = hit_app(1);
+        app.columns = vec![Column::Port, Column::Command];
+        assert_eq!(column_at(&app, 200, 1, 11), Some(Column::Port));
+        assert_eq!(
+            column_at(&app, 200, 1 + PORT_W as u16, 11),
+            Some(Column::Command)
+        );
+    }
+
+    #[test]
+    fn cell_text_service() {
+        let mut app = App::test_app(1);
+        assert_eq!(cell_text(&app.entries[0], Column::Service), "");
+        app.entries[0].service = Some("redis".to_string());
+        assert_eq!(cell_text(&app.entries[0], Column::Service), "redis");
+    }
+
+    #[test]
+    fn action_popup_below_when_it_fits() {
+        let mut app = hit_app(3);
+        app.height = 40;
+        app.open_menu_for_test();
+        assert_eq!(action_popup_rect(&app, 80).y, 13); // just below the selected row
+    }
+
+    #[test]
+    fn action_popup_flips_above_near_bottom() {
+        let mut app = hit_app(20);
+        app.start_row = 20;
+        app.height = 20; // room above for the whole menu
+        app.visible_rows = 17;
+        app.selected = 15;
+        app.open_menu_for_test();
+        let rect = action_popup_rect(&app, 80);
+        // ends right above the selected row
+        assert_eq!(rect.y as usize + rect.h as usize, selected_row_y(&app));
+    }
+
+    #[test]
+    fn action_popup_stays_in_the_app_area() {
+        let mut app = hit_app(3);
+        app.open_menu_for_test();
+        let rect = action_popup_rect(&app, 80);
+        assert_eq!((rect.y, rect.h), (10, 8));
+        // scrolled to keep the selection in view
+        let menu = app.action_menu.as_mut().unwrap();
+        menu.selected = menu.items.len() - 1;
+        let last = menu.selected;
+        assert_eq!(
+            action_item_at(&app, 80, rect.x + 2, rect.y + rect.h - 2),
+            Some(last)
+        );
+        assert_eq!(
+            action_item_at(&app, 80, rect.x + 2, rect.y + 1),
+            Some(last - 5)
+        );
+    }
+
+    #[test]
+    fn action_item_hit_test() {
+        let mut app = hit_app(3);
+        app.open_menu_for_test();
+        let rect = action_popup_rect(&app, 80);
+        assert_eq!(action_item_at(&app, 80, rect.x + 2, rect.y), None); // top border
+        assert_eq!(action_item_at(&app, 80, rect.x + 2, rect.y + 1), Some(0));
+        assert_eq!(action_item_at(&app, 80, rect.x + 2, rect.y + 2), Some(1));
+        let bottom = rect.y + rect.h - 1;
+        assert_eq!(
+            action_item_at(&app, 80, rect.x + 2, bottom - 1),
+            Some(rect.h as usize - 3)
+        );
+        assert_eq!(action_item_at(&app, 80, rect.x + 2, bottom), None);
+        assert_eq!(action_item_at(&app, 80, rect.x + rect.w, rect.y + 1), None);
+    }
+
+    #[test]
+    fn help_lists_every_binding() {
+        let lines = help_lines();
+        let bindings = lines
+            .iter()
+            .filter(|l| matches!(l, HelpLine::Binding(..)))
+            .count();
+        assert_eq!(bindings, keymap::BINDINGS.len());
+        let headings = lines
+            .iter()
+            .filter(|l| matches!(l, HelpLine::Heading(_)))
+            .count();
+        assert_eq!(headings, Context::ALL.len());
+        assert!(lines.iter().any(
+            |l| matches!(l, HelpLine::Note(note) if note.starts_with("Mouse, fullscreen only"))
+        ));
+    }
+
+    #[test]
+    fn help_mentions_action_menu_keys() {
+        let lines = help_lines();
+        let menu = lines
+            .iter()
+            .position(|l| matches!(l, HelpLine::Heading("Action menu")))
+            .unwrap();
+        assert!(matches!(&lines[menu + 1], HelpLine::Binding(keys, _) if keys.starts_with('j')));
+    }
+
     #[test]
     fn popup_borders() {
         let bar = "\u{2500}\u{2500}\u{2500}";
         assert_eq!(popup_top(bar), "\u{250c}\u{2500}\u{2500}\u{2500}\u{2510}");
-        assert_eq!(popup_bottom(bar), "\u{2514}\u{2500}\u{2500}\u{2500}\u{2518}");
+        assert_eq!(
+            popup_bottom(bar),
+            "\u{2514}\u{2500}\u{2500}\u{2500}\u{2518}"
+        );
+    }
+
+    #[test]
+    fn fit_lines_marks_overflow() {
+        let lines: Vec<String> = (0..5).map(|i| i.to_string()).collect();
+        assert_eq!(fit_lines(&lines, 5), lines);
+        assert_eq!(fit_lines(&lines, 3), ["0", "1", "\u{2026} 3 more lines"]);
     }
 }
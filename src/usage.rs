@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Samples kept per process for the sparklines.
+pub const HISTORY: usize = 8;
+
+const BARS: [char; 8] = [
+    '\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}', '\u{2588}',
+];
+
+/// A process's CPU and memory use at the latest sample, with the samples
+/// before it, oldest first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Usage {
+    /// Percent of one CPU since the previous sample, so above 100 for a
+    /// busy multithreaded process. `None` until there are two samples.
+    pub cpu: Option<f64>,
+    /// Resident set size in bytes.
+    pub rss: u64,
+    pub cpu_history: Vec<f64>,
+    pub rss_history: Vec<u64>,
+}
+
+impl Usage {
+    /// Like `12.5 ▁▂▅`, the percentage right-aligned so sparklines line up.
+    pub fn cpu_label(&self) -> String {
+        let top = self.cpu_history.iter().copied().fold(100.0, f64::max);
+        match self.cpu {
+            Some(cpu) => format!("{:>5.1} {}", cpu, sparkline(&self.cpu_history, top)),
+            None => format!("{:>5}", "-"),
+        }
+    }
+
+    /// Like `  48M ▃▄█`, scaled to the largest sample.
+    pub fn rss_label(&self) -> String {
+        let history: Vec<f64> = self.rss_history.iter().map(|&b| b as f64).collect();
+        let top = history.iter().copied().fold(0.0, f64::max);
+        format!("{:>5} {}", format_bytes(self.rss), sparkline(&history, top))
+    }
+}
+
+/// What one read of a process gives.
+#[derive(Debug, PartialEq)]
+struct Reading {
+    cpu: Cpu,
+    rss: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Cpu {
+    /// User plus system time from `/proc/<pid>/stat`, in clock ticks.
+    Ticks(u64),
+    /// Already a percentage, from `ps`.
+    Percent(f64),
+}
+
+/// CPU time of the whole system from `/proc/stat`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SystemTicks {
+    /// Summed over every CPU.
+    total: u64,
+    cpus: usize,
+}
+
+struct Tracked {
+    ticks: Option<u64>,
+    usage: Usage,
+}
+
+/// Keeps the recent usage of the processes it's asked about, dropping
+/// the ones that are gone.
+#[derive(Default)]
+pub struct Tracker {
+    system: Option<SystemTicks>,
+    processes: HashMap<u32, Tracked>,
+}
+
+impl Tracker {
+    /// Reads the current usage of `pids`, from procfs on Linux and `ps`
+    /// elsewhere.
+    pub fn sample(&mut self, pids: &[u32]) {
+        if Path::new("/proc/self/stat").exists() {
+            let readings = pids
+                .iter()
+                .filter_map(|&pid| Some((pid, read_proc(pid)?)))
+                .collect();
+            let system = fs::read_to_string("/proc/stat")
+                .ok()
+                .and_then(|s| parse_system_stat(&s));
+            self.record(readings, system);
+        } else {
+            self.record(read_ps(pids), None);
+        }
+    }
+
+    fn record(&mut self, readings: HashMap<u32, Reading>, system: Option<SystemTicks>) {
+        let elapsed = match (self.system, system) {
+            // ticks of one CPU, what a process using one fully accrues
+            (Some(before), Some(now)) if now.total > before.total => {
+                Some((now.total - before.total) as f64 / now.cpus.max(1) as f64)
+            }
+            _ => None,
+        };
+        self.processes.retain(|pid, _| readings.contains_key(pid));
+        for (pid, reading) in readings {
+            let tracked = self.processes.entry(pid).or_insert(Tracked {
+                ticks: None,
+                usage: Usage::default(),
+            });
+            let cpu = match reading.cpu {
+                Cpu::Percent(percent) => Some(percent),
+                Cpu::Ticks(ticks) => {
+                    let before = tracked.ticks.replace(ticks);
+                    match (before, elapsed) {
+                        (Some(before), Some(elapsed)) => {
+                            Some(ticks.saturating_sub(before) as f64 * 100.0 / elapsed)
+                        }
+                        _ => None,
+                    }
+                }
+            };
+            let usage = &mut tracked.usage;
+            usage.cpu = cpu;
+            usage.rss = reading.rss;
+            if let Some(cpu) = cpu {
+                push_capped(&mut usage.cpu_history, cpu);
+            }
+            push_capped(&mut usage.rss_history, reading.rss);
+        }
+        self.system = system;
+    }
+
+    pub fn get(&self, pid: u32) -> Option<&Usage> {
+        self.processes.get(&pid).map(|t| &t.usage)
+    }
+}
+
+fn push_capped<T>(history: &mut Vec<T>, value: T) {
+    if history.len() == HISTORY {
+        history.remove(0);
+    }
+    history.push(value);
+}
+
+fn read_proc(pid: u32) -> Option<Reading> {
+    let ticks = parse_pid_stat(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)?;
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    Some(Reading {
+        cpu: Cpu::Ticks(ticks),
+        // kernel threads have no VmRSS
+        rss: parse_status_rss(&status).unwrap_or(0),
+    })
+}
+
+/// utime + stime from `/proc/<pid>/stat`. The command name in parentheses
+/// may contain spaces, so fields are counted from the last `)`.
+fn parse_pid_stat(content: &str) -> Option<u64> {
+    let fields: Vec<&str> = content[content.rfind(')')? + 1..]
+        .split_whitespace()
+        .collect();
+    // fields 14 and 15 of proc_pid_stat(5), counting from state as 3
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// `VmRSS:   123456 kB` from `/proc/<pid>/status`, in bytes.
+fn parse_status_rss(content: &str) -> Option<u64> {
+    let line = content.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// The `cpu` line's busy and idle ticks, and the number of `cpuN` lines.
+fn parse_system_stat(content: &str) -> Option<SystemTicks> {
+    let mut total = None;
+    let mut cpus = 0;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            // user nice system idle iowait irq softirq steal; guest time
+            // is already counted in user
+            Some("cpu") => {
+                total = Some(fields.take(8).filter_map(|f| f.parse::<u64>().ok()).sum());
+            }
+            Some(name) if name.starts_with("cpu") => cpus += 1,
+            _ => {}
+        }
+    }
+    Some(SystemTicks {
+        total: total?,
+        cpus,
+    })
+}
+
+fn read_ps(pids: &[u32]) -> HashMap<u32, Reading> {
+    if pids.is_empty() {
+        return HashMap::new();
+    }
+    let list: Vec<String> = pids.iter().map(u32::to_string).collect();
+    // exits non-zero when some PID is gone, but still lists the rest
+    match Command::new("ps")
+        .args(["-o", "pid=,%cpu=,rss=", "-p", &list.join(",")])
+        .output()
+    {
+        Ok(output) => parse_ps(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// `ps -o pid=,%cpu=,rss=`: RSS is in KiB.
+fn parse_ps(output: &str) -> HashMap<u32, Reading> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [pid, cpu, rss] = fields[..] else {
+                return None;
+            };
+            Some((
+                pid.parse().ok()?,
+                Reading {
+                    cpu: Cpu::Percent(cpu.parse().ok()?),
+                    rss: rss.parse::<u64>().ok()? * 1024,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// One bar per value, from empty at 0 to full at `top`.
+pub fn sparkline(values: &[f64], top: f64) -> String {
+    values
+        .iter()
+        .map(|&v| {
+            let level = if top > 0.0 { v / top * 7.0 } else { 0.0 };
+            BARS[(level.round() as usize).min(7)]
+        })
+        .collect()
+}
+
+/// Compact binary size in at most five characters: `512K`, `9.5M`, `123M`.
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < 2 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    let unit = ["K", "M", "G"][unit];
+    if value < 10.0 && unit != "K" {
+        format!("{:.1}{}", value, unit)
+    } else {
+        format!("{:.0}{}", value, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticks(pid: u32, ticks: u64, rss: u64) -> (u32, Reading) {
+        (
+            pid,
+            Reading {
+                cpu: Cpu::Ticks(ticks),
+                rss,
+            },
+        )
+    }
+
+    fn system(total: u64) -> Option<SystemTicks> {
+        Some(SystemTicks { total, cpus: 4 })
+    }
+
+    #[test]
+    fn parse_stat_files() {
+        let stat = "2001 (node server) S 1 2001 2001 0 -1 4194560 9182 0 0 0 1250 310 0 0 \
+                    20 0 11 0 348812 1180430336 45213 18446744073709551615";
+        assert_eq!(parse_pid_stat(stat), Some(1560));
+        assert_eq!(parse_pid_stat("2001 (node"), None);
+        let status = "Name:\tnode\nVmPeak:\t 1210000 kB\nVmRSS:\t  180848 kB\nThreads:\t11\n";
+        assert_eq!(parse_status_rss(status), Some(180848 * 1024));
+        assert_eq!(parse_status_rss("Name:\tkthreadd\n"), None);
+        assert_eq!(
+            parse_system_stat(include_str!("../fixtures/usage/proc-stat.txt")),
+            Some(SystemTicks {
+                total: 184736 + 1120 + 52918 + 9384712 + 4211 + 3127,
+                cpus: 4
+            })
+        );
+    }
+
+    #[test]
+    fn parse_ps_output() {
+        let readings = parse_ps(include_str!("../fixtures/usage/ps.txt"));
+        assert_eq!(readings.len(), 3);
+        assert_eq!(
+            readings[&5120],
+            Reading {
+                cpu: Cpu::Percent(103.2),
+                rss: 1024 * 1024 * 1024
+            }
+        );
+    }
+
+    #[test]
+    fn cpu_from_tick_deltas() {
+        let mut tracker = Tracker::default();
+        tracker.record(
+            [ticks(1, 100, 4096), ticks(2, 50, 8192)].into(),
+            system(1000),
+        );
+        assert_eq!(tracker.get(1).unwrap().cpu, None);
+        // 400 ticks over 4 CPUs is 100 ticks of one
+        tracker.record(
+            [ticks(1, 150, 8192), ticks(2, 50, 8192)].into(),
+            system(1400),
+        );
+        let usage = tracker.get(1).unwrap();
+        assert_eq!(usage.cpu, Some(50.0));
+        assert_eq!(usage.rss_history, [4096, 8192]);
+        assert_eq!(tracker.get(2).unwrap().cpu, Some(0.0));
+        // gone processes are forgotten
+        tracker.record([ticks(2, 60, 8192)].into(), system(1800));
+        assert!(tracker.get(1).is_none());
+        assert_eq!(tracker.get(2).unwrap().cpu_history, [0.0, 10.0]);
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut tracker = Tracker::default();
+        for i in 0..HISTORY as u64 + 3 {
+            tracker.record([ticks(1, i * 10, i)].into(), system(i * 40));
+        }
+        let usage = tracker.get(1).unwrap();
+        assert_eq!(usage.cpu_history.len(), HISTORY);
+        assert_eq!(usage.rss_history.len(), HISTORY);
+        assert_eq!(usage.rss_history[0], 3);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            sparkline(&[0.0, 50.0, 100.0], 100.0),
+            "\u{2581}\u{2585}\u{2588}"
+        );
+        assert_eq!(sparkline(&[0.0, 0.0], 0.0), "\u{2581}\u{2581}");
+        for (bytes, shown) in [
+            (0, "0B"),
+            (512 * 1024, "512K"),
+            (9_961_472, "9.5M"),
+            (128_974_848, "123M"),
+            (3 << 30, "3.0G"),
+        ] {
+            assert_eq!(format_bytes(bytes), shown);
+        }
+        let usage = Usage {
+            cpu: Some(12.5),
+            rss: 48 << 20,
+            cpu_history: vec![0.0, 12.5],
+            rss_history: vec![24 << 20, 48 << 20],
+        };
+        assert_eq!(usage.cpu_label(), " 12.5 \u{2581}\u{2582}");
+        assert_eq!(usage.rss_label(), "  48M ▅█");
+        assert_eq!(Usage::default().cpu_label(), "    -");
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cli::WaitArgs;
+use crate::netns::NetnsScope;
+use crate::ports::{self, PortEntry, ScanOptions};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// State `portit wait` waits for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Until {
+    Open,
+    Free,
+}
+
+impl Until {
+    fn verb(self) -> &'static str {
+        match self {
+            Until::Open => "open",
+            Until::Free => "be free",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Reached,
+    TimedOut(Vec<u16>),
+}
+
+/// Exit codes: 0 once every port is in the wanted state, 1 on timeout.
+pub fn run(args: &WaitArgs) -> ExitCode {
+    let scan = ScanOptions {
+        netns: args.netns.clone(),
+        ..Default::default()
+    };
+    let mut out = io::stdout();
+    let outcome = wait_for(args, || ports::list_listening_ports(&scan), &mut out);
+    match outcome {
+        Outcome::Reached => ExitCode::SUCCESS,
+        Outcome::TimedOut(pending) => {
+            if !args.quiet {
+                let ports: Vec<String> = pending.iter().map(u16::to_string).collect();
+                eprintln!(
+                    "portit: timed out waiting for port {} to {}",
+                    ports.join(", "),
+                    args.until.verb()
+                );
+            }
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn wait_for(
+    args: &WaitArgs,
+    mut scan: impl FnMut() -> Vec<PortEntry>,
+    out: &mut impl Write,
+) -> Outcome {
+    let start = Instant::now();
+    let mut reported = HashSet::new();
+    // connecting only reaches listeners in portit's own namespace
+    let can_connect = args.netns.as_ref().is_none_or(NetnsScope::includes_own);
+    loop {
+        let entries = scan();
+        let mut pending = Vec::new();
+        for &port in &args.ports {
+            let owner = entries.iter().find(|e| e.port == port);
+            // lsof only lists our own processes unless we're root, so a
+            // listener it can't see still counts if it accepts connections
+            let open = owner.is_some() || (can_connect && accepts_connections(port));
+            if open != (args.until == Until::Open) {
+                pending.push(port);
+            } else if reported.insert(port) && !args.quiet {
+                let _ = writeln!(out, "{}", describe(port, args.until, owner));
+            }
+        }
+        if pending.is_empty() {
+            return Outcome::Reached;
+        }
+
+        let remaining = match args.timeout {
+            Some(timeout) => match timeout.checked_sub(start.elapsed()) {
+                Some(left) if !left.is_zero() => left,
+                _ => return Outcome::TimedOut(pending),
+            },
+            None => args.interval,
+        };
+        thread::sleep(args.interval.min(remaining));
+    }
+}
+
+fn describe(port: u16, until: Until, owner: Option<&PortEntry>) -> String {
+    match (until, owner) {
+        (Until::Free, _) => format!("{} free", port),
+        (Until::Open, Some(e)) => format!(
+            "{} open: {} (PID {}) on {}",
+            port,
+            e.process_name,
+            e.pid,
+            e.socket_addr()
+        ),
+        (Until::Open, None) => format!("{} open", port),
+    }
+}
+
+fn accepts_connections(port: u16) -> bool {
+    [
+        SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        SocketAddr::from((Ipv6Addr::LOCALHOST, port)),
+    ]
+    .iter()
+    .any(|addr| TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn args(ports: &[u16], until: Until, timeout_ms: u64) -> WaitArgs {
+        WaitArgs {
+            ports: ports.to_vec(),
+            until,
+            timeout: Some(Duration::from_millis(timeout_ms)),
+            interval: Duration::from_millis(10),
+            quiet: false,
+            netns: None,
+        }
+    }
+
+    fn entry(port: u16) -> PortEntry {
+        PortEntry {
+            pid: 42,
+            process_name: "postgres".to_string(),
+            port,
+            address: "127.0.0.1".parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    /// A port nothing listens on, so the connect fallback stays quiet.
+    fn closed_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[test]
+    fn waits_until_port_opens() {
+        let port = closed_port();
+        let mut scans = 0;
+        let mut out = Vec::new();
+        let outcome = wait_for(
+            &args(&[port], Until::Open, 2000),
+            || {
+                scans += 1;
+                if scans < 3 { vec![] } else { vec![entry(port)] }
+            },
+            &mut out,
+        );
+        assert_eq!(outcome, Outcome::Reached);
+        assert_eq!(scans, 3);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{} open: postgres (PID 42) on 127.0.0.1:{}\n", port, port)
+        );
+    }
+
+    #[test]
+    fn waits_until_port_is_free() {
+        let port = closed_port();
+        let mut scans = 0;
+        let mut out = Vec::new();
+        let outcome = wait_for(
+            &args(&[port], Until::Free, 2000),
+            || {
+                scans += 1;
+                if scans < 2 { vec![entry(port)] } else { vec![] }
+            },
+            &mut out,
+        );
+        assert_eq!(outcome, Outcome::Reached);
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{} free\n", port));
+    }
+
+    #[test]
+    fn times_out_with_pending_ports() {
+        let (open, closed) = (closed_port(), closed_port());
+        let mut out = Vec::new();
+        let outcome = wait_for(
+            &args(&[open, closed], Until::Open, 50),
+            || vec![entry(open)],
+            &mut out,
+        );
+        assert_eq!(outcome, Outcome::TimedOut(vec![closed]));
+        // the port that did open is reported once, not on every poll
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn unlisted_listener_counts_as_open() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut out = Vec::new();
+        let outcome = wait_for(&args(&[port], Until::Open, 500), Vec::new, &mut out);
+        assert_eq!(outcome, Outcome::Reached);
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{} open\n", port));
+    }
+
+    #[test]
+    fn other_namespace_ignores_own_listeners() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut out = Vec::new();
+        let mut blue = args(&[port], Until::Open, 50);
+        blue.netns = Some(NetnsScope::Named("blue".to_string()));
+        assert_eq!(
+            wait_for(&blue, Vec::new, &mut out),
+            Outcome::TimedOut(vec![port])
+        );
+        blue.until = Until::Free;
+        assert_eq!(wait_for(&blue, Vec::new, &mut out), Outcome::Reached);
+        blue.netns = Some(NetnsScope::Named("host".to_string()));
+        assert_eq!(
+            wait_for(&blue, Vec::new, &mut out),
+            Outcome::TimedOut(vec![port])
+        );
+    }
+}